@@ -0,0 +1,160 @@
+// スタイル計算のベンチマーク
+// 大きな合成ドキュメントとスタイルシートでstyle_treeにかかる時間を測る
+// cargo bench --bench style
+extern crate browser;
+
+use browser::dom::dom::{AttrMap, Node};
+use browser::dom::element_state::ElementState;
+use browser::layout::{layout_tree, Dimensions};
+use browser::style::{style_tree, style_tree_parallel, style_tree_with_statistics, StyleCache};
+use browser::style_sheet::css_parser::new_css_parser;
+use browser::style_sheet::interface::CSSParserTrait;
+use browser::style_sheet::media_query::MediaEnvironment;
+use browser::style_sheet::style_sheet::Stylesheet;
+use std::time::{Duration, Instant};
+
+const TAGS: [&str; 6] = ["div", "p", "span", "a", "li", "section"];
+
+// 各要素が10個の子を持つ木（深さ4で約11000要素）
+fn document(depth: usize, counter: &mut usize) -> Node {
+    *counter += 1;
+    let index = *counter;
+    let mut attributes = AttrMap::new();
+    attributes.insert(
+        "class".to_string(),
+        format!("c{} c{}", index % 500, index % 37),
+    );
+    if index.is_multiple_of(10) {
+        attributes.insert("id".to_string(), format!("e{}", index));
+    }
+    let children = if depth == 0 {
+        Vec::new()
+    } else {
+        (0..10).map(|_| document(depth - 1, counter)).collect()
+    };
+    Node::elem(TAGS[index % TAGS.len()].to_string(), attributes, children)
+}
+
+// 同じクラスのセルが並ぶ長い表（2000行×5列）
+fn table() -> Node {
+    let element = |tag: &str, class: &str, children| {
+        let mut attributes = AttrMap::new();
+        attributes.insert("class".to_string(), class.to_string());
+        Node::elem(tag.to_string(), attributes, children)
+    };
+    let rows = (0..2000)
+        .map(|row| {
+            let cells = (0..5)
+                .map(|column| {
+                    element(
+                        "td",
+                        if column == 0 { "cell key" } else { "cell" },
+                        Vec::new(),
+                    )
+                })
+                .collect();
+            element("tr", if row % 2 == 0 { "even" } else { "odd" }, cells)
+        })
+        .collect();
+    element("table", "data", rows)
+}
+
+// id、クラス、タグ、ユニバーサルのルールが混ざったスタイルシート
+fn stylesheet(rules: usize) -> Stylesheet {
+    let css: String = (0..rules)
+        .map(|index| {
+            let selector = match index % 5 {
+                0 => format!("#e{}", index * 2),
+                1 => format!(".c{}", index % 500),
+                2 => format!("{} > .c{}", TAGS[index % TAGS.len()], index % 37),
+                3 => format!(".c{} {}", index % 500, TAGS[index % TAGS.len()]),
+                // ユニバーサルなルールは少ない
+                _ if index % 100 == 4 => format!("*:not(.c{}):hover", index % 500),
+                _ => format!("{}.c{}:hover", TAGS[index % TAGS.len()], index % 500),
+            };
+            format!(
+                "{} {{ margin-left: {}px; color: #{:06x} }}\n",
+                selector,
+                index % 20,
+                index
+            )
+        })
+        .collect();
+    new_css_parser(css).parse()
+}
+
+fn bench<F: FnMut()>(name: &str, iterations: u32, mut f: F) {
+    let mut total = Duration::default();
+    for _ in 0..iterations {
+        let start = Instant::now();
+        f();
+        total += start.elapsed();
+    }
+    println!("{:<48} {:>10.2?} / iter", name, total / iterations);
+}
+
+fn main() {
+    let root = document(4, &mut 0);
+    for &rules in &[0, 500, 5000] {
+        let stylesheet = stylesheet(rules);
+        bench(
+            &format!("style_tree 11k elements, {} rules", rules),
+            5,
+            || {
+                style_tree(&root, &[&stylesheet], MediaEnvironment::default());
+            },
+        );
+    }
+
+    // 継承プロパティとカスタムプロパティをルートで指定し、子孫はすべて継承する
+    let inherited = new_css_parser(
+        ":root { --gap: 4px; --accent: red; font-family: serif; line-height: 1.5; \
+         letter-spacing: 1px; visibility: visible; cursor: default } \
+         div, p, section, li { display: block } .c7 { margin-left: var(--gap) }"
+            .to_string(),
+    )
+    .parse();
+    bench("style_tree 11k elements, inherited properties", 5, || {
+        style_tree(&root, &[&inherited], MediaEnvironment::default());
+    });
+    let styled = style_tree(&root, &[&inherited], MediaEnvironment::default());
+    let mut viewport = Dimensions::default();
+    viewport.content.width = 800.0;
+    bench("layout_tree 11k elements", 5, || {
+        layout_tree(&styled, viewport);
+    });
+
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let stylesheet = stylesheet(5000);
+    bench(
+        &format!("style_tree_parallel {} threads, 5000 rules", threads),
+        5,
+        || {
+            style_tree_parallel(&root, &[&stylesheet], MediaEnvironment::default(), threads);
+        },
+    );
+
+    // 葉の要素の:hoverを切り替えて差分だけ計算し直す
+    let mut root = root;
+    let mut cache = StyleCache::default();
+    cache.restyle(&mut root, &[&stylesheet], MediaEnvironment::default());
+    let mut hover = false;
+    bench("restyle after :hover on a leaf, 5000 rules", 5, || {
+        hover = !hover;
+        root.children[3].children[5].children[7].children[1].set_state(ElementState::HOVER, hover);
+        let (_, statistics) =
+            cache.restyle_with_statistics(&mut root, &[&stylesheet], MediaEnvironment::default());
+        assert!(statistics.restyled < 100);
+    });
+
+    let table = table();
+    bench("style_tree 2000x5 table, 5000 rules", 5, || {
+        style_tree(&table, &[&stylesheet], MediaEnvironment::default());
+    });
+    let (_, statistics) =
+        style_tree_with_statistics(&table, &[&stylesheet], MediaEnvironment::default());
+    println!(
+        "style sharing: {} hits, {} misses",
+        statistics.hits, statistics.misses
+    );
+}
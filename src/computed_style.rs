@@ -0,0 +1,685 @@
+// カスケードで求めた計算値を型付きで保持する
+// レイアウトと描画で使うプロパティはフィールドに、それ以外は名前をキーにしたマップに格納する
+// https://www.w3.org/TR/css-cascade-4/#computed
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use style_sheet::math::MathExpression;
+use style_sheet::property;
+use style_sheet::util::{Color, LengthContext, Unit, Value, DEFAULT_FONT_SIZE};
+use style_sheet::variable::is_custom_property;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Display {
+    Inline,
+    Block,
+    ListItem,
+    InlineBlock,
+    FlowRoot,
+    Flex,
+    InlineFlex,
+    Grid,
+    InlineGrid,
+    Table,
+    InlineTable,
+    TableRowGroup,
+    TableHeaderGroup,
+    TableFooterGroup,
+    TableRow,
+    TableCell,
+    TableColumnGroup,
+    TableColumn,
+    TableCaption,
+    Contents,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+    Fixed,
+    Sticky,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Float {
+    None,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorderStyle {
+    None,
+    Hidden,
+    Dotted,
+    Dashed,
+    Solid,
+    Double,
+    Groove,
+    Ridge,
+    Inset,
+    Outset,
+}
+
+/// A computed length: absolute px, or a percentage resolved during layout.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LengthPercentage {
+    Px(f32),
+    Percent(f32),
+    /// 長さと%が混在した数学関数
+    Calc(MathExpression),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LengthPercentageOrAuto {
+    Auto,
+    Length(LengthPercentage),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sides<T> {
+    pub top: T,
+    pub right: T,
+    pub bottom: T,
+    pub left: T,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedStyle {
+    pub display: Display,
+    pub position: Position,
+    pub float: Float,
+    pub width: LengthPercentageOrAuto,
+    pub height: LengthPercentageOrAuto,
+    pub margin: Sides<LengthPercentageOrAuto>,
+    pub padding: Sides<LengthPercentage>,
+    pub border_style: Sides<BorderStyle>,
+    /// border-styleがnoneまたはhiddenの辺は0になる
+    pub border_width: Sides<f32>,
+    pub border_color: Sides<Color>,
+    pub color: Color,
+    pub background_color: Color,
+    pub font_size: f32,
+    /// 型付きのフィールドを持たない継承プロパティとカスタムプロパティの計算値。
+    /// どれも指定されていない要素は親要素のマップをそのまま共有する
+    pub inherited: Arc<HashMap<String, Value>>,
+    /// 型付きのフィールドを持たない、継承されないプロパティの計算値
+    pub other: HashMap<String, Value>,
+}
+
+const BLACK: Color = Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 255,
+};
+
+const MEDIUM_BORDER_WIDTH: f32 = 3.0;
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+const TRANSPARENT: Color = Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 0,
+};
+
+impl Display {
+    pub fn from_value(value: &Value) -> Option<Display> {
+        match keyword(value)? {
+            "inline" => Some(Display::Inline),
+            "block" => Some(Display::Block),
+            "list-item" => Some(Display::ListItem),
+            "inline-block" => Some(Display::InlineBlock),
+            "flow-root" => Some(Display::FlowRoot),
+            "flex" => Some(Display::Flex),
+            "inline-flex" => Some(Display::InlineFlex),
+            "grid" => Some(Display::Grid),
+            "inline-grid" => Some(Display::InlineGrid),
+            "table" => Some(Display::Table),
+            "inline-table" => Some(Display::InlineTable),
+            "table-row-group" => Some(Display::TableRowGroup),
+            "table-header-group" => Some(Display::TableHeaderGroup),
+            "table-footer-group" => Some(Display::TableFooterGroup),
+            "table-row" => Some(Display::TableRow),
+            "table-cell" => Some(Display::TableCell),
+            "table-column-group" => Some(Display::TableColumnGroup),
+            "table-column" => Some(Display::TableColumn),
+            "table-caption" => Some(Display::TableCaption),
+            "contents" => Some(Display::Contents),
+            "none" => Some(Display::None),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Display::Inline => "inline",
+            Display::Block => "block",
+            Display::ListItem => "list-item",
+            Display::InlineBlock => "inline-block",
+            Display::FlowRoot => "flow-root",
+            Display::Flex => "flex",
+            Display::InlineFlex => "inline-flex",
+            Display::Grid => "grid",
+            Display::InlineGrid => "inline-grid",
+            Display::Table => "table",
+            Display::InlineTable => "inline-table",
+            Display::TableRowGroup => "table-row-group",
+            Display::TableHeaderGroup => "table-header-group",
+            Display::TableFooterGroup => "table-footer-group",
+            Display::TableRow => "table-row",
+            Display::TableCell => "table-cell",
+            Display::TableColumnGroup => "table-column-group",
+            Display::TableColumn => "table-column",
+            Display::TableCaption => "table-caption",
+            Display::Contents => "contents",
+            Display::None => "none",
+        }
+    }
+
+    /// Whether the box takes part in an inline formatting context,
+    /// like `inline`, `inline-block` and the other `inline-*` values.
+    pub fn is_inline_level(self) -> bool {
+        matches!(
+            self,
+            Display::Inline
+                | Display::InlineBlock
+                | Display::InlineFlex
+                | Display::InlineGrid
+                | Display::InlineTable
+        )
+    }
+}
+
+impl Position {
+    pub fn from_value(value: &Value) -> Option<Position> {
+        match keyword(value)? {
+            "static" => Some(Position::Static),
+            "relative" => Some(Position::Relative),
+            "absolute" => Some(Position::Absolute),
+            "fixed" => Some(Position::Fixed),
+            "sticky" => Some(Position::Sticky),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Position::Static => "static",
+            Position::Relative => "relative",
+            Position::Absolute => "absolute",
+            Position::Fixed => "fixed",
+            Position::Sticky => "sticky",
+        }
+    }
+}
+
+impl Float {
+    pub fn from_value(value: &Value) -> Option<Float> {
+        match keyword(value)? {
+            "none" => Some(Float::None),
+            "left" => Some(Float::Left),
+            "right" => Some(Float::Right),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Float::None => "none",
+            Float::Left => "left",
+            Float::Right => "right",
+        }
+    }
+}
+
+impl BorderStyle {
+    pub fn from_value(value: &Value) -> Option<BorderStyle> {
+        match keyword(value)? {
+            "none" => Some(BorderStyle::None),
+            "hidden" => Some(BorderStyle::Hidden),
+            "dotted" => Some(BorderStyle::Dotted),
+            "dashed" => Some(BorderStyle::Dashed),
+            "solid" => Some(BorderStyle::Solid),
+            "double" => Some(BorderStyle::Double),
+            "groove" => Some(BorderStyle::Groove),
+            "ridge" => Some(BorderStyle::Ridge),
+            "inset" => Some(BorderStyle::Inset),
+            "outset" => Some(BorderStyle::Outset),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BorderStyle::None => "none",
+            BorderStyle::Hidden => "hidden",
+            BorderStyle::Dotted => "dotted",
+            BorderStyle::Dashed => "dashed",
+            BorderStyle::Solid => "solid",
+            BorderStyle::Double => "double",
+            BorderStyle::Groove => "groove",
+            BorderStyle::Ridge => "ridge",
+            BorderStyle::Inset => "inset",
+            BorderStyle::Outset => "outset",
+        }
+    }
+}
+
+impl LengthPercentage {
+    fn from_value(value: &Value) -> Option<LengthPercentage> {
+        match *value {
+            Value::Length(length, Unit::Px) => Some(LengthPercentage::Px(length)),
+            Value::Length(percent, Unit::Percent) => Some(LengthPercentage::Percent(percent)),
+            // 単位のない0は長さとして扱う
+            Value::Number(0.0) => Some(LengthPercentage::Px(0.0)),
+            Value::Math(ref expression) => Some(LengthPercentage::Calc(expression.clone())),
+            _ => None,
+        }
+    }
+
+    /// Resolves percentages against `base`, e.g. the containing block width.
+    pub fn resolve(&self, base: f32) -> f32 {
+        match *self {
+            LengthPercentage::Px(length) => length,
+            LengthPercentage::Percent(percent) => base * percent / 100.0,
+            LengthPercentage::Calc(ref expression) => expression.resolve_px(&LengthContext {
+                percentage_base: base,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Whether the length depends on the base that percentages resolve against.
+    pub fn has_percentage(&self) -> bool {
+        match *self {
+            LengthPercentage::Px(_) => false,
+            LengthPercentage::Percent(_) => true,
+            LengthPercentage::Calc(ref expression) => expression.has_percentage(),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match *self {
+            LengthPercentage::Px(length) => Value::Length(length, Unit::Px),
+            LengthPercentage::Percent(percent) => Value::Length(percent, Unit::Percent),
+            LengthPercentage::Calc(ref expression) => Value::Math(expression.clone()),
+        }
+    }
+}
+
+impl LengthPercentageOrAuto {
+    fn from_value(value: &Value) -> Option<LengthPercentageOrAuto> {
+        match keyword(value) {
+            Some("auto") => Some(LengthPercentageOrAuto::Auto),
+            _ => LengthPercentage::from_value(value).map(LengthPercentageOrAuto::Length),
+        }
+    }
+
+    /// Resolves against `base`; `auto` resolves to 0.
+    pub fn resolve(&self, base: f32) -> f32 {
+        match *self {
+            LengthPercentageOrAuto::Auto => 0.0,
+            LengthPercentageOrAuto::Length(ref length) => length.resolve(base),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match *self {
+            LengthPercentageOrAuto::Auto => Value::Keyword("auto".to_string()),
+            LengthPercentageOrAuto::Length(ref length) => length.to_value(),
+        }
+    }
+}
+
+impl<T: Clone> Sides<T> {
+    fn all(value: T) -> Sides<T> {
+        Sides {
+            top: value.clone(),
+            right: value.clone(),
+            bottom: value.clone(),
+            left: value,
+        }
+    }
+
+    fn get(&self, side: &str) -> Option<T> {
+        match side {
+            "top" => Some(self.top.clone()),
+            "right" => Some(self.right.clone()),
+            "bottom" => Some(self.bottom.clone()),
+            "left" => Some(self.left.clone()),
+            _ => None,
+        }
+    }
+
+    fn get_mut(&mut self, side: &str) -> Option<&mut T> {
+        match side {
+            "top" => Some(&mut self.top),
+            "right" => Some(&mut self.right),
+            "bottom" => Some(&mut self.bottom),
+            "left" => Some(&mut self.left),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ComputedStyle {
+    fn default() -> ComputedStyle {
+        ComputedStyle {
+            display: Display::Inline,
+            position: Position::Static,
+            float: Float::None,
+            width: LengthPercentageOrAuto::Auto,
+            height: LengthPercentageOrAuto::Auto,
+            margin: Sides::all(LengthPercentageOrAuto::Length(LengthPercentage::Px(0.0))),
+            padding: Sides::all(LengthPercentage::Px(0.0)),
+            border_style: Sides::all(BorderStyle::None),
+            border_width: Sides::all(0.0),
+            border_color: Sides::all(BLACK),
+            color: BLACK,
+            background_color: TRANSPARENT,
+            font_size: DEFAULT_FONT_SIZE,
+            inherited: initial_inherited_values(),
+            other: HashMap::new(),
+        }
+    }
+}
+
+impl ComputedStyle {
+    /*
+     カスケード後の計算値のマップから作成する
+     型付きのフィールドに変換できない値は初期値になる
+     型付きでない継承プロパティは親要素（ルート要素では初期値）のマップを引き継ぎ、
+     値が変わるときだけ複製する
+    */
+    pub fn from_properties(
+        properties: HashMap<String, Value>,
+        parent: Option<&ComputedStyle>,
+    ) -> ComputedStyle {
+        let mut style = ComputedStyle::default();
+        if let Some(parent) = parent {
+            style.inherited = parent.inherited.clone();
+        }
+        // border-*-colorの初期値はcurrentcolor、border-*-widthの初期値はmedium
+        let current_color = properties.get("color").and_then(color).unwrap_or(BLACK);
+        style.border_color = Sides::all(current_color);
+        style.border_width = Sides::all(MEDIUM_BORDER_WIDTH);
+        for (name, value) in properties {
+            if style.set(&name, &value) {
+                continue;
+            }
+            if !is_inherited(&name) {
+                style.other.insert(name, value);
+            } else if style.inherited.get(&name) != Some(&value) {
+                Arc::make_mut(&mut style.inherited).insert(name, value);
+            }
+        }
+        for side in SIDES.iter() {
+            if let Some(BorderStyle::None | BorderStyle::Hidden) = style.border_style.get(side) {
+                *style.border_width.get_mut(side).unwrap() = 0.0;
+            }
+        }
+        style
+    }
+
+    /// Whether the property is stored in a typed field rather than in a map.
+    pub fn has_field(name: &str) -> bool {
+        match name {
+            "display" | "position" | "float" | "width" | "height" | "color"
+            | "background-color" | "font-size" => true,
+            _ => {
+                let mut parts = name.splitn(3, '-');
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some("margin" | "padding"), Some(side), None)
+                    | (Some("border"), Some(side), Some("style" | "width" | "color")) => {
+                        SIDES.contains(&side)
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    /// Drops an inherited value, e.g. a custom property that became invalid on this element.
+    pub fn remove_inherited(&mut self, name: &str) {
+        if self.inherited.contains_key(name) {
+            Arc::make_mut(&mut self.inherited).remove(name);
+        }
+    }
+
+    /// The computed value of any property as a `Value`, for inheritance and generic access.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        let value = match name {
+            // 型付きで表せなかったdisplayの値はマップに残っている
+            "display" => match self.other.get(name) {
+                Some(value) => value.clone(),
+                None => Value::Keyword(self.display.as_str().to_string()),
+            },
+            "position" => Value::Keyword(self.position.as_str().to_string()),
+            "float" => Value::Keyword(self.float.as_str().to_string()),
+            "width" => self.width.to_value(),
+            "height" => self.height.to_value(),
+            "color" => Value::ColorValue(self.color),
+            "background-color" => Value::ColorValue(self.background_color),
+            "font-size" => Value::Length(self.font_size, Unit::Px),
+            _ => {
+                return self
+                    .side_value(name)
+                    .or_else(|| self.other.get(name).cloned())
+                    .or_else(|| self.inherited.get(name).cloned())
+            }
+        };
+        Some(value)
+    }
+
+    fn side_value(&self, name: &str) -> Option<Value> {
+        let mut parts = name.splitn(3, '-');
+        match (parts.next()?, parts.next()?, parts.next()) {
+            ("margin", side, None) => self.margin.get(side).map(|margin| margin.to_value()),
+            ("padding", side, None) => self.padding.get(side).map(|padding| padding.to_value()),
+            ("border", side, Some("style")) => self
+                .border_style
+                .get(side)
+                .map(|style| Value::Keyword(style.as_str().to_string())),
+            ("border", side, Some("width")) => self
+                .border_width
+                .get(side)
+                .map(|width| Value::Length(width, Unit::Px)),
+            ("border", side, Some("color")) => self.border_color.get(side).map(Value::ColorValue),
+            _ => None,
+        }
+    }
+
+    // 型付きのフィールドに値を設定する。対応するフィールドがなければfalseを返す
+    fn set(&mut self, name: &str, value: &Value) -> bool {
+        match name {
+            // 解釈できないdisplayの値は作者の値のまま汎用のマップに残す
+            "display" => match Display::from_value(value) {
+                Some(display) => self.display = display,
+                None => return false,
+            },
+            "position" => self.position = Position::from_value(value).unwrap_or(Position::Static),
+            "float" => self.float = Float::from_value(value).unwrap_or(Float::None),
+            "width" => {
+                self.width = LengthPercentageOrAuto::from_value(value)
+                    .unwrap_or(LengthPercentageOrAuto::Auto)
+            }
+            "height" => {
+                self.height = LengthPercentageOrAuto::from_value(value)
+                    .unwrap_or(LengthPercentageOrAuto::Auto)
+            }
+            "color" => self.color = color(value).unwrap_or(BLACK),
+            "background-color" => self.background_color = color(value).unwrap_or(TRANSPARENT),
+            "font-size" => {
+                if let Value::Length(size, Unit::Px) = *value {
+                    self.font_size = size;
+                }
+            }
+            _ => return self.set_side(name, value),
+        }
+        true
+    }
+
+    // margin-top, border-left-width などの辺ごとのプロパティ
+    fn set_side(&mut self, name: &str, value: &Value) -> bool {
+        let mut parts = name.splitn(3, '-');
+        let (property, side, suffix) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(property), Some(side), suffix) if SIDES.contains(&side) => {
+                (property, side, suffix)
+            }
+            _ => return false,
+        };
+        match (property, suffix) {
+            ("margin", None) => {
+                *self.margin.get_mut(side).unwrap() = LengthPercentageOrAuto::from_value(value)
+                    .unwrap_or(LengthPercentageOrAuto::Length(LengthPercentage::Px(0.0)))
+            }
+            ("padding", None) => {
+                *self.padding.get_mut(side).unwrap() =
+                    LengthPercentage::from_value(value).unwrap_or(LengthPercentage::Px(0.0))
+            }
+            ("border", Some("style")) => {
+                *self.border_style.get_mut(side).unwrap() =
+                    BorderStyle::from_value(value).unwrap_or(BorderStyle::None)
+            }
+            ("border", Some("width")) => {
+                *self.border_width.get_mut(side).unwrap() = border_width(value)
+            }
+            ("border", Some("color")) => {
+                if let Some(color) = color(value) {
+                    *self.border_color.get_mut(side).unwrap() = color;
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+fn is_inherited(name: &str) -> bool {
+    is_custom_property(name)
+        || property::lookup(name).is_some_and(|definition| definition.inherited)
+}
+
+// ルート要素が継承する、型付きのフィールドを持たない継承プロパティの初期値
+fn initial_inherited_values() -> Arc<HashMap<String, Value>> {
+    static INITIAL: OnceLock<Arc<HashMap<String, Value>>> = OnceLock::new();
+    INITIAL
+        .get_or_init(|| {
+            Arc::new(
+                property::inherited_properties()
+                    .filter(|definition| !ComputedStyle::has_field(definition.name))
+                    .map(|definition| (definition.name.to_string(), definition.initial.clone()))
+                    .collect(),
+            )
+        })
+        .clone()
+}
+
+fn keyword(value: &Value) -> Option<&str> {
+    match *value {
+        Value::Keyword(ref keyword) => Some(keyword),
+        _ => None,
+    }
+}
+
+fn color(value: &Value) -> Option<Color> {
+    match *value {
+        Value::ColorValue(color) => Some(color),
+        _ => None,
+    }
+}
+
+// https://www.w3.org/TR/css-backgrounds-3/#border-width
+fn border_width(value: &Value) -> f32 {
+    match *value {
+        Value::Length(width, Unit::Px) => width,
+        Value::Keyword(ref keyword) if keyword == "thin" => 1.0,
+        Value::Keyword(ref keyword) if keyword == "thick" => 5.0,
+        _ => MEDIUM_BORDER_WIDTH,
+    }
+}
+
+#[test]
+fn test_computed_style_from_properties() {
+    let properties = vec![
+        ("display", Value::Keyword("list-item".to_string())),
+        ("width", Value::Length(50.0, Unit::Percent)),
+        ("margin-left", Value::Keyword("auto".to_string())),
+        ("margin-top", Value::Number(0.0)),
+        ("border-top-style", Value::Keyword("solid".to_string())),
+        ("border-left-width", Value::Length(4.0, Unit::Px)),
+        (
+            "color",
+            Value::ColorValue(Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            }),
+        ),
+        ("cursor", Value::Keyword("pointer".to_string())),
+    ];
+    let style = ComputedStyle::from_properties(
+        properties
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+        None,
+    );
+
+    assert_eq!(style.display, Display::ListItem);
+    assert_eq!(
+        style.get("display"),
+        Some(Value::Keyword("list-item".to_string()))
+    );
+    assert_eq!(style.width.resolve(300.0), 150.0);
+    assert_eq!(style.margin.left, LengthPercentageOrAuto::Auto);
+    assert_eq!(style.margin.top.resolve(300.0), 0.0);
+    // border-styleがnoneの辺の幅は0、指定がなければmedium
+    assert_eq!(style.border_width.left, 0.0);
+    assert_eq!(style.border_width.top, MEDIUM_BORDER_WIDTH);
+    assert_eq!(style.border_color.top, style.color);
+    assert_eq!(
+        style.get("cursor"),
+        Some(Value::Keyword("pointer".to_string()))
+    );
+    assert_eq!(style.get("width"), Some(Value::Length(50.0, Unit::Percent)));
+}
+
+#[test]
+fn test_inherited_values_are_shared() {
+    let properties = |properties: &[(&str, &str)]| {
+        properties
+            .iter()
+            .map(|&(name, value)| (name.to_string(), Value::Keyword(value.to_string())))
+            .collect()
+    };
+    let root = ComputedStyle::from_properties(properties(&[("cursor", "pointer")]), None);
+    // 型付きでない継承プロパティを指定しない子孫は、親要素のマップを複製せずに共有する
+    let child = ComputedStyle::from_properties(properties(&[("display", "block")]), Some(&root));
+    assert!(Arc::ptr_eq(&child.inherited, &root.inherited));
+    assert_eq!(
+        child.get("cursor"),
+        Some(Value::Keyword("pointer".to_string()))
+    );
+    assert_eq!(
+        child.get("visibility"),
+        Some(Value::Keyword("visible".to_string()))
+    );
+    assert!(!child.other.contains_key("cursor"));
+    assert!(!child.inherited.contains_key("color"));
+
+    let text = ComputedStyle::from_properties(properties(&[("cursor", "text")]), Some(&child));
+    assert!(!Arc::ptr_eq(&text.inherited, &child.inherited));
+    assert_eq!(text.get("cursor"), Some(Value::Keyword("text".to_string())));
+    assert_eq!(
+        child.get("cursor"),
+        Some(Value::Keyword("pointer".to_string()))
+    );
+}
@@ -1,19 +1,45 @@
 //DOM Data Structure
 
+use dom::element_state::ElementState;
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 pub type AttrMap = HashMap<String, String>;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Node {
     pub children: Vec<Node>,
     pub node_type: NodeType,
+    /// Changes made through the methods of `Node` since the node was last
+    /// restyled by `style::StyleCache`. Direct changes to the fields are not recorded.
+    pub dirty: Dirty,
+}
+
+/// What changed on a node since it was last styled.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Dirty {
+    /// The element as it was before its first attribute or state change.
+    pub snapshot: Option<Box<ElementData>>,
+    /// Children were inserted or removed.
+    pub children: bool,
+}
+
+impl Dirty {
+    pub fn is_clean(&self) -> bool {
+        self.snapshot.is_none() && !self.children
+    }
+}
+
+// 変更の記録は内容ではないので比較しない
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        self.node_type == other.node_type && self.children == other.children
+    }
 }
 
 impl fmt::Display for Node {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self)
+        write!(f, "{:?}", self)
     }
 }
 
@@ -22,18 +48,95 @@ impl Node {
         Node {
             children: vec![],
             node_type: NodeType::Text(data),
+            dirty: Dirty::default(),
         }
     }
 
     pub fn elem(name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
         Node {
-            children: children,
+            children,
             node_type: NodeType::Element(ElementData {
                 tag_name: name,
                 attributes: attrs,
+                state: ElementState::empty(),
             }),
+            dirty: Dirty::default(),
+        }
+    }
+
+    /// Sets or clears dynamic state flags (e.g. `ElementState::HOVER`) on an element node.
+    /// Text nodes have no state and are left untouched.
+    pub fn set_state(&mut self, state: ElementState, value: bool) {
+        let mut new_state = match self.node_type {
+            NodeType::Element(ref elem) => elem.state,
+            NodeType::Text(_) => return,
+        };
+        new_state.set(state, value);
+        if let Some(elem) = self.element_to_change(|elem| elem.state != new_state) {
+            elem.state = new_state;
+        }
+    }
+
+    /// Sets an attribute of an element node. Text nodes are left untouched.
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        let changed = |elem: &ElementData| elem.attributes.get(name).is_none_or(|v| v != value);
+        if let Some(elem) = self.element_to_change(changed) {
+            elem.attributes.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) {
+        if let Some(elem) = self.element_to_change(|elem| elem.attributes.contains_key(name)) {
+            elem.attributes.remove(name);
         }
     }
+
+    pub fn append_child(&mut self, child: Node) {
+        self.children.push(child);
+        self.dirty.children = true;
+    }
+
+    /// Inserts `child` before the child at `index`. Panics if `index` is out of bounds.
+    pub fn insert_child(&mut self, index: usize, child: Node) {
+        self.children.insert(index, child);
+        self.dirty.children = true;
+    }
+
+    /// Removes and returns the child at `index`. Panics if `index` is out of bounds.
+    pub fn remove_child(&mut self, index: usize) -> Node {
+        self.dirty.children = true;
+        self.children.remove(index)
+    }
+
+    // 変更がある場合だけ、変更前の要素を記録してから要素を返す
+    fn element_to_change(
+        &mut self,
+        changed: impl Fn(&ElementData) -> bool,
+    ) -> Option<&mut ElementData> {
+        match self.node_type {
+            NodeType::Element(ref mut elem) if changed(elem) => {
+                if self.dirty.snapshot.is_none() {
+                    self.dirty.snapshot = Some(Box::new(elem.clone()));
+                }
+                Some(elem)
+            }
+            _ => None,
+        }
+    }
+
+    /// Depth-first search for the element whose `id` attribute equals `id`.
+    pub fn find_by_id_mut(&mut self, id: &str) -> Option<&mut Node> {
+        let found = match self.node_type {
+            NodeType::Element(ref elem) => elem.id().is_some_and(|v| v == id),
+            NodeType::Text(_) => false,
+        };
+        if found {
+            return Some(self);
+        }
+        self.children
+            .iter_mut()
+            .find_map(|child| child.find_by_id_mut(id))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -46,6 +149,7 @@ pub enum NodeType {
 pub struct ElementData {
     pub tag_name: String,
     pub attributes: AttrMap,
+    pub state: ElementState,
 }
 
 impl ElementData {
@@ -61,12 +165,42 @@ impl ElementData {
     }
 }
 
+#[test]
+fn test_dirty() {
+    let mut node = Node::elem(
+        "p".to_string(),
+        AttrMap::new(),
+        vec![Node::text("a".to_string())],
+    );
+    // 値が変わらない変更は記録しない
+    node.set_state(ElementState::HOVER, false);
+    node.remove_attribute("class");
+    assert!(node.dirty.is_clean());
+
+    node.set_attribute("class", "a");
+    node.set_state(ElementState::HOVER, true);
+    node.set_attribute("class", "b");
+    let snapshot = node.dirty.snapshot.as_ref().unwrap();
+    assert!(snapshot.classes().is_empty());
+    assert!(snapshot.state.is_empty());
+    assert!(!node.dirty.children);
+
+    node.remove_child(0);
+    assert!(node.dirty.children);
+
+    // 同じ内容のノードは変更の記録によらず等しい
+    let mut clean = node.clone();
+    clean.dirty = Dirty::default();
+    assert_eq!(node, clean);
+}
+
 #[test]
 fn test_id() {
     assert_eq!(
         ElementData {
             tag_name: "".to_string(),
             attributes: HashMap::new(),
+            state: ElementState::empty(),
         }
         .id(),
         None
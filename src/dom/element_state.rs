@@ -0,0 +1,66 @@
+// 要素の動的な状態（:hover, :focus など）を表すビットセット
+// ブラウザでは入力イベントで更新されるが、ここでは呼び出し側が明示的に設定する
+
+use std::ops::{BitOr, BitOrAssign};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ElementState(u16);
+
+impl ElementState {
+    pub const HOVER: ElementState = ElementState(1 << 0);
+    pub const ACTIVE: ElementState = ElementState(1 << 1);
+    pub const FOCUS: ElementState = ElementState(1 << 2);
+    pub const FOCUS_WITHIN: ElementState = ElementState(1 << 3);
+    pub const CHECKED: ElementState = ElementState(1 << 4);
+    pub const DISABLED: ElementState = ElementState(1 << 5);
+    pub const VISITED: ElementState = ElementState(1 << 6);
+    pub const TARGET: ElementState = ElementState(1 << 7);
+
+    pub fn empty() -> ElementState {
+        ElementState(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if every flag in `other` is set.
+    pub fn contains(&self, other: ElementState) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if any flag in `other` is set.
+    pub fn intersects(&self, other: ElementState) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn insert(&mut self, other: ElementState) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: ElementState) {
+        self.0 &= !other.0;
+    }
+
+    pub fn set(&mut self, other: ElementState, value: bool) {
+        if value {
+            self.insert(other);
+        } else {
+            self.remove(other);
+        }
+    }
+}
+
+impl BitOr for ElementState {
+    type Output = ElementState;
+
+    fn bitor(self, other: ElementState) -> ElementState {
+        ElementState(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for ElementState {
+    fn bitor_assign(&mut self, other: ElementState) {
+        self.insert(other);
+    }
+}
@@ -1 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod dom;
+pub mod element_state;
@@ -0,0 +1,327 @@
+// ::before / ::after の content プロパティを文字列に解決する
+// カウンターと引用符の深さは文書順に決まるため、カスケードの後に木全体を一度だけ走査する
+// https://www.w3.org/TR/css-content-3/
+// https://www.w3.org/TR/css-lists-3/#auto-numbering
+
+use dom::dom::NodeType;
+use std::collections::HashMap;
+use style::StyledNode;
+use style_sheet::util::Value;
+
+const DEFAULT_QUOTES: [&str; 4] = ["\u{201C}", "\u{201D}", "\u{2018}", "\u{2019}"];
+
+#[derive(Default)]
+struct ContentState {
+    // カウンター名 => ネストしたカウンターの値（末尾が最も内側）
+    counters: HashMap<String, Vec<i32>>,
+    quote_depth: usize,
+}
+
+pub fn resolve_generated_content(root: &mut StyledNode) {
+    let mut state = ContentState::default();
+    let mut created = Vec::new();
+    resolve_node(root, &mut state, &mut created);
+    state.pop_counters(&created);
+}
+
+/*
+ created: 同じ階層の兄弟要素が作成したカウンター名
+ カウンターのスコープは作成した要素の子孫と後続の兄弟要素なので、親の走査が終わるときに破棄する
+*/
+fn resolve_node(node: &mut StyledNode, state: &mut ContentState, created: &mut Vec<String>) {
+    if let NodeType::Text(_) = node.node.node_type {
+        return;
+    }
+    state.apply_counter_properties(node, created);
+
+    let mut child_created = Vec::new();
+    if let Some(ref mut before) = node.before {
+        resolve_pseudo_element(before, state, &mut child_created);
+    }
+    for child in &mut node.children {
+        resolve_node(child, state, &mut child_created);
+    }
+    if let Some(ref mut after) = node.after {
+        resolve_pseudo_element(after, state, &mut child_created);
+    }
+    state.pop_counters(&child_created);
+}
+
+fn resolve_pseudo_element(
+    pseudo: &mut StyledNode,
+    state: &mut ContentState,
+    created: &mut Vec<String>,
+) {
+    state.apply_counter_properties(pseudo, created);
+    let quotes = quotes(pseudo);
+    let text = match pseudo.style.other.get("content") {
+        Some(Value::List(items)) => items
+            .iter()
+            .map(|item| state.resolve_item(pseudo, item, &quotes))
+            .collect(),
+        Some(item) => state.resolve_item(pseudo, item, &quotes),
+        None => String::new(),
+    };
+    pseudo.generated_content = Some(text);
+}
+
+impl ContentState {
+    // counter-reset, counter-increment, counter-set の順に適用する
+    fn apply_counter_properties(&mut self, node: &StyledNode, created: &mut Vec<String>) {
+        for (name, value) in counter_list(node.style.other.get("counter-reset"), 0) {
+            if created.contains(&name) {
+                // 兄弟要素が作成したカウンターを置き換える
+                *self.counters.get_mut(&name).unwrap().last_mut().unwrap() = value;
+            } else {
+                self.counters.entry(name.clone()).or_default().push(value);
+                created.push(name);
+            }
+        }
+        for (name, value) in counter_list(node.style.other.get("counter-increment"), 1) {
+            // 値の範囲を超える増分は溢れさせずに上限・下限で止める
+            let counter = self.instantiate(name, created);
+            *counter = counter.saturating_add(value);
+        }
+        for (name, value) in counter_list(node.style.other.get("counter-set"), 0) {
+            *self.instantiate(name, created) = value;
+        }
+    }
+
+    // 存在しないカウンターを参照した場合は、その要素で0から作成する
+    fn instantiate(&mut self, name: String, created: &mut Vec<String>) -> &mut i32 {
+        let instances = self.counters.entry(name.clone()).or_default();
+        if instances.is_empty() {
+            instances.push(0);
+            created.push(name);
+        }
+        instances.last_mut().unwrap()
+    }
+
+    fn pop_counters(&mut self, created: &[String]) {
+        for name in created {
+            if let Some(instances) = self.counters.get_mut(name) {
+                instances.pop();
+            }
+        }
+    }
+
+    fn resolve_item(&mut self, pseudo: &StyledNode, item: &Value, quotes: &[String]) -> String {
+        match *item {
+            Value::Str(ref text) => text.clone(),
+            Value::Function(ref name, ref args) => match (&**name, &args[..]) {
+                ("attr", [Value::Keyword(attribute), ..]) => match pseudo.node.node_type {
+                    NodeType::Element(ref elem) => {
+                        elem.attributes.get(attribute).cloned().unwrap_or_default()
+                    }
+                    NodeType::Text(_) => String::new(),
+                },
+                ("counter", [Value::Keyword(counter), rest @ ..]) => {
+                    let value = self.current_counter(counter);
+                    format_counter(value, list_style(rest.first()))
+                }
+                ("counters", [Value::Keyword(counter), Value::Str(separator), rest @ ..]) => {
+                    let style = list_style(rest.first());
+                    match self.counters.get(counter) {
+                        Some(instances) if !instances.is_empty() => instances
+                            .iter()
+                            .map(|&value| format_counter(value, style))
+                            .collect::<Vec<_>>()
+                            .join(separator),
+                        _ => format_counter(0, style),
+                    }
+                }
+                _ => String::new(),
+            },
+            Value::Keyword(ref keyword) => match &**keyword {
+                "open-quote" => {
+                    let text = quote(quotes, self.quote_depth, 0);
+                    self.quote_depth += 1;
+                    text
+                }
+                "close-quote" if self.quote_depth > 0 => {
+                    self.quote_depth -= 1;
+                    quote(quotes, self.quote_depth, 1)
+                }
+                "no-open-quote" => {
+                    self.quote_depth += 1;
+                    String::new()
+                }
+                "no-close-quote" => {
+                    self.quote_depth = self.quote_depth.saturating_sub(1);
+                    String::new()
+                }
+                _ => String::new(),
+            },
+            _ => String::new(),
+        }
+    }
+
+    fn current_counter(&self, name: &str) -> i32 {
+        self.counters
+            .get(name)
+            .and_then(|instances| instances.last())
+            .cloned()
+            .unwrap_or(0)
+    }
+}
+
+// `counter-reset: a 2 b` => [("a", 2), ("b", default)]
+fn counter_list(value: Option<&Value>, default: i32) -> Vec<(String, i32)> {
+    let items = match value {
+        Some(Value::List(items)) => items.clone(),
+        Some(value) => vec![value.clone()],
+        None => return Vec::new(),
+    };
+    let mut counters: Vec<(String, i32)> = Vec::new();
+    for item in items {
+        match item {
+            Value::Keyword(ref name) if name == "none" => {}
+            Value::Keyword(name) => counters.push((name, default)),
+            Value::Number(n) => {
+                if let Some(last) = counters.last_mut() {
+                    last.1 = n as i32;
+                }
+            }
+            _ => {}
+        }
+    }
+    counters
+}
+
+fn quotes(pseudo: &StyledNode) -> Vec<String> {
+    match pseudo.style.inherited.get("quotes") {
+        Some(Value::List(items)) => items
+            .iter()
+            .filter_map(|item| match *item {
+                Value::Str(ref text) => Some(text.clone()),
+                _ => None,
+            })
+            .collect(),
+        Some(Value::Keyword(keyword)) if keyword == "none" => Vec::new(),
+        _ => DEFAULT_QUOTES.iter().map(|q| q.to_string()).collect(),
+    }
+}
+
+// 深さがquotesのペア数を超えた場合は最後のペアを使う
+fn quote(quotes: &[String], depth: usize, side: usize) -> String {
+    let pairs = quotes.len() / 2;
+    if pairs == 0 {
+        return String::new();
+    }
+    quotes[depth.min(pairs - 1) * 2 + side].clone()
+}
+
+fn list_style(value: Option<&Value>) -> &str {
+    match value {
+        Some(Value::Keyword(style)) => style,
+        _ => "decimal",
+    }
+}
+
+pub fn format_counter(value: i32, style: &str) -> String {
+    match style {
+        "none" => String::new(),
+        "disc" => "\u{2022}".to_string(),
+        "circle" => "\u{25E6}".to_string(),
+        "square" => "\u{25AA}".to_string(),
+        "decimal-leading-zero" if (0..10).contains(&value) => format!("0{}", value),
+        "lower-alpha" | "lower-latin" if value > 0 => alphabetic(value),
+        "upper-alpha" | "upper-latin" if value > 0 => alphabetic(value).to_uppercase(),
+        "lower-roman" if value > 0 && value < 4000 => roman(value),
+        "upper-roman" if value > 0 && value < 4000 => roman(value).to_uppercase(),
+        _ => value.to_string(),
+    }
+}
+
+// 1 => a, 26 => z, 27 => aa
+fn alphabetic(mut value: i32) -> String {
+    let mut letters = Vec::new();
+    while value > 0 {
+        value -= 1;
+        letters.push((b'a' + (value % 26) as u8) as char);
+        value /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+fn roman(mut value: i32) -> String {
+    const NUMERALS: [(i32, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut result = String::new();
+    for &(number, numeral) in NUMERALS.iter() {
+        while value >= number {
+            result.push_str(numeral);
+            value -= number;
+        }
+    }
+    result
+}
+
+#[test]
+fn test_generated_content() {
+    use html::html_parser::new_html_parser;
+    use html::interface::HTMLParserTrait;
+    use style::style_tree;
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+    use style_sheet::media_query::MediaEnvironment;
+
+    let html = "<ol><li title=\"a\"></li><li title=\"b\"><ol><li></li></ol></li></ol>";
+    let css = "ol { counter-reset: item } \
+               li::before { counter-increment: item; content: counters(item, \".\") \" \" attr(title) } \
+               li::after { content: open-quote \"\\201C\" close-quote; quotes: \"<\" \">\" }";
+    let root = new_html_parser(html.to_string()).parse();
+    let stylesheet = new_css_parser(css.to_string()).parse();
+    let styled = style_tree(&root, &[&stylesheet], MediaEnvironment::default());
+
+    let before = |node: &StyledNode| node.before.as_ref().unwrap().generated_content.clone();
+    let first = &styled.children[0];
+    let second = &styled.children[1];
+    let nested = &second.children[0].children[0];
+    assert_eq!(before(first), Some("1 a".to_string()));
+    assert_eq!(before(second), Some("2 b".to_string()));
+    assert_eq!(before(nested), Some("2.1 ".to_string()));
+    assert_eq!(
+        first.after.as_ref().unwrap().generated_content,
+        Some("<\u{201C}>".to_string())
+    );
+}
+
+#[test]
+fn test_large_counter_increments() {
+    use html::html_parser::new_html_parser;
+    use html::interface::HTMLParserTrait;
+    use style::style_tree;
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+    use style_sheet::media_query::MediaEnvironment;
+
+    let html = "<div><p></p><p></p></div><section><p></p></section>";
+    let css = "div p { counter-increment: c 2000000000 } \
+               section { counter-reset: c -2147483648 } \
+               section p { counter-increment: c -1 } \
+               p::before { content: counter(c) }";
+    let root = new_html_parser(html.to_string()).parse();
+    let stylesheet = new_css_parser(css.to_string()).parse();
+    let styled = style_tree(&root, &[&stylesheet], MediaEnvironment::default());
+
+    let before = |node: &StyledNode| node.before.as_ref().unwrap().generated_content.clone();
+    let div = &styled.children[0];
+    let section = &styled.children[1];
+    assert_eq!(before(&div.children[0]), Some("2000000000".to_string()));
+    assert_eq!(before(&div.children[1]), Some(i32::MAX.to_string()));
+    assert_eq!(before(&section.children[0]), Some(i32::MIN.to_string()));
+}
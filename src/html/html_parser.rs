@@ -6,19 +6,12 @@ use parser::Parser;
 use std::collections::HashMap;
 
 pub fn new_html_parser(source: String) -> impl HTMLParserTrait {
-    return Parser {
-        position: 0,
-        source: source,
-    };
+    Parser::new(source)
 }
 
 impl HTMLParserTrait for Parser {
     fn parse(&mut self) -> Node {
-        let mut nodes = Parser {
-            position: 0,
-            source: self.source.clone(),
-        }
-        .parse_nodes();
+        let mut nodes = Parser::new(self.source.clone()).parse_nodes();
 
         if nodes.len() == 1 {
             nodes.swap_remove(0)
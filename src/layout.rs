@@ -1,10 +1,10 @@
 // style => 2次元空間の長方形の束に変換
 // StyledNodeを受け取り、レイアウトをくむ(CSS必須)
 
+use computed_style::LengthPercentageOrAuto::{Auto, Length};
+use computed_style::{Display, LengthPercentage, LengthPercentageOrAuto};
 use std::default::Default;
 use style::StyledNode;
-use style_sheet::interface::ValueTrait;
-use style_sheet::util::{Unit::*, Value::*};
 
 #[derive(Debug)]
 pub enum BoxType<'a> {
@@ -79,7 +79,7 @@ impl<'a> LayoutBox<'a> {
     fn new(box_type: BoxType) -> LayoutBox {
         LayoutBox {
             dimensions: Default::default(),
-            box_type: box_type,
+            box_type,
             children: Vec::new(),
         }
     }
@@ -127,123 +127,116 @@ impl<'a> LayoutBox<'a> {
     // 幅の計算
     // w3.org/TR/CSS2/visudet.html#blockwidth
     fn calculate_block_width(&mut self, containing_block: Dimensions) {
-        let style = self.get_styled_node();
+        let style = &self.get_styled_node().style;
+        // %は包含ブロックの幅を基準にする
+        let base = containing_block.content.width;
 
-        // widthが指定されていない場合を考慮する
-        let auto = Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or(auto.clone());
+        let mut width = style.width.clone();
+        let mut margin_left = style.margin.left.clone();
+        let mut margin_right = style.margin.right.clone();
 
-        let zero = Length(0.0, Px);
+        let border_left = style.border_width.left;
+        let border_right = style.border_width.right;
 
-        let mut margin_left = style.lookup("margin-left", "margin", &zero);
-        let mut margin_right = style.lookup("margin-right", "margin", &zero);
-
-        let border_left = style.lookup("border-left-width", "border-width", &zero);
-        let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-        let padding_left = style.lookup("padding-left", "padding", &zero);
-        let padding_right = style.lookup("padding-right", "padding", &zero);
+        let padding_left = style.padding.left.resolve(base);
+        let padding_right = style.padding.right.resolve(base);
 
         // 幅合計値
         let total = sum([
-            &margin_left,
-            &margin_right,
-            &border_left,
-            &border_right,
-            &padding_left,
-            &padding_right,
-            &width,
+            margin_left.resolve(base),
+            margin_right.resolve(base),
+            border_left,
+            border_right,
+            padding_left,
+            padding_right,
+            width.resolve(base),
         ]
         .iter()
-        .map(|v| v.to_px()));
+        .cloned());
 
         // 最初にボックス(total)が大きすぎるかどうかを確認
         // 大きい場合はmarginの幅を0にする
-        if width != auto && total > containing_block.content.width {
-            if margin_left == auto {
-                margin_left = Length(0.0, Px);
+        if width != Auto && total > containing_block.content.width {
+            if margin_left == Auto {
+                margin_left = px(0.0);
             }
-            if margin_right == auto {
-                margin_right = Length(0.0, Px);
+            if margin_right == Auto {
+                margin_right = px(0.0);
             }
         }
 
         // コンテナに残っている余分なスペースの量を計算
         let underflow = containing_block.content.width - total;
 
-        match (width == auto, margin_left == auto, margin_right == auto) {
+        match (width == Auto, margin_left == Auto, margin_right == Auto) {
             // 値が過大に制約されている場合は、margin_rightを計算します。
             (false, false, false) => {
-                margin_right = Length(margin_right.to_px() + underflow, Px);
+                margin_right = px(margin_right.resolve(base) + underflow);
             }
 
             // marginのどちらかがautoの場合
             (false, false, true) => {
-                margin_right = Length(underflow, Px);
+                margin_right = px(underflow);
             }
 
             (false, true, false) => {
-                margin_left = Length(underflow, Px);
+                margin_left = px(underflow);
             }
 
             // widthがautoの場合
             (true, _, _) => {
-                if margin_left == auto {
-                    margin_left = Length(0.0, Px);
+                if margin_left == Auto {
+                    margin_left = px(0.0);
                 }
-                if margin_right == auto {
-                    margin_right = Length(0.0, Px);
+                if margin_right == Auto {
+                    margin_right = px(0.0);
                 }
 
                 // underflowを埋める
                 if underflow >= 0.0 {
-                    width = Length(underflow, Px);
+                    width = px(underflow);
                 } else {
-                    width = Length(0.0, Px);
-                    margin_right = Length(margin_right.to_px() + underflow, Px);
+                    width = px(0.0);
+                    margin_right = px(margin_right.resolve(base) + underflow);
                 }
             }
 
             // If margin-left and margin-right are both auto, their used values are equal.
             (false, true, true) => {
-                margin_left = Length(underflow / 2.0, Px);
-                margin_right = Length(underflow / 2.0, Px);
+                margin_left = px(underflow / 2.0);
+                margin_right = px(underflow / 2.0);
             }
         }
 
         let d = &mut self.dimensions;
-        d.content.width = width.to_px();
+        d.content.width = width.resolve(base);
 
-        d.padding.left = padding_left.to_px();
-        d.padding.right = padding_right.to_px();
+        d.padding.left = padding_left;
+        d.padding.right = padding_right;
 
-        d.border.left = border_left.to_px();
-        d.border.right = border_right.to_px();
+        d.border.left = border_left;
+        d.border.right = border_right;
 
-        d.margin.left = margin_left.to_px();
-        d.margin.right = margin_right.to_px();
+        d.margin.left = margin_left.resolve(base);
+        d.margin.right = margin_right.resolve(base);
     }
 
     // 残りの余白/パディング/境界線のスタイルを検索し、これらを含むブロックの寸法とともに使用して、ページ上のこのブロックの位置を決定
     fn calculate_block_position(&mut self, containing_block: Dimensions) {
-        let style = self.get_styled_node();
+        let style = &self.get_styled_node().style;
         let d = &mut self.dimensions;
-
-        let zero = Length(0.0, Px);
+        // 上下のmargin, paddingの%も包含ブロックの幅を基準にする
+        let base = containing_block.content.width;
 
         // margin-topまたはmargin-bottomが`auto`の場合、使用される値は0である。
-        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+        d.margin.top = style.margin.top.resolve(base);
+        d.margin.bottom = style.margin.bottom.resolve(base);
 
-        d.border.top = style
-            .lookup("border-top-width", "border-width", &zero)
-            .to_px();
-        d.border.bottom = style
-            .lookup("border-bottom-width", "border-width", &zero)
-            .to_px();
+        d.border.top = style.border_width.top;
+        d.border.bottom = style.border_width.bottom;
 
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+        d.padding.top = style.padding.top.resolve(base);
+        d.padding.bottom = style.padding.bottom.resolve(base);
 
         d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
 
@@ -261,15 +254,19 @@ impl<'a> LayoutBox<'a> {
         for child in &mut self.children {
             child.layout(*d);
             // Track the height so each child is laid out below the previous content.
-            d.content.height = d.content.height + child.dimensions.margin_box().height;
+            d.content.height += child.dimensions.margin_box().height;
         }
     }
 
-    // 高さが明示的な長さに設定されている場合は、正確な長さを使用します。
+    // 高さが明示的な長さ（calc() などの数学関数を含む）に設定されている場合は、正確な長さを使用します。
     // それ以外の場合は `layout_block_children` で設定された値を保持します。
+    // 包含ブロックの高さは決まっていないので、%を含む高さは auto と同じです。
     fn calculate_block_height(&mut self) {
-        if let Some(Length(h, Px)) = self.get_styled_node().value("height") {
-            self.dimensions.content.height = h;
+        match self.get_styled_node().style.height {
+            Length(ref height) if !height.has_percentage() => {
+                self.dimensions.content.height = height.resolve(0.0);
+            }
+            _ => {}
         }
     }
 }
@@ -286,24 +283,43 @@ pub fn layout_tree<'a>(
 }
 
 fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
-    let mut root = LayoutBox::new(match style_node.display() {
-        Block => BoxType::BlockNode(style_node),
-        Inline => BoxType::InlineNode(style_node),
-        DisplayNone => panic!("Root node has display: none."),
+    // ブロック、フレックス、グリッド、表などはまだブロックとして配置する
+    let display = style_node.display();
+    let mut root = LayoutBox::new(if display == Display::None {
+        panic!("Root node has display: none.")
+    } else if display.is_inline_level() {
+        BoxType::InlineNode(style_node)
+    } else {
+        BoxType::BlockNode(style_node)
     });
+    add_child_boxes(&mut root, style_node);
+    root
+}
 
-    for child in &style_node.children {
+fn add_child_boxes<'a>(root: &mut LayoutBox<'a>, style_node: &'a StyledNode<'a>) {
+    // ::before と ::after は子要素の前後にボックスを生成する
+    let children = style_node
+        .before
+        .iter()
+        .map(|before| &**before)
+        .chain(style_node.children.iter())
+        .chain(style_node.after.iter().map(|after| &**after));
+    for child in children {
         match child.display() {
-            Block => root.children.push(build_layout_tree(child)),
-            Inline => root
+            Display::None => {} // display: none のノードはボックスを生成しない
+            // display: contents の要素は自身のボックスを作らず、子のボックスだけを生成する
+            Display::Contents => add_child_boxes(root, child),
+            display if display.is_inline_level() => root
                 .get_inline_container()
                 .children
                 .push(build_layout_tree(child)),
-            DisplayNone => panic!("Root node has display: none."),
+            _ => root.children.push(build_layout_tree(child)),
         }
     }
+}
 
-    return root;
+fn px(length: f32) -> LengthPercentageOrAuto {
+    Length(LengthPercentage::Px(length))
 }
 
 fn sum<I>(iter: I) -> f32
@@ -312,3 +328,66 @@ where
 {
     iter.fold(0., |a, b| a + b)
 }
+
+#[test]
+fn test_block_height() {
+    use html::html_parser::new_html_parser;
+    use html::interface::HTMLParserTrait;
+    use style::style_tree;
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+    use style_sheet::media_query::MediaEnvironment;
+
+    let html = "<html><div class=\"a\"></div><div class=\"b\"></div><div class=\"c\"></div></html>";
+    let css = "html, div { display: block } .a { height: 30px } \
+               .b { height: calc(2em + 10px) } .c { height: calc(50% + 10px) }";
+    let root = new_html_parser(html.to_string()).parse();
+    let stylesheet = new_css_parser(css.to_string()).parse();
+    let styled = style_tree(&root, &[&stylesheet], MediaEnvironment::default());
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = 800.0;
+    let layout = layout_tree(&styled, viewport);
+
+    let heights: Vec<f32> = layout
+        .children
+        .iter()
+        .map(|child| child.dimensions.content.height)
+        .collect();
+    // %を含む高さは包含ブロックの高さが決まらないので auto になる
+    assert_eq!(heights, vec![30.0, 42.0, 0.0]);
+    assert_eq!(layout.dimensions.content.height, 72.0);
+}
+
+#[test]
+fn test_display_values() {
+    use html::html_parser::new_html_parser;
+    use html::interface::HTMLParserTrait;
+    use style::style_tree;
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+    use style_sheet::media_query::MediaEnvironment;
+
+    let html = "<html><div class=\"a\"></div><div class=\"b\"><p></p><p></p></div>\
+                <span class=\"c\"></span><div class=\"d\"></div></html>";
+    let css = "html, div, p { display: block } div, p { height: 10px } \
+               .a { display: flex } .b { display: contents } \
+               .c { display: inline-block } .d { display: table }";
+    let root = new_html_parser(html.to_string()).parse();
+    let stylesheet = new_css_parser(css.to_string()).parse();
+    let styled = style_tree(&root, &[&stylesheet], MediaEnvironment::default());
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = 800.0;
+    let layout = layout_tree(&styled, viewport);
+
+    // flexとtableはブロック、contentsは子の p だけ、inline-blockは匿名のブロックに入る
+    let kinds: Vec<&str> = layout
+        .children
+        .iter()
+        .map(|child| match child.box_type {
+            BoxType::BlockNode(node) => node.display().as_str(),
+            BoxType::InlineNode(node) => node.display().as_str(),
+            BoxType::AnonymousBlock => "anonymous",
+        })
+        .collect();
+    assert_eq!(kinds, vec!["flex", "block", "block", "anonymous", "table"]);
+}
@@ -1,5 +1,7 @@
+pub mod computed_style;
 pub mod dom;
 pub mod error;
+pub mod generated_content;
 pub mod html;
 pub mod layout;
 pub mod painting;
@@ -3,25 +3,36 @@ extern crate browser;
 use browser::{
     html::interface::HTMLParserTrait,
     layout::{layout_tree, Dimensions},
-    style_sheet::interface::CSSParserTrait,
+    style_sheet::import::load_style_sheet,
+    style_sheet::media_query::MediaEnvironment,
+    style_sheet::style_sheet::Origin,
 };
 
 use std::default::Default;
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
 
 fn main() {
     let html = read_source("test.html".to_string());
-    let css = read_source("test.css".to_string());
 
     let root_node = browser::html::html_parser::new_html_parser(html).parse();
-    let stylesheet = browser::style_sheet::css_parser::new_css_parser(css).parse();
-    let style_root = browser::style::style_tree(&root_node, &stylesheet);
+    let (stylesheet, diagnostics) = load_style_sheet(Path::new("test.css"), Origin::Author);
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic);
+    }
 
     let mut dimensions: Dimensions = Default::default();
     dimensions.content.width = 800.0;
     dimensions.content.height = 600.0;
 
+    let media = MediaEnvironment {
+        width: dimensions.content.width,
+        height: dimensions.content.height,
+        ..Default::default()
+    };
+    let style_root = browser::style::style_tree(&root_node, &[&stylesheet], media);
+
     let layout_tree = layout_tree(&style_root, dimensions);
 
     println!("{:?}", layout_tree);
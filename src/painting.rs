@@ -1,10 +1,11 @@
+use computed_style::ComputedStyle;
 use layout::{BoxType, LayoutBox, Rect};
-use style_sheet::util::{Color, Value};
+use style_sheet::util::Color;
 
-struct Canvas {
-    pixels: Vec<Color>,
-    width: usize,
-    height: usize,
+pub struct Canvas {
+    pub pixels: Vec<Color>,
+    pub width: usize,
+    pub height: usize,
 }
 
 impl Canvas {
@@ -17,8 +18,8 @@ impl Canvas {
         };
         Canvas {
             pixels: vec![white; width * height],
-            width: width,
-            height: height,
+            width,
+            height,
         }
     }
 
@@ -30,10 +31,13 @@ impl Canvas {
                 let x1 = (rect.x + rect.width).clamp(0.0, self.width as f32) as usize;
                 let y1 = (rect.y + rect.height).clamp(0.0, self.height as f32) as usize;
 
+                if color.a == 0 {
+                    return;
+                }
                 for y in y0..y1 {
                     for x in x0..x1 {
-                        // TODO: alpha compositing with existing pixel
-                        self.pixels[y * self.width + x] = color;
+                        let pixel = &mut self.pixels[y * self.width + x];
+                        *pixel = blend(color, *pixel);
                     }
                 }
             }
@@ -41,25 +45,44 @@ impl Canvas {
     }
 }
 
-fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
+// source-over合成（ストレートアルファ）
+fn blend(source: Color, destination: Color) -> Color {
+    if source.a == 255 {
+        return source;
+    }
+    let source_alpha = source.a as f32 / 255.0;
+    let destination_alpha = destination.a as f32 / 255.0 * (1.0 - source_alpha);
+    let alpha = source_alpha + destination_alpha;
+    let channel = |s: u8, d: u8| {
+        ((s as f32 * source_alpha + d as f32 * destination_alpha) / alpha).round() as u8
+    };
+    Color {
+        r: channel(source.r, destination.r),
+        g: channel(source.g, destination.g),
+        b: channel(source.b, destination.b),
+        a: (alpha * 255.0).round() as u8,
+    }
+}
+
+pub fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
     let display_list = build_display_list(layout_root);
     let mut canvas = Canvas::new(bounds.width as usize, bounds.height as usize);
     for item in display_list {
         canvas.paint_item(&item);
     }
-    return canvas;
+    canvas
 }
 
-enum DisplayCommand {
+pub enum DisplayCommand {
     SolidColor(Color, Rect),
 }
 
-type DisplayList = Vec<DisplayCommand>;
+pub type DisplayList = Vec<DisplayCommand>;
 
-fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
+pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
     let mut list = Vec::new();
     render_layout_box(&mut list, layout_root);
-    return list;
+    list
 }
 
 fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
@@ -67,88 +90,79 @@ fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
     render_borders(list, layout_box);
 
     for child in &layout_box.children {
-        render_layout_box(list, layout_box);
+        render_layout_box(list, child);
     }
 }
 
 fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
-    get_color(layout_box, "background").map(|color| {
+    if let Some(color) = get_style(layout_box).map(|style| style.background_color) {
         list.push(DisplayCommand::SolidColor(
             color,
             layout_box.dimensions.border_box(),
         ))
-    });
+    }
 }
 
 fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
-    let color = match get_color(layout_box, "border-color") {
-        Some(color) => color,
-        _ => return,
-    };
-
     let d = &layout_box.dimensions;
     let border_box = d.border_box();
 
     // Left Border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: d.border.left,
-            height: border_box.height,
-        },
-    ));
+    if let Some(color) = get_style(layout_box).map(|style| style.border_color.left) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: d.border.left,
+                height: border_box.height,
+            },
+        ));
+    }
 
     // Right border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x + border_box.width - d.border.right,
-            y: border_box.y,
-            width: d.border.right,
-            height: border_box.height,
-        },
-    ));
+    if let Some(color) = get_style(layout_box).map(|style| style.border_color.right) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x + border_box.width - d.border.right,
+                y: border_box.y,
+                width: d.border.right,
+                height: border_box.height,
+            },
+        ));
+    }
 
     // Top border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: border_box.width,
-            height: d.border.top,
-        },
-    ));
+    if let Some(color) = get_style(layout_box).map(|style| style.border_color.top) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: border_box.width,
+                height: d.border.top,
+            },
+        ));
+    }
 
     // Bottom border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y + border_box.height - d.border.bottom,
-            width: border_box.width,
-            height: d.border.bottom,
-        },
-    ));
+    if let Some(color) = get_style(layout_box).map(|style| style.border_color.bottom) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y + border_box.height - d.border.bottom,
+                width: border_box.width,
+                height: d.border.bottom,
+            },
+        ));
+    }
 }
 
-fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
+fn get_style<'a>(layout_box: &LayoutBox<'a>) -> Option<&'a ComputedStyle> {
     match layout_box.box_type {
-        BoxType::BlockNode(style) | BoxType::InlineNode(style) => match style.value(name) {
-            Some(Value::ColorValue(color)) => Some(color),
-            _ => None,
-        },
+        BoxType::BlockNode(node) | BoxType::InlineNode(node) => Some(&node.style),
         BoxType::AnonymousBlock => None,
     }
 }
-
-trait Clamp {
-    fn clamp(self, lower: Self, upper: Self) -> Self;
-}
-impl Clamp for f32 {
-    fn clamp(self, lower: f32, upper: f32) -> f32 {
-        self.max(lower).min(upper)
-    }
-}
@@ -6,6 +6,26 @@ use error::Error;
 pub struct Parser {
     pub position: usize,
     pub source: String,
+    /// Problems the parser recovered from, in order of appearance.
+    pub errors: Vec<ParseError>,
+}
+
+/// Invalid input that the parser dropped before carrying on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Byte offset in the source.
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Parser {
+    pub fn new(source: String) -> Parser {
+        Parser {
+            position: 0,
+            source,
+            errors: Vec::new(),
+        }
+    }
 }
 
 impl interface::DefaultParserTrait for Parser {
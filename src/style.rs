@@ -1,20 +1,40 @@
 // domにcssスタイルシートを適用するファイル
+use computed_style::{ComputedStyle, Display};
 use dom::dom::{ElementData, Node, NodeType};
+use dom::element_state::ElementState;
+use generated_content::resolve_generated_content;
+use parser::interface::DefaultParserTrait;
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use style_sheet::bloom_filter::{self, CountingBloomFilter, ANCESTOR_HASHES};
+use style_sheet::color;
+use style_sheet::css_parser::new_css_parser;
+use style_sheet::declaration::Declaration;
+use style_sheet::interface::CSSParserTrait;
 use style_sheet::interface::SelectorTrait;
+use style_sheet::interface::ValueTrait;
+use style_sheet::layer::{LayerKey, LayerTree};
+use style_sheet::media_query::MediaEnvironment;
+use style_sheet::property;
+use style_sheet::pseudo_class::PseudoClass;
+use style_sheet::pseudo_element::PseudoElement;
 use style_sheet::rule::Rule;
-use style_sheet::selector::{Selector, Specificity};
+use style_sheet::selector::{Combinator, RelativeSelector, Selector, Specificity};
+use style_sheet::selector_map::SelectorMap;
+use style_sheet::shorthand::expand_shorthand;
 use style_sheet::simple_selector::SimpleSelector;
-use style_sheet::style_sheet::Stylesheet;
-use style_sheet::util::Value;
+use style_sheet::source_location::SourceLocation;
+use style_sheet::style_sheet::{Origin, Stylesheet};
+use style_sheet::user_agent::user_agent_stylesheet;
+use style_sheet::util::{Color, LengthContext, Unit, Value, DEFAULT_FONT_SIZE};
+use style_sheet::variable::{self, is_custom_property};
 
 type CSSPropertyMap = HashMap<String, Value>;
-
-pub enum Display {
-    Inline,
-    Block,
-    None,
-}
+// プロパティごとの、カスケードで勝った宣言の位置
+type SourceMap = HashMap<String, SourceLocation>;
 
 #[derive(Debug)]
 /*
@@ -25,40 +45,170 @@ pub enum Display {
 */
 pub struct StyledNode<'a> {
     pub node: &'a Node,
-    pub css_properties: CSSPropertyMap,
+    // 差分スタイル計算で保持しているスタイルと共有する
+    pub style: Arc<ComputedStyle>,
     pub children: Vec<StyledNode<'a>>,
+    // ::before / ::after の場合、nodeは生成元の要素を指す
+    pub pseudo_element: Option<PseudoElement>,
+    pub before: Option<Box<StyledNode<'a>>>,
+    pub after: Option<Box<StyledNode<'a>>>,
+    // contentプロパティから生成されたテキスト
+    pub generated_content: Option<String>,
+    // カスケードで値が決まったプロパティの宣言の位置（継承した値や初期値は含まない）
+    pub sources: Arc<SourceMap>,
 }
 
 impl<'a> StyledNode<'a> {
     pub fn value(&self, name: &str) -> Option<Value> {
-        self.css_properties.get(name).map(|v| v.clone())
+        self.style.get(name)
     }
 
     pub fn display(&self) -> Display {
-        match self.value("display") {
-            Some(Value::Keyword(s)) => match &*s {
-                "block" => Display::Block,
-                "none" => Display::None,
-                _ => Display::Inline,
-            },
-            _ => Display::Inline,
+        self.style.display
+    }
+
+    /// Where the declaration that set `name` on this element was written.
+    /// `None` if no declaration applies and the value is inherited or initial.
+    pub fn source(&self, name: &str) -> Option<&SourceLocation> {
+        self.sources.get(name)
+    }
+}
+
+/*
+ セレクターマッチング用の要素への参照
+ コンビネーターや:has()の判定のため、親への参照と親のchildren内での位置を保持する
+*/
+#[derive(Clone, Copy)]
+struct ElementRef<'a> {
+    node: &'a Node,
+    parent: Option<&'a ElementRef<'a>>,
+    index: usize,
+    descendant_states: &'a DescendantStates,
+}
+
+impl<'a> ElementRef<'a> {
+    fn data(&self) -> Option<&'a ElementData> {
+        match self.node.node_type {
+            NodeType::Element(ref elem) => Some(elem),
+            NodeType::Text(_) => None,
         }
     }
 
-    pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
-        self.value(name)
-            .unwrap_or_else(|| self.value(fallback_name).unwrap_or_else(|| default.clone()))
+    fn is(&self, other: &ElementRef) -> bool {
+        std::ptr::eq(self.node, other.node)
+    }
+
+    fn ancestors(&self) -> impl Iterator<Item = &'a ElementRef<'a>> {
+        std::iter::successors(self.parent, |parent| parent.parent)
+    }
+
+    // 直前の兄弟要素から順に返す
+    fn previous_siblings(&self) -> Vec<ElementRef<'a>> {
+        match self.parent {
+            Some(parent) => (0..self.index)
+                .rev()
+                .map(|index| parent.child(index))
+                .filter(|sibling| sibling.data().is_some())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn following_siblings(&self) -> Vec<ElementRef<'a>> {
+        match self.parent {
+            Some(parent) => (self.index + 1..parent.node.children.len())
+                .map(|index| parent.child(index))
+                .filter(|sibling| sibling.data().is_some())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn child(&'a self, index: usize) -> ElementRef<'a> {
+        ElementRef {
+            node: &self.node.children[index],
+            parent: Some(self),
+            index,
+            descendant_states: self.descendant_states,
+        }
+    }
+
+    // 子孫要素の状態の和集合
+    fn states_of_descendants(&self) -> ElementState {
+        self.descendant_states.get(self.node)
+    }
+
+    // 子孫要素のいずれかが条件を満たすか（深さ優先）
+    fn any_descendant(&self, f: &dyn Fn(&ElementRef) -> bool) -> bool {
+        (0..self.node.children.len()).any(|index| {
+            let child = self.child(index);
+            child.data().is_some() && (f(&child) || child.any_descendant(f))
+        })
     }
 }
 
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+fn matches(element: &ElementRef, selector: &Selector) -> bool {
+    matches_anchored(element, selector, None)
+}
+
+// anchor: :has()の基点要素と、基点から左端の複合セレクターへのコンビネーター
+fn matches_anchored(
+    element: &ElementRef,
+    selector: &Selector,
+    anchor: Option<(&ElementRef, Combinator)>,
+) -> bool {
     match *selector {
-        Selector::Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector),
+        Selector::Simple(ref simple_selector) => {
+            matches_simple_selector(element, simple_selector)
+                && anchor.is_none_or(|(anchor, combinator)| is_related(anchor, combinator, element))
+        }
+        Selector::Complex(ref left, combinator, ref right) => {
+            if !matches_simple_selector(element, right) {
+                return false;
+            }
+            match combinator {
+                Combinator::Child => element
+                    .parent
+                    .is_some_and(|parent| matches_anchored(parent, left, anchor)),
+                Combinator::Descendant => element
+                    .ancestors()
+                    .any(|ancestor| matches_anchored(ancestor, left, anchor)),
+                Combinator::NextSibling => element
+                    .previous_siblings()
+                    .first()
+                    .is_some_and(|sibling| matches_anchored(sibling, left, anchor)),
+                Combinator::SubsequentSibling => element
+                    .previous_siblings()
+                    .iter()
+                    .any(|sibling| matches_anchored(sibling, left, anchor)),
+            }
+        }
+    }
+}
+
+// elementがanchorからみてcombinatorの関係にあるか
+fn is_related(anchor: &ElementRef, combinator: Combinator, element: &ElementRef) -> bool {
+    match combinator {
+        Combinator::Child => element.parent.is_some_and(|parent| parent.is(anchor)),
+        Combinator::Descendant => element.ancestors().any(|ancestor| ancestor.is(anchor)),
+        Combinator::NextSibling => element
+            .previous_siblings()
+            .first()
+            .is_some_and(|sibling| sibling.is(anchor)),
+        Combinator::SubsequentSibling => element
+            .previous_siblings()
+            .iter()
+            .any(|sibling| sibling.is(anchor)),
     }
 }
 
 // cssで定義されているセレクターとdomのchildrenの各要素のタグの整合性の確認
-fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
+fn matches_simple_selector(element: &ElementRef, selector: &SimpleSelector) -> bool {
+    let elem = match element.data() {
+        Some(elem) => elem,
+        None => return false,
+    };
+
     // tagチェック
     if selector.tag_name.iter().any(|name| elem.tag_name != *name) {
         return false;
@@ -79,50 +229,2934 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
         return false;
     }
 
-    return true;
+    // 疑似クラスチェック
+    selector
+        .pseudo_classes
+        .iter()
+        .all(|pseudo_class| matches_pseudo_class(element, pseudo_class))
+}
+
+fn matches_pseudo_class(element: &ElementRef, pseudo_class: &PseudoClass) -> bool {
+    match *pseudo_class {
+        PseudoClass::Not(ref selectors) => !selectors.iter().any(|s| matches(element, s)),
+        PseudoClass::Is(ref selectors) | PseudoClass::Where(ref selectors) => {
+            selectors.iter().any(|s| matches(element, s))
+        }
+        PseudoClass::Has(ref selectors) => selectors.iter().any(|s| has_match(element, s)),
+        // :hover/:activeは祖先要素にも伝播する
+        PseudoClass::Hover => in_state_or_descendant(element, ElementState::HOVER),
+        PseudoClass::Active => in_state_or_descendant(element, ElementState::ACTIVE),
+        PseudoClass::Focus | PseudoClass::FocusVisible => in_state(element, ElementState::FOCUS),
+        PseudoClass::FocusWithin => {
+            in_state(element, ElementState::FOCUS | ElementState::FOCUS_WITHIN)
+                || in_state_or_descendant(element, ElementState::FOCUS)
+        }
+        PseudoClass::Checked => {
+            in_state(element, ElementState::CHECKED) || has_attribute(element, "checked")
+        }
+        PseudoClass::Disabled => is_disabled(element),
+        PseudoClass::Enabled => is_form_control(element) && !is_disabled(element),
+        PseudoClass::Link => is_link(element) && !in_state(element, ElementState::VISITED),
+        PseudoClass::Visited => is_link(element) && in_state(element, ElementState::VISITED),
+        PseudoClass::AnyLink => is_link(element),
+        PseudoClass::Target => in_state(element, ElementState::TARGET),
+    }
+}
+
+fn in_state(element: &ElementRef, state: ElementState) -> bool {
+    element
+        .data()
+        .is_some_and(|elem| elem.state.intersects(state))
+}
+
+fn in_state_or_descendant(element: &ElementRef, state: ElementState) -> bool {
+    in_state(element, state) || element.states_of_descendants().intersects(state)
+}
+
+/*
+ 子孫要素の状態の和集合
+ :hoverなどのマッチや共有の判定のたびに部分木をたどらないよう、木をたどる前に一度だけ求める
+ 状態を持つ子孫がある要素だけを記録する
+*/
+#[derive(Default)]
+struct DescendantStates(HashMap<usize, ElementState>);
+
+impl DescendantStates {
+    fn new(root: &Node) -> DescendantStates {
+        let mut states = DescendantStates::default();
+        states.collect(root);
+        states
+    }
+
+    // nodeとその子孫の状態の和集合を返す
+    fn collect(&mut self, node: &Node) -> ElementState {
+        let descendants = node
+            .children
+            .iter()
+            .fold(ElementState::empty(), |states, child| {
+                states | self.collect(child)
+            });
+        self.insert(node, descendants);
+        match node.node_type {
+            NodeType::Element(ref elem) => descendants | elem.state,
+            NodeType::Text(_) => descendants,
+        }
+    }
+
+    fn insert(&mut self, node: &Node, descendants: ElementState) {
+        if !descendants.is_empty() {
+            self.0.insert(node as *const Node as usize, descendants);
+        }
+    }
+
+    fn get(&self, node: &Node) -> ElementState {
+        self.0
+            .get(&(node as *const Node as usize))
+            .cloned()
+            .unwrap_or(ElementState::empty())
+    }
+}
+
+fn has_attribute(element: &ElementRef, name: &str) -> bool {
+    element
+        .data()
+        .is_some_and(|elem| elem.attributes.contains_key(name))
+}
+
+fn is_form_control(element: &ElementRef) -> bool {
+    element.data().is_some_and(|elem| {
+        matches!(
+            &*elem.tag_name,
+            "button" | "input" | "select" | "textarea" | "option" | "optgroup" | "fieldset"
+        )
+    })
+}
+
+fn is_disabled(element: &ElementRef) -> bool {
+    in_state(element, ElementState::DISABLED)
+        || (is_form_control(element) && has_attribute(element, "disabled"))
+}
+
+fn is_link(element: &ElementRef) -> bool {
+    element.data().is_some_and(|elem| {
+        matches!(&*elem.tag_name, "a" | "area" | "link") && elem.attributes.contains_key("href")
+    })
+}
+
+// :has()の相対セレクターにマッチする要素が基点要素の周囲に存在するか
+fn has_match(anchor: &ElementRef, relative: &RelativeSelector) -> bool {
+    let found = |candidate: &ElementRef| {
+        matches_anchored(
+            candidate,
+            &relative.selector,
+            Some((anchor, relative.combinator)),
+        )
+    };
+    match relative.combinator {
+        Combinator::Child | Combinator::Descendant => anchor.any_descendant(&found),
+        Combinator::NextSibling | Combinator::SubsequentSibling => anchor
+            .following_siblings()
+            .iter()
+            .any(|sibling| found(sibling) || sibling.any_descendant(&found)),
+    }
 }
 
-type MatchedRule<'a> = (Specificity, &'a Rule);
+// カスケードの対象となるマッチしたルール
+struct MatchedRule<'a> {
+    rule: &'a Rule,
+    // マッチしたセレクターのうち最も詳細度の高いもの
+    selector: &'a Selector,
+    specificity: Specificity,
+    origin: Origin,
+    layer: &'a LayerKey,
+    // 出現順 (スタイルシートの順番, スタイルシート内のルールの順番)
+    order: (usize, usize),
+}
+
+// セレクターの索引から候補を取り出し、ルールごとにマッチした最初のセレクターを返す
+// ルールのセレクターは詳細度の高い順に並んでいるので、最初にマッチしたものが最も詳細度が高い
+// ancestorsは要素の祖先のキーを入れたフィルターで、必要な祖先がいない候補は試さない
+fn matching_rules<'a>(
+    element: &ElementRef,
+    sheets: &'a [CascadeSheet<'a>],
+    pseudo_element: Option<PseudoElement>,
+    ancestors: &CountingBloomFilter,
+) -> Vec<MatchedRule<'a>> {
+    let elem = match element.data() {
+        Some(elem) => elem,
+        None => return Vec::new(),
+    };
+    let classes = elem.classes();
+    let mut matched = Vec::new();
+    for (sheet_index, sheet) in sheets.iter().enumerate() {
+        let mut candidates = sheet.selectors.candidates(
+            &elem.tag_name,
+            elem.id().map(String::as_str),
+            classes.iter().cloned(),
+        );
+        candidates.sort_unstable();
+        let mut matched_rule = None;
+        for entry in candidates {
+            let rule_index = entry.rule;
+            if matched_rule == Some(rule_index)
+                || !ancestors.might_contain_all(&entry.ancestor_hashes)
+            {
+                continue;
+            }
+            let (ref rule, ref layer) = sheet.rules[rule_index];
+            let rule: &Rule = rule;
+            let selector = &rule.selectors[entry.selector];
+            if selector.rightmost().pseudo_element == pseudo_element && matches(element, selector) {
+                matched_rule = Some(rule_index);
+                matched.push(MatchedRule {
+                    rule,
+                    selector,
+                    specificity: selector.specificity(),
+                    origin: sheet.origin,
+                    layer,
+                    order: (sheet_index, rule_index),
+                });
+            }
+        }
+    }
+    matched
+}
+
+// https://www.w3.org/TR/css-cascade-4/#cascade-origin
+// !importantな宣言ではオリジンの優先順位が逆転する
+fn cascade_rank(origin: Origin, important: bool) -> u8 {
+    match (origin, important) {
+        (Origin::UserAgent, false) => 0,
+        (Origin::User, false) => 1,
+        (Origin::Author, false) => 2,
+        (Origin::Author, true) => 3,
+        (Origin::User, true) => 4,
+        (Origin::UserAgent, true) => 5,
+    }
+}
+
+// カスケードの対象となるスタイルシートと、メディア環境で有効なルールとそのレイヤー
+// 差分スタイル計算ではスタイルシートより長く持つので、ルールを所有することもある
+struct CascadeSheet<'a> {
+    origin: Origin,
+    rules: Vec<(Cow<'a, Rule>, LayerKey)>,
+    selectors: SelectorMap<SelectorEntry>,
+    // 右端の複合セレクターにある疑似要素
+    pseudo_elements: Vec<PseudoElement>,
+}
+
+// 索引に入れるルールの各セレクター
+// 番号の順に並べると、ルールの順、ルール内では詳細度の高い順になる
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct SelectorEntry {
+    rule: usize,
+    selector: usize,
+    // 祖先に必要なid、クラス、タグ名のハッシュ
+    ancestor_hashes: [u32; ANCESTOR_HASHES],
+}
+
+impl<'a> CascadeSheet<'a> {
+    fn new(origin: Origin, rules: Vec<(&'a Rule, LayerKey)>) -> CascadeSheet<'a> {
+        let mut selectors = SelectorMap::default();
+        let mut pseudo_elements = Vec::new();
+        for (rule_index, &(rule, _)) in rules.iter().enumerate() {
+            for (selector_index, selector) in rule.selectors.iter().enumerate() {
+                let entry = SelectorEntry {
+                    rule: rule_index,
+                    selector: selector_index,
+                    ancestor_hashes: bloom_filter::ancestor_hashes(selector),
+                };
+                selectors.insert(selector, entry);
+                if let Some(pseudo_element) = selector.rightmost().pseudo_element {
+                    if !pseudo_elements.contains(&pseudo_element) {
+                        pseudo_elements.push(pseudo_element);
+                    }
+                }
+            }
+        }
+        CascadeSheet {
+            origin,
+            rules: rules
+                .into_iter()
+                .map(|(rule, layer)| (Cow::Borrowed(rule), layer))
+                .collect(),
+            selectors,
+            pseudo_elements,
+        }
+    }
 
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
-    rule.selectors
+    // ルールを複製して、スタイルシートを借用しないようにする
+    fn into_owned(self) -> CascadeSheet<'static> {
+        CascadeSheet {
+            origin: self.origin,
+            rules: self
+                .rules
+                .into_iter()
+                .map(|(rule, layer)| (Cow::Owned(rule.into_owned()), layer))
+                .collect(),
+            selectors: self.selectors,
+            pseudo_elements: self.pseudo_elements,
+        }
+    }
+}
+
+/// Styles the DOM with the built-in user-agent stylesheet followed by `stylesheets`
+/// (user and author sheets, in order of appearance). Rules in `@media` blocks
+/// apply only if their queries match `media`. Cascade layers are shared by all
+/// sheets of the same origin.
+pub fn style_tree<'a>(
+    root: &'a Node,
+    stylesheets: &[&Stylesheet],
+    media: MediaEnvironment,
+) -> StyledNode<'a> {
+    style_tree_with_statistics(root, stylesheets, media).0
+}
+
+/// How often `style_tree` reused the style of a previous sibling instead of
+/// matching rules for an element.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StyleSharingStatistics {
+    pub hits: usize,
+    /// Elements that could have shared a style but found no matching sibling.
+    pub misses: usize,
+}
+
+/// `style_tree`, also returning how often styles were shared between siblings.
+pub fn style_tree_with_statistics<'a>(
+    root: &'a Node,
+    stylesheets: &[&Stylesheet],
+    media: MediaEnvironment,
+) -> (StyledNode<'a>, StyleSharingStatistics) {
+    let sheets = cascade_sheets(stylesheets, &media);
+    let length_context = LengthContext {
+        viewport_width: media.width,
+        viewport_height: media.height,
+        ..Default::default()
+    };
+    let parent_style = ParentStyle {
+        style: None,
+        context: length_context,
+    };
+    let hazards = sharing_hazards(&sheets);
+    let descendant_states = DescendantStates::new(root);
+    let mut context = StyleContext {
+        sheets: &sheets,
+        ancestors: CountingBloomFilter::default(),
+        descendant_states: &descendant_states,
+        share_styles: !hazards.structural,
+        check_descendant_states: hazards.descendant_states,
+        statistics: StyleSharingStatistics::default(),
+    };
+    let mut styled_root = style_node(root, None, 0, &parent_style, &mut context, &mut Vec::new());
+    resolve_generated_content(&mut styled_root);
+    (styled_root, context.statistics)
+}
+
+// ユーザーエージェントのスタイルシートに続けて、与えられたスタイルシートを並べる
+fn cascade_sheets<'s>(
+    stylesheets: &[&'s Stylesheet],
+    media: &MediaEnvironment,
+) -> Vec<CascadeSheet<'s>> {
+    let mut layers: HashMap<Origin, LayerTree> = HashMap::new();
+    std::iter::once(user_agent_stylesheet())
+        .chain(stylesheets.iter().cloned())
+        .map(|stylesheet| {
+            let layers = layers.entry(stylesheet.origin).or_default();
+            CascadeSheet::new(stylesheet.origin, stylesheet.cascade_rules(media, layers))
+        })
+        .collect()
+}
+
+// 木をたどってスタイルを計算する間の状態
+struct StyleContext<'s> {
+    sheets: &'s [CascadeSheet<'s>],
+    // 今スタイルを計算している要素の祖先
+    ancestors: CountingBloomFilter,
+    descendant_states: &'s DescendantStates,
+    share_styles: bool,
+    // 子孫の状態に依存する疑似クラスがあり、子孫が:hoverなどの要素は共有できない
+    check_descendant_states: bool,
+    statistics: StyleSharingStatistics,
+}
+
+/*
+ スタイル共有キャッシュ
+ 同じ親を持ち、タグ名・属性・状態が同じ兄弟要素には同じルールがマッチするので、計算値をそのまま使える
+ ただし兄弟や子孫によってマッチが変わるセレクター（兄弟コンビネーター、:has()）があるときは共有しない
+*/
+const SHARING_CACHE_SIZE: usize = 8;
+
+struct SharedStyle<'a> {
+    element: &'a ElementData,
+    style: Arc<ComputedStyle>,
+    sources: Arc<SourceMap>,
+    context: LengthContext,
+}
+
+// スタイルシートのセレクターのうち、スタイル共有を妨げるもの
+#[derive(Default)]
+struct SharingHazards {
+    // 兄弟コンビネーターか:has()
+    structural: bool,
+    // :hover, :active, :focus-within
+    descendant_states: bool,
+}
+
+fn sharing_hazards(sheets: &[CascadeSheet]) -> SharingHazards {
+    let mut hazards = SharingHazards::default();
+    for sheet in sheets {
+        for (rule, _) in &sheet.rules {
+            for selector in &rule.selectors {
+                collect_sharing_hazards(selector, &mut hazards);
+            }
+        }
+    }
+    hazards
+}
+
+fn collect_sharing_hazards(selector: &Selector, hazards: &mut SharingHazards) {
+    let compound = match *selector {
+        Selector::Simple(ref compound) => compound,
+        Selector::Complex(ref left, combinator, ref compound) => {
+            if let Combinator::NextSibling | Combinator::SubsequentSibling = combinator {
+                hazards.structural = true;
+            }
+            collect_sharing_hazards(left, hazards);
+            compound
+        }
+    };
+    for pseudo_class in &compound.pseudo_classes {
+        match *pseudo_class {
+            PseudoClass::Not(ref selectors)
+            | PseudoClass::Is(ref selectors)
+            | PseudoClass::Where(ref selectors) => {
+                for selector in selectors {
+                    collect_sharing_hazards(selector, hazards);
+                }
+            }
+            // :has()の中の:hoverなども子孫の状態を参照する
+            PseudoClass::Has(ref relatives) => {
+                hazards.structural = true;
+                for relative in relatives {
+                    collect_sharing_hazards(&relative.selector, hazards);
+                }
+            }
+            PseudoClass::Hover | PseudoClass::Active | PseudoClass::FocusWithin => {
+                hazards.descendant_states = true
+            }
+            _ => {}
+        }
+    }
+}
+
+// 兄弟とスタイルを共有してよい要素か
+fn can_share_style(element: &ElementRef, context: &StyleContext) -> bool {
+    context.share_styles
+        && element.data().is_some()
+        && !(context.check_descendant_states
+            && element
+                .states_of_descendants()
+                .intersects(ElementState::HOVER | ElementState::ACTIVE | ElementState::FOCUS))
+}
+
+fn style_node<'a>(
+    node: &'a Node,
+    parent: Option<&ElementRef>,
+    index: usize,
+    parent_style: &ParentStyle,
+    context: &mut StyleContext,
+    sharing_cache: &mut Vec<SharedStyle<'a>>,
+) -> StyledNode<'a> {
+    let element = ElementRef {
+        node,
+        parent,
+        index,
+        descendant_states: context.descendant_states,
+    };
+    let (mut styled, length_context) =
+        style_element(node, &element, parent_style, context, sharing_cache);
+    let element_style = ParentStyle {
+        style: Some(&styled.style),
+        context: length_context,
+    };
+    let children = style_children(node, &element, &element_style, context);
+    styled.children = children;
+    styled
+}
+
+// 要素自身と疑似要素のスタイルを計算する（子はまだ含まない）
+// 子のスタイルの計算に使うLengthContextも返す
+fn style_element<'a>(
+    node: &'a Node,
+    element: &ElementRef,
+    parent_style: &ParentStyle,
+    context: &mut StyleContext,
+    sharing_cache: &mut Vec<SharedStyle<'a>>,
+) -> (StyledNode<'a>, LengthContext) {
+    let elem = match node.node_type {
+        NodeType::Element(ref elem) => Some(elem),
+        NodeType::Text(_) => None,
+    };
+    let shareable = can_share_style(element, context);
+    let shared = match elem {
+        Some(elem) if shareable => sharing_cache
+            .iter()
+            .position(|shared| shared.element == elem),
+        _ => None,
+    };
+    let (style, sources, length_context) = match shared {
+        Some(position) => {
+            context.statistics.hits += 1;
+            let shared = &sharing_cache[position];
+            (shared.style.clone(), shared.sources.clone(), shared.context)
+        }
+        None => {
+            let (css_properties, sources) = if elem.is_some() {
+                parse_css_property(element, context.sheets, None, &context.ancestors)
+            } else {
+                (HashMap::new(), HashMap::new())
+            };
+            let (style, length_context) =
+                compute_values(css_properties, parent_style, element.parent.is_none());
+            let (style, sources) = (Arc::new(style), Arc::new(sources));
+            if let Some(elem) = elem.filter(|_| shareable) {
+                context.statistics.misses += 1;
+                sharing_cache.insert(
+                    0,
+                    SharedStyle {
+                        element: elem,
+                        style: style.clone(),
+                        sources: sources.clone(),
+                        context: length_context,
+                    },
+                );
+                sharing_cache.truncate(SHARING_CACHE_SIZE);
+            }
+            (style, sources, length_context)
+        }
+    };
+    let element_style = ParentStyle {
+        style: Some(&style),
+        context: length_context,
+    };
+    let before = style_pseudo_element(
+        node,
+        element,
+        context.sheets,
+        &element_style,
+        PseudoElement::Before,
+        &context.ancestors,
+    );
+    let after = style_pseudo_element(
+        node,
+        element,
+        context.sheets,
+        &element_style,
+        PseudoElement::After,
+        &context.ancestors,
+    );
+    let styled = StyledNode {
+        node,
+        style,
+        children: Vec::new(),
+        pseudo_element: None,
+        before,
+        after,
+        generated_content: None,
+        sources,
+    };
+    (styled, length_context)
+}
+
+// elementはnodeを指す
+fn style_children<'a>(
+    node: &'a Node,
+    element: &ElementRef,
+    element_style: &ParentStyle,
+    context: &mut StyleContext,
+) -> Vec<StyledNode<'a>> {
+    // 子要素のスタイルを計算する間は、この要素を祖先としてフィルターに入れておく
+    let hashes = element_hashes(element);
+    for &hash in &hashes {
+        context.ancestors.insert_hash(hash);
+    }
+    let mut sharing_cache = Vec::new();
+    let children = node
+        .children
         .iter()
-        .find(|selector| matches(elem, *selector))
-        .map(|selector| (selector.specificity(), rule))
+        .enumerate()
+        .map(|(index, child)| {
+            style_node(
+                child,
+                Some(element),
+                index,
+                element_style,
+                context,
+                &mut sharing_cache,
+            )
+        })
+        .collect();
+    for &hash in &hashes {
+        context.ancestors.remove_hash(hash);
+    }
+    children
 }
 
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a Stylesheet) -> Vec<MatchedRule<'a>> {
-    stylesheet
-        .rules
+/*
+ 並列スタイル計算
+ 木の上の方の要素は1スレッドで計算し、ある深さの部分木をタスクとして共有のキューに入れる
+ 各スレッドはキューから次の部分木を取っていく。大きすぎる部分木はルートの要素だけを計算し、
+ 子の部分木をタスクとしてキューに戻すので、大きな部分木に当たったスレッドがあっても空いたスレッドが残りを引き受ける
+*/
+const TASKS_PER_THREAD: usize = 8;
+
+/// `style_tree` computed on up to `threads` threads. The subtrees below the top
+/// few levels of the tree are styled in parallel, and a subtree much larger
+/// than the others is split again into its children's subtrees when a thread
+/// takes it; the result is the same as that of `style_tree`. With one thread
+/// or a small tree this is `style_tree`.
+pub fn style_tree_parallel<'a>(
+    root: &'a Node,
+    stylesheets: &[&Stylesheet],
+    media: MediaEnvironment,
+    threads: usize,
+) -> StyledNode<'a> {
+    let split_depth = split_depth(root, threads * TASKS_PER_THREAD);
+    if threads <= 1 || split_depth == 0 {
+        return style_tree(root, stylesheets, media);
+    }
+    let sheets = cascade_sheets(stylesheets, &media);
+    let hazards = sharing_hazards(&sheets);
+    let descendant_states = DescendantStates::new(root);
+    let new_context = || StyleContext {
+        sheets: &sheets,
+        ancestors: CountingBloomFilter::default(),
+        descendant_states: &descendant_states,
+        share_styles: !hazards.structural,
+        check_descendant_states: hazards.descendant_states,
+        statistics: StyleSharingStatistics::default(),
+    };
+    let parent_style = ParentStyle {
+        style: None,
+        context: LengthContext {
+            viewport_width: media.width,
+            viewport_height: media.height,
+            ..Default::default()
+        },
+    };
+
+    let mut tasks = Vec::new();
+    let top = style_top(
+        TopNode {
+            node: root,
+            parent: None,
+            index: 0,
+        },
+        &parent_style,
+        &mut new_context(),
+        &mut Vec::new(),
+        &mut TopSplit {
+            depth: split_depth,
+            path: Vec::new(),
+            tasks: &mut tasks,
+        },
+    );
+
+    // これより大きな部分木は、スレッドが取ったときに子の部分木に分け直す
+    let max_task_size = (count_nodes(root) / (threads * TASKS_PER_THREAD)).max(1);
+    let queue = Mutex::new(TaskQueue {
+        tasks,
+        next: 0,
+        running: 0,
+    });
+    let root_element = ElementRef {
+        node: root,
+        parent: None,
+        index: 0,
+        descendant_states: &descendant_states,
+    };
+    let styled: Vec<(usize, TopStyle)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut styled = Vec::new();
+                    while let Some((index, task)) = take_task(&queue) {
+                        let mut split = Vec::new();
+                        let mut result = style_task(
+                            root,
+                            &root_element,
+                            &task,
+                            &mut new_context(),
+                            max_task_size,
+                            &mut split,
+                        );
+                        let mut queue = queue.lock().unwrap();
+                        result.offset_tasks(queue.tasks.len());
+                        queue.tasks.extend(split);
+                        queue.running -= 1;
+                        styled.push((index, result));
+                    }
+                    styled
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    let mut results: Vec<Option<TopStyle>> = (0..styled.len()).map(|_| None).collect();
+    for (index, result) in styled {
+        results[index] = Some(result);
+    }
+
+    let mut styled_root = assemble(top, &mut results);
+    resolve_generated_content(&mut styled_root);
+    styled_root
+}
+
+// 各深さのノード数を数え、タスクの数がtasks以上になる最初の深さを返す
+// どの深さでも足りなければ、最もノードの多い深さで分ける
+fn split_depth(root: &Node, tasks: usize) -> usize {
+    let mut level = vec![root];
+    let mut best = (1, 0);
+    for depth in 0.. {
+        if level.len() >= tasks {
+            return depth;
+        }
+        if level.len() > best.0 {
+            best = (level.len(), depth);
+        }
+        level = level.iter().flat_map(|node| node.children.iter()).collect();
+        if level.is_empty() {
+            break;
+        }
+    }
+    best.1
+}
+
+// 子を分けて計算した要素、計算し終えた部分木、またはスレッドで計算する部分木
+enum TopStyle<'a> {
+    Styled(Box<StyledNode<'a>>, Vec<TopStyle<'a>>),
+    Subtree(Box<StyledNode<'a>>),
+    Task(usize),
+}
+
+impl TopStyle<'_> {
+    // 別に番号をつけたタスクを、キューでの番号に直す
+    fn offset_tasks(&mut self, offset: usize) {
+        match *self {
+            TopStyle::Styled(_, ref mut children) => {
+                for child in children {
+                    child.offset_tasks(offset);
+                }
+            }
+            TopStyle::Subtree(_) => {}
+            TopStyle::Task(ref mut index) => *index += offset,
+        }
+    }
+}
+
+// スレッドが取っていくタスク
+struct TaskQueue {
+    tasks: Vec<StyleTask>,
+    // 次に取るタスクの番号
+    next: usize,
+    // 計算中のタスクの数。計算中のタスクがキューに戻すことがあるので、0になるまでは終わらない
+    running: usize,
+}
+
+// 次のタスクを取る。キューが空でも計算中のタスクがあれば、戻されるのを待つ
+fn take_task(queue: &Mutex<TaskQueue>) -> Option<(usize, StyleTask)> {
+    loop {
+        {
+            let mut queue = queue.lock().unwrap();
+            let index = queue.next;
+            if let Some(task) = queue.tasks.get(index).cloned() {
+                queue.next += 1;
+                queue.running += 1;
+                return Some((index, task));
+            }
+            if queue.running == 0 {
+                return None;
+            }
+        }
+        thread::yield_now();
+    }
+}
+
+fn count_nodes(node: &Node) -> usize {
+    1 + node.children.iter().map(count_nodes).sum::<usize>()
+}
+
+// limitより多くのノードを含む部分木か（limitを超えたら数えるのをやめる）
+fn larger_than(node: &Node, limit: usize) -> bool {
+    let mut count = 0;
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        count += 1;
+        if count > limit {
+            return true;
+        }
+        stack.extend(node.children.iter());
+    }
+    false
+}
+
+// スレッドで計算する部分木
+#[derive(Clone)]
+struct StyleTask {
+    // ルートからの子の番号の列
+    path: Vec<usize>,
+    parent_style: Option<ComputedStyle>,
+    parent_context: LengthContext,
+}
+
+struct TopNode<'a, 'p> {
+    node: &'a Node,
+    parent: Option<&'p ElementRef<'p>>,
+    index: usize,
+}
+
+struct TopSplit<'t> {
+    depth: usize,
+    path: Vec<usize>,
+    tasks: &'t mut Vec<StyleTask>,
+}
+
+fn style_top<'a>(
+    top: TopNode<'a, '_>,
+    parent_style: &ParentStyle,
+    context: &mut StyleContext,
+    sharing_cache: &mut Vec<SharedStyle<'a>>,
+    split: &mut TopSplit,
+) -> TopStyle<'a> {
+    if split.path.len() == split.depth {
+        split.tasks.push(StyleTask {
+            path: split.path.clone(),
+            parent_style: parent_style.style.cloned(),
+            parent_context: parent_style.context,
+        });
+        return TopStyle::Task(split.tasks.len() - 1);
+    }
+    let element = ElementRef {
+        node: top.node,
+        parent: top.parent,
+        index: top.index,
+        descendant_states: context.descendant_states,
+    };
+    let (styled, length_context) =
+        style_element(top.node, &element, parent_style, context, sharing_cache);
+    let element_style = ParentStyle {
+        style: Some(&styled.style),
+        context: length_context,
+    };
+    let hashes = element_hashes(&element);
+    for &hash in &hashes {
+        context.ancestors.insert_hash(hash);
+    }
+    let mut children_cache = Vec::new();
+    let mut children = Vec::new();
+    for (index, child) in top.node.children.iter().enumerate() {
+        split.path.push(index);
+        let child = TopNode {
+            node: child,
+            parent: Some(&element),
+            index,
+        };
+        children.push(style_top(
+            child,
+            &element_style,
+            context,
+            &mut children_cache,
+            split,
+        ));
+        split.path.pop();
+    }
+    for &hash in &hashes {
+        context.ancestors.remove_hash(hash);
+    }
+    TopStyle::Styled(Box::new(styled), children)
+}
+
+// 部分木を計算する。max_sizeより大きな部分木はルートの要素だけを計算し、子の部分木をtasksに入れる
+fn style_task<'a>(
+    root: &'a Node,
+    root_element: &ElementRef,
+    task: &StyleTask,
+    context: &mut StyleContext,
+    max_size: usize,
+    tasks: &mut Vec<StyleTask>,
+) -> TopStyle<'a> {
+    let (&index, parent_path) = task.path.split_last().unwrap();
+    let parent_node = parent_path
         .iter()
-        .filter_map(|rule| match_rule(elem, rule))
-        .collect()
+        .fold(root, |node, &index| &node.children[index]);
+    let node = &parent_node.children[index];
+    let parent_style = ParentStyle {
+        style: task.parent_style.as_ref(),
+        context: task.parent_context,
+    };
+    with_descendant(root_element, parent_path, &mut |parent| {
+        for element in std::iter::once(parent).chain(parent.ancestors()) {
+            for hash in element_hashes(element) {
+                context.ancestors.insert_hash(hash);
+            }
+        }
+        if larger_than(node, max_size) {
+            let top = TopNode {
+                node,
+                parent: Some(parent),
+                index,
+            };
+            let mut split = TopSplit {
+                depth: task.path.len() + 1,
+                path: task.path.clone(),
+                tasks,
+            };
+            style_top(top, &parent_style, context, &mut Vec::new(), &mut split)
+        } else {
+            TopStyle::Subtree(Box::new(style_node(
+                node,
+                Some(parent),
+                index,
+                &parent_style,
+                context,
+                &mut Vec::new(),
+            )))
+        }
+    })
+}
+
+// pathの子の番号をたどった子孫要素で、親への参照を持つElementRefを作ってfを呼ぶ
+fn with_descendant<T>(
+    element: &ElementRef,
+    path: &[usize],
+    f: &mut dyn FnMut(&ElementRef) -> T,
+) -> T {
+    match path.split_first() {
+        Some((&index, rest)) => with_descendant(&element.child(index), rest, f),
+        None => f(element),
+    }
+}
+
+fn assemble<'a>(top: TopStyle<'a>, results: &mut [Option<TopStyle<'a>>]) -> StyledNode<'a> {
+    match top {
+        TopStyle::Styled(styled, children) => {
+            let mut styled = *styled;
+            styled.children = children
+                .into_iter()
+                .map(|child| assemble(child, results))
+                .collect();
+            styled
+        }
+        TopStyle::Subtree(styled) => *styled,
+        TopStyle::Task(index) => {
+            let task = results[index].take().unwrap();
+            assemble(task, results)
+        }
+    }
+}
+
+/*
+ 差分スタイル計算
+ DOMの変更は各ノードのdirtyに記録される。前回のスタイルと並べて木をたどり、変わったid・クラス・属性・状態を
+ 参照するセレクターでの位置から、スタイルを計算し直す要素に印（リスタイルヒント）をつける
+ スタイルシートの変更では、前回と異なるルールのセレクターにマッチしうる要素に印をつける
+ 計算し直した要素の計算値が変わらなければ、子は前回のスタイルをそのまま使う
+ https://wiki.mozilla.org/Servo/StyleUpdateOnDOMChange
+*/
+
+/// Styles kept between restyles, so that after changes to the DOM or to the
+/// stylesheets only the elements the changes may affect are styled again.
+/// DOM changes must be made through the methods of `Node` to be noticed.
+#[derive(Default)]
+pub struct StyleCache {
+    stylesheets: Vec<Stylesheet>,
+    media: Option<MediaEnvironment>,
+    // スタイルシートとメディア環境が変わらない間は、以下を作り直さずに使う
+    sheets: Vec<CascadeSheet<'static>>,
+    hazards: SharingHazards,
+    invalidations: InvalidationMap,
+    root: Option<RetainedStyle>,
+}
+
+/// How many elements a restyle styled again and how many kept their previous style.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RestyleStatistics {
+    pub restyled: usize,
+    pub reused: usize,
+}
+
+impl StyleCache {
+    /// Styles `root` like `style_tree` and clears the changes recorded on its
+    /// nodes. The first call styles every element, as does a change of `media`;
+    /// later calls style only the elements that changed since the previous call,
+    /// or that depend on changed elements or stylesheet rules.
+    pub fn restyle<'a>(
+        &mut self,
+        root: &'a mut Node,
+        stylesheets: &[&Stylesheet],
+        media: MediaEnvironment,
+    ) -> StyledNode<'a> {
+        self.restyle_with_statistics(root, stylesheets, media).0
+    }
+
+    /// `restyle`, also returning how many elements were styled again.
+    pub fn restyle_with_statistics<'a>(
+        &mut self,
+        root: &'a mut Node,
+        stylesheets: &[&Stylesheet],
+        media: MediaEnvironment,
+    ) -> (StyledNode<'a>, RestyleStatistics) {
+        let previous = self.root.take().filter(|_| self.media == Some(media));
+        let sheets_changed = previous.is_none()
+            || self.stylesheets.len() != stylesheets.len()
+            || self
+                .stylesheets
+                .iter()
+                .zip(stylesheets)
+                .any(|(old, &new)| old != new);
+        let mut descendant_states = DescendantStates::default();
+        let mut retained = match previous {
+            Some(mut previous) => {
+                let document = if sheets_changed {
+                    let sheets = cascade_sheets(stylesheets, &media);
+                    let hazards = sharing_hazards(&sheets);
+                    let changed = changed_rules(&self.sheets, &sheets);
+                    let mut changed_selectors = SelectorMap::default();
+                    for rule in &changed {
+                        for selector in &rule.selectors {
+                            changed_selectors.insert(selector, ());
+                        }
+                    }
+                    let mut invalidator = Invalidator {
+                        invalidations: &InvalidationMap::new(&sheets, &changed),
+                        changed_selectors: Some(&changed_selectors).filter(|_| !changed.is_empty()),
+                        descendant_states: Some(&mut descendant_states)
+                            .filter(|_| hazards.descendant_states),
+                    };
+                    let (invalidation, _) = invalidate(root, &mut previous, &mut invalidator);
+                    self.set_sheets(stylesheets, sheets, hazards);
+                    invalidation.document
+                } else {
+                    let mut invalidator = Invalidator {
+                        invalidations: &self.invalidations,
+                        changed_selectors: None,
+                        descendant_states: Some(&mut descendant_states)
+                            .filter(|_| self.hazards.descendant_states),
+                    };
+                    invalidate(root, &mut previous, &mut invalidator).0.document
+                };
+                if document {
+                    previous.hint = RestyleHint::Subtree;
+                }
+                previous
+            }
+            None => {
+                clear_dirty(root);
+                let sheets = cascade_sheets(stylesheets, &media);
+                let hazards = sharing_hazards(&sheets);
+                if hazards.descendant_states {
+                    descendant_states = DescendantStates::new(root);
+                }
+                self.set_sheets(stylesheets, sheets, hazards);
+                RetainedStyle::unstyled()
+            }
+        };
+        self.media = Some(media);
+
+        let root: &'a Node = root;
+        let mut context = StyleContext {
+            sheets: &self.sheets,
+            ancestors: CountingBloomFilter::default(),
+            descendant_states: &descendant_states,
+            share_styles: !self.hazards.structural,
+            check_descendant_states: self.hazards.descendant_states,
+            statistics: StyleSharingStatistics::default(),
+        };
+        let parent_style = ParentStyle {
+            style: None,
+            context: LengthContext {
+                viewport_width: media.width,
+                viewport_height: media.height,
+                ..Default::default()
+            },
+        };
+        let mut statistics = RestyleStatistics::default();
+        restyle_node(
+            RestyleTarget {
+                node: root,
+                parent: None,
+                index: 0,
+                forced: RestyleHint::None,
+            },
+            &mut retained,
+            &parent_style,
+            &mut context,
+            &mut Vec::new(),
+            &mut statistics,
+        );
+        let mut styled_root = styled_node(root, &retained);
+        self.root = Some(retained);
+        resolve_generated_content(&mut styled_root);
+        (styled_root, statistics)
+    }
+
+    // スタイルシートが変わったときだけ、カスケードの準備をし直す
+    // ルールは複製して持ち、次に呼ばれたときに前回のルールと比べる
+    fn set_sheets(
+        &mut self,
+        stylesheets: &[&Stylesheet],
+        sheets: Vec<CascadeSheet>,
+        hazards: SharingHazards,
+    ) {
+        self.sheets = sheets.into_iter().map(CascadeSheet::into_owned).collect();
+        self.invalidations = InvalidationMap::new(&self.sheets, &[]);
+        self.hazards = hazards;
+        self.stylesheets = stylesheets
+            .iter()
+            .map(|&stylesheet| stylesheet.clone())
+            .collect();
+    }
+}
+
+// 前回計算したスタイル。DOMを借用しないので、DOMを変更する間も持っておける
+// 計算値はStyledNodeと共有する
+struct RetainedStyle {
+    style: Arc<ComputedStyle>,
+    sources: Arc<SourceMap>,
+    // 子のスタイルの計算に使ったLengthContext
+    context: LengthContext,
+    before: Option<(Arc<ComputedStyle>, Arc<SourceMap>)>,
+    after: Option<(Arc<ComputedStyle>, Arc<SourceMap>)>,
+    children: Vec<RetainedStyle>,
+    hint: RestyleHint,
+    // 印のついた子孫がある
+    dirty_descendants: bool,
+    // 部分木の要素の数
+    size: usize,
+}
+
+impl RetainedStyle {
+    // まだスタイルを計算していない要素
+    fn unstyled() -> RetainedStyle {
+        RetainedStyle {
+            style: Arc::default(),
+            sources: Arc::default(),
+            context: LengthContext::default(),
+            before: None,
+            after: None,
+            children: Vec::new(),
+            hint: RestyleHint::Subtree,
+            dirty_descendants: false,
+            size: 1,
+        }
+    }
+
+    // 計算し直したスタイルに置き換え、計算値かLengthContextが変わったかを返す
+    // styledは子を含まない
+    fn update(&mut self, styled: StyledNode, context: LengthContext) -> bool {
+        let changed = self.style != styled.style || self.context != context;
+        let pseudo =
+            |pseudo: Option<Box<StyledNode>>| pseudo.map(|pseudo| (pseudo.style, pseudo.sources));
+        self.style = styled.style;
+        self.sources = styled.sources;
+        self.context = context;
+        self.before = pseudo(styled.before);
+        self.after = pseudo(styled.after);
+        changed
+    }
+
+    fn mark(&mut self, hint: RestyleHint) {
+        self.hint = self.hint.max(hint);
+    }
+}
+
+// 要素のスタイルをどこまで計算し直すか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum RestyleHint {
+    None,
+    Element,
+    // 要素とすべての子孫
+    Subtree,
+}
+
+// 変更された要素から見て、スタイルを計算し直す必要のある要素
+#[derive(Debug, Clone, Copy, Default)]
+struct Invalidation {
+    element: bool,
+    descendants: bool,
+    // 後続の兄弟要素とその子孫
+    later_siblings: bool,
+    // 祖先要素（子孫の状態が伝播する疑似クラス）
+    ancestors: bool,
+    document: bool,
+}
+
+impl Invalidation {
+    fn merge(&mut self, other: Invalidation) {
+        self.element |= other.element;
+        self.descendants |= other.descendants;
+        self.later_siblings |= other.later_siblings;
+        self.ancestors |= other.ancestors;
+        self.document |= other.document;
+    }
+}
+
+// id、クラス、属性、状態ごとに、それが変わったときに計算し直す範囲
+#[derive(Default)]
+struct InvalidationMap {
+    ids: HashMap<String, Invalidation>,
+    classes: HashMap<String, Invalidation>,
+    attributes: HashMap<&'static str, Invalidation>,
+    states: Vec<(ElementState, Invalidation)>,
+    // 子の追加や削除で祖先のマッチが変わりうる（:has()や、子孫の状態に依存する疑似クラス）
+    structural: bool,
+}
+
+impl InvalidationMap {
+    // 今のスタイルシートのルールに加え、前回だけにあったルールも変更前のマッチのために含める
+    fn new(sheets: &[CascadeSheet], changed_rules: &[&Rule]) -> InvalidationMap {
+        let mut map = InvalidationMap::default();
+        let subject = Invalidation {
+            element: true,
+            ..Default::default()
+        };
+        let rules = sheets
+            .iter()
+            .flat_map(|sheet| sheet.rules.iter().map(|(rule, _)| &**rule))
+            .chain(changed_rules.iter().cloned());
+        for rule in rules {
+            for selector in &rule.selectors {
+                map.insert_selector(selector, subject);
+            }
+        }
+        map
+    }
+
+    // position: 右端の複合セレクターにマッチする要素から見た範囲
+    fn insert_selector(&mut self, selector: &Selector, position: Invalidation) {
+        match *selector {
+            Selector::Simple(ref compound) => self.insert_compound(compound, position),
+            Selector::Complex(ref left, combinator, ref compound) => {
+                self.insert_compound(compound, position);
+                let mut left_position = Invalidation {
+                    later_siblings: position.later_siblings,
+                    document: position.document,
+                    ..Default::default()
+                };
+                match combinator {
+                    Combinator::Descendant | Combinator::Child => left_position.descendants = true,
+                    Combinator::NextSibling | Combinator::SubsequentSibling => {
+                        left_position.descendants = position.descendants;
+                        left_position.later_siblings = true;
+                    }
+                }
+                self.insert_selector(left, left_position);
+            }
+        }
+    }
+
+    fn insert_compound(&mut self, compound: &SimpleSelector, position: Invalidation) {
+        if let Some(ref id) = compound.id {
+            self.ids.entry(id.clone()).or_default().merge(position);
+        }
+        for class in &compound.class {
+            self.classes
+                .entry(class.clone())
+                .or_default()
+                .merge(position);
+        }
+        for pseudo_class in &compound.pseudo_classes {
+            match *pseudo_class {
+                PseudoClass::Not(ref selectors)
+                | PseudoClass::Is(ref selectors)
+                | PseudoClass::Where(ref selectors) => {
+                    for selector in selectors {
+                        self.insert_selector(selector, position);
+                    }
+                }
+                // 子孫や兄弟の変更がどの要素の:has()を変えるかは追わず、文書全体を計算し直す
+                PseudoClass::Has(ref relatives) => {
+                    self.structural = true;
+                    let document = Invalidation {
+                        document: true,
+                        ..Default::default()
+                    };
+                    for relative in relatives {
+                        self.insert_selector(&relative.selector, document);
+                    }
+                }
+                PseudoClass::Hover => self.insert_state(ElementState::HOVER, position, true),
+                PseudoClass::Active => self.insert_state(ElementState::ACTIVE, position, true),
+                PseudoClass::Focus | PseudoClass::FocusVisible => {
+                    self.insert_state(ElementState::FOCUS, position, false)
+                }
+                PseudoClass::FocusWithin => self.insert_state(
+                    ElementState::FOCUS | ElementState::FOCUS_WITHIN,
+                    position,
+                    true,
+                ),
+                PseudoClass::Checked => {
+                    self.insert_state(ElementState::CHECKED, position, false);
+                    self.insert_attribute("checked", position);
+                }
+                PseudoClass::Disabled | PseudoClass::Enabled => {
+                    self.insert_state(ElementState::DISABLED, position, false);
+                    self.insert_attribute("disabled", position);
+                }
+                PseudoClass::Link | PseudoClass::Visited | PseudoClass::AnyLink => {
+                    self.insert_state(ElementState::VISITED, position, false);
+                    self.insert_attribute("href", position);
+                }
+                PseudoClass::Target => self.insert_state(ElementState::TARGET, position, false),
+            }
+        }
+    }
+
+    // descendant_state: 子孫の状態が祖先に伝播する疑似クラス（:hoverなど）
+    fn insert_state(
+        &mut self,
+        state: ElementState,
+        mut position: Invalidation,
+        descendant_state: bool,
+    ) {
+        if descendant_state {
+            self.structural = true;
+            position.ancestors |= position.element;
+            // 祖先の子孫はどこまでも広がるので文書全体になる
+            position.document |= position.descendants || position.later_siblings;
+        }
+        match self.states.iter_mut().find(|&&mut (s, _)| s == state) {
+            Some(&mut (_, ref mut invalidation)) => invalidation.merge(position),
+            None => self.states.push((state, position)),
+        }
+    }
+
+    fn insert_attribute(&mut self, name: &'static str, position: Invalidation) {
+        self.attributes.entry(name).or_default().merge(position);
+    }
+
+    // 変更前の要素と今の要素の違いから、計算し直す範囲を求める
+    fn changes(&self, old: &ElementData, new: &ElementData) -> Invalidation {
+        let mut invalidation = Invalidation::default();
+        if old.id() != new.id() {
+            for id in old.id().into_iter().chain(new.id()) {
+                invalidation.merge(self.ids.get(id).cloned().unwrap_or_default());
+            }
+        }
+        for class in old.classes().symmetric_difference(&new.classes()) {
+            invalidation.merge(self.classes.get(*class).cloned().unwrap_or_default());
+        }
+        for (&name, &attribute_invalidation) in &self.attributes {
+            if old.attributes.get(name) != new.attributes.get(name) {
+                invalidation.merge(attribute_invalidation);
+            }
+        }
+        for &(state, state_invalidation) in &self.states {
+            if old.state.intersects(state) != new.state.intersects(state) {
+                invalidation.merge(state_invalidation);
+            }
+        }
+        invalidation
+    }
 }
 
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyledNode<'a> {
+/*
+ 前回と今回のスタイルシートで異なるルール（追加、削除、変更、移動されたもの）
+ スタイルシートごとに、先頭と末尾から同じルールを除いた残りを比べる
+ ユーザーエージェントのスタイルシート（先頭）は変わらないので比べない
+*/
+fn changed_rules<'r>(
+    previous: &'r [CascadeSheet<'_>],
+    current: &'r [CascadeSheet<'_>],
+) -> Vec<&'r Rule> {
+    let mut changed = Vec::new();
+    for index in 1..previous.len().max(current.len()) {
+        let (old_origin, old) = previous.get(index).map_or((None, &[][..]), |sheet| {
+            (Some(sheet.origin), &sheet.rules[..])
+        });
+        let (new_origin, new) = current.get(index).map_or((None, &[][..]), |sheet| {
+            (Some(sheet.origin), &sheet.rules[..])
+        });
+        // 起源が変わったら、すべてのルールの優先順位が変わる
+        // 位置も比べるので、位置だけが変わったルールも変わったものとして宣言の位置を記録し直す
+        let (prefix, suffix) = if old_origin == new_origin {
+            let prefix = old.iter().zip(new).take_while(|&(a, b)| a == b).count();
+            let suffix = old[prefix..]
+                .iter()
+                .rev()
+                .zip(new[prefix..].iter().rev())
+                .take_while(|&(a, b)| a == b)
+                .count();
+            (prefix, suffix)
+        } else {
+            (0, 0)
+        };
+        changed.extend(
+            old[prefix..old.len() - suffix]
+                .iter()
+                .chain(&new[prefix..new.len() - suffix])
+                .map(|(rule, _)| &**rule),
+        );
+    }
+    changed
+}
+
+// 前回のスタイルに印をつける間の状態
+struct Invalidator<'i> {
+    invalidations: &'i InvalidationMap,
+    // スタイルシートで変わったルールのセレクター
+    changed_selectors: Option<&'i SelectorMap<()>>,
+    // 子孫の状態に依存する疑似クラスがあるときだけ、木をたどるついでに子孫の状態を集める
+    descendant_states: Option<&'i mut DescendantStates>,
+}
+
+/*
+ DOMとスタイルシートの変更から前回のスタイルに印をつけ、ノードのdirtyを消す
+ 印のついた子孫がある要素も記録し、スタイルを計算し直すときはそこだけをたどる
+ 戻り値は親に伝える祖先や兄弟に及ぶ範囲と、ノードとその子孫の状態の和集合
+*/
+fn invalidate(
+    node: &mut Node,
+    retained: &mut RetainedStyle,
+    invalidator: &mut Invalidator,
+) -> (Invalidation, ElementState) {
+    let dirty = std::mem::take(&mut node.dirty);
+    let mut invalidation = Invalidation::default();
+    let mut states = ElementState::empty();
+    if let NodeType::Element(ref elem) = node.node_type {
+        states = elem.state;
+        if let Some(snapshot) = dirty.snapshot {
+            invalidation = invalidator.invalidations.changes(&snapshot, elem);
+        }
+        if let Some(changed_selectors) = invalidator.changed_selectors {
+            let id = elem.id().map(|id| id.as_str());
+            let candidates = changed_selectors.candidates(&elem.tag_name, id, elem.classes());
+            invalidation.element |= !candidates.is_empty();
+        }
+    }
+    if invalidation.element {
+        retained.mark(RestyleHint::Element);
+    }
+    if invalidation.descendants {
+        retained.mark(RestyleHint::Subtree);
+    }
+    let mut descendants = ElementState::empty();
+    // 子が入れ替わったら、子のスタイルは前回と対応しない
+    if dirty.children || node.children.len() != retained.children.len() {
+        retained.mark(RestyleHint::Subtree);
+        invalidation.document |= invalidator.invalidations.structural;
+        for child in node.children.iter_mut() {
+            clear_dirty(child);
+            if let Some(ref mut descendant_states) = invalidator.descendant_states {
+                descendants |= descendant_states.collect(child);
+            }
+        }
+    } else {
+        let mut later_siblings = None;
+        let mut descendant_state_changed = false;
+        for (index, (child, retained_child)) in node
+            .children
+            .iter_mut()
+            .zip(retained.children.iter_mut())
+            .enumerate()
+        {
+            let (child_invalidation, child_states) = invalidate(child, retained_child, invalidator);
+            if child_invalidation.later_siblings && later_siblings.is_none() {
+                later_siblings = Some(index + 1);
+            }
+            descendant_state_changed |= child_invalidation.ancestors;
+            invalidation.document |= child_invalidation.document;
+            descendants |= child_states;
+        }
+        if descendant_state_changed {
+            retained.mark(RestyleHint::Element);
+            invalidation.ancestors = true;
+        }
+        if let Some(first) = later_siblings {
+            for sibling in retained.children[first..].iter_mut() {
+                sibling.mark(RestyleHint::Subtree);
+            }
+        }
+        retained.dirty_descendants = retained
+            .children
+            .iter()
+            .any(|child| child.hint != RestyleHint::None || child.dirty_descendants);
+    }
+    if let Some(ref mut descendant_states) = invalidator.descendant_states {
+        descendant_states.insert(node, descendants);
+    }
+    (invalidation, states | descendants)
+}
+
+fn clear_dirty(node: &mut Node) {
+    node.dirty = Default::default();
+    for child in node.children.iter_mut() {
+        clear_dirty(child);
+    }
+}
+
+struct RestyleTarget<'a, 'p> {
+    node: &'a Node,
+    parent: Option<&'p ElementRef<'p>>,
+    index: usize,
+    // 祖先から強制される計算し直し（親の計算値が変わったときはElement）
+    forced: RestyleHint,
+}
+
+// 保持しているスタイルをその場で更新する。印がなく、印のついた子孫もない部分木はたどらない
+fn restyle_node<'a>(
+    target: RestyleTarget<'a, '_>,
+    retained: &mut RetainedStyle,
+    parent_style: &ParentStyle,
+    context: &mut StyleContext,
+    sharing_cache: &mut Vec<SharedStyle<'a>>,
+    statistics: &mut RestyleStatistics,
+) {
+    let hint = retained.hint.max(target.forced);
+    if hint == RestyleHint::None && !retained.dirty_descendants {
+        statistics.reused += retained.size;
+        return;
+    }
+    let node = target.node;
+    let element = ElementRef {
+        node,
+        parent: target.parent,
+        index: target.index,
+        descendant_states: context.descendant_states,
+    };
+    let changed = if hint == RestyleHint::None {
+        statistics.reused += 1;
+        false
+    } else {
+        statistics.restyled += 1;
+        let (styled, length_context) =
+            style_element(node, &element, parent_style, context, sharing_cache);
+        retained.update(styled, length_context)
+    };
+    let forced = if hint == RestyleHint::Subtree {
+        RestyleHint::Subtree
+    } else if changed {
+        RestyleHint::Element
+    } else {
+        RestyleHint::None
+    };
+    retained.hint = RestyleHint::None;
+    retained.dirty_descendants = false;
+    // 子が入れ替わった要素は部分木ごと計算し直すので、前回の子とは位置で対応させるだけでよい
+    retained
+        .children
+        .resize_with(node.children.len(), RetainedStyle::unstyled);
+
+    let RetainedStyle {
+        ref style,
+        context: length_context,
+        ref mut children,
+        ..
+    } = *retained;
+    let element_style = ParentStyle {
+        style: Some(style),
+        context: length_context,
+    };
+    let hashes = element_hashes(&element);
+    for &hash in &hashes {
+        context.ancestors.insert_hash(hash);
+    }
+    let mut children_cache = Vec::new();
+    for (index, (child, retained_child)) in node.children.iter().zip(children).enumerate() {
+        let child = RestyleTarget {
+            node: child,
+            parent: Some(&element),
+            index,
+            forced,
+        };
+        restyle_node(
+            child,
+            retained_child,
+            &element_style,
+            context,
+            &mut children_cache,
+            statistics,
+        );
+    }
+    for &hash in &hashes {
+        context.ancestors.remove_hash(hash);
+    }
+    retained.size = 1 + retained
+        .children
+        .iter()
+        .map(|child| child.size)
+        .sum::<usize>();
+}
+
+// 保持しているスタイルを共有してStyledNodeの木を作る（生成コンテンツは後で解決する）
+fn styled_node<'a>(node: &'a Node, retained: &RetainedStyle) -> StyledNode<'a> {
+    let pseudo = |pseudo_element, pseudo: &Option<(Arc<ComputedStyle>, Arc<SourceMap>)>| {
+        pseudo.as_ref().map(|(style, sources)| {
+            Box::new(StyledNode {
+                node,
+                style: style.clone(),
+                children: Vec::new(),
+                pseudo_element: Some(pseudo_element),
+                before: None,
+                after: None,
+                generated_content: None,
+                sources: sources.clone(),
+            })
+        })
+    };
     StyledNode {
-        node: root,
-        css_properties: match root.node_type {
-            NodeType::Element(ref elem) => parse_css_property(elem, stylesheet),
-            NodeType::Text(_) => HashMap::new(),
-        },
-        children: root
+        node,
+        style: retained.style.clone(),
+        children: node
             .children
             .iter()
-            .map(|child| style_tree(child, stylesheet))
+            .zip(&retained.children)
+            .map(|(child, retained)| styled_node(child, retained))
             .collect(),
+        pseudo_element: None,
+        before: pseudo(PseudoElement::Before, &retained.before),
+        after: pseudo(PseudoElement::After, &retained.after),
+        generated_content: None,
+        sources: retained.sources.clone(),
+    }
+}
+
+// contentがnone/normal以外の場合のみ疑似要素のボックスを生成する
+fn style_pseudo_element<'a>(
+    node: &'a Node,
+    element: &ElementRef,
+    sheets: &[CascadeSheet],
+    element_style: &ParentStyle,
+    pseudo_element: PseudoElement,
+    ancestors: &CountingBloomFilter,
+) -> Option<Box<StyledNode<'a>>> {
+    element.data()?;
+    // その疑似要素のセレクターがどのシートにもなければ、ルールを探すまでもない
+    if !sheets
+        .iter()
+        .any(|sheet| sheet.pseudo_elements.contains(&pseudo_element))
+    {
+        return None;
+    }
+    let (css_properties, sources) =
+        parse_css_property(element, sheets, Some(pseudo_element), ancestors);
+    match css_properties.get("content") {
+        None => return None,
+        Some(Value::Keyword(keyword)) if keyword == "none" || keyword == "normal" => return None,
+        _ => {}
     }
+    let (style, _) = compute_values(css_properties, element_style, false);
+    Some(Box::new(StyledNode {
+        node,
+        style: Arc::new(style),
+        children: Vec::new(),
+        pseudo_element: Some(pseudo_element),
+        before: None,
+        after: None,
+        generated_content: None,
+        sources: Arc::new(sources),
+    }))
 }
 
-fn parse_css_property(elem: &ElementData, stylesheet: &Stylesheet) -> CSSPropertyMap {
+// カスケードで並べ替える宣言
+struct CascadedDeclaration<'a> {
+    rank: u8,
+    layer: &'a LayerKey,
+    specificity: Specificity,
+    order: (usize, usize),
+    // ルール内での宣言の順番
+    index: usize,
+    declaration: &'a Declaration,
+}
+
+impl<'a> CascadedDeclaration<'a> {
+    // !importantな宣言ではレイヤーの優先順位が逆転する
+    fn cmp(&self, other: &CascadedDeclaration) -> Ordering {
+        let important = self.declaration.important;
+        self.rank
+            .cmp(&other.rank)
+            .then_with(|| match self.layer.cmp(other.layer) {
+                ordering if important => ordering.reverse(),
+                ordering => ordering,
+            })
+            .then(self.specificity.cmp(&other.specificity))
+            .then(self.order.cmp(&other.order))
+            .then(self.index.cmp(&other.index))
+    }
+}
+
+// マッチしたルールの宣言を、優先度の低いものから順に並べる
+fn cascade_order<'a>(rules: &[MatchedRule<'a>]) -> Vec<CascadedDeclaration<'a>> {
+    let mut declarations: Vec<_> = rules
+        .iter()
+        .flat_map(|matched| {
+            matched
+                .rule
+                .declarations
+                .iter()
+                .enumerate()
+                .map(move |(index, declaration)| CascadedDeclaration {
+                    rank: cascade_rank(matched.origin, declaration.important),
+                    layer: matched.layer,
+                    specificity: matched.specificity,
+                    order: matched.order,
+                    index,
+                    declaration,
+                })
+        })
+        .collect();
+    declarations.sort_by(|a, b| a.cmp(b));
+    declarations
+}
+
+// オリジンと重要度、レイヤー、詳細度、出現順の順に優先度の低い宣言から適用する
+fn parse_css_property(
+    element: &ElementRef,
+    sheets: &[CascadeSheet],
+    pseudo_element: Option<PseudoElement>,
+    ancestors: &CountingBloomFilter,
+) -> (CSSPropertyMap, SourceMap) {
     let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+    let mut sources = HashMap::new();
+    let rules = matching_rules(element, sheets, pseudo_element, ancestors);
+    for (name, winner) in cascade_winners(&cascade_order(&rules), sheets) {
+        if let Some(declaration) = winner.value {
+            values.insert(name.to_string(), declaration.value.clone());
+            sources.insert(name.to_string(), declaration.location.clone());
+        }
+    }
+    (values, sources)
+}
+
+// プロパティごとの、カスケードで最後に来た宣言と、revertを解決して値を与える宣言
+struct CascadeWinner<'a> {
+    declaration: &'a Declaration,
+    // 戻す先がなければunsetと同じになり、値を与える宣言はない
+    value: Option<&'a Declaration>,
+}
 
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    for (_, rule) in rules {
-        for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+// cascade_orderで並べた宣言から、プロパティごとの勝者を決める
+fn cascade_winners<'a>(
+    declarations: &[CascadedDeclaration<'a>],
+    sheets: &[CascadeSheet],
+) -> HashMap<&'a str, CascadeWinner<'a>> {
+    let mut candidates: HashMap<&str, Vec<&CascadedDeclaration>> = HashMap::new();
+    for cascaded in declarations {
+        candidates
+            .entry(&cascaded.declaration.name)
+            .or_default()
+            .push(cascaded);
+    }
+    candidates
+        .into_iter()
+        .map(|(name, candidates)| {
+            let declaration = candidates.last().unwrap().declaration;
+            let value = cascaded_value(candidates, sheets);
+            (name, CascadeWinner { declaration, value })
+        })
+        .collect()
+}
+
+/*
+ 一つのプロパティの宣言（優先度の低い順）から、revertを解決して値を与える宣言を選ぶ
+ revertは、それより優先度の低い宣言から同じオリジンの宣言を通常・!importantとも除いてカスケードし直す
+ 優先度は(オリジン, 重要度)で決まるので、!importantなユーザーの宣言のrevertには作者の宣言も残る
+ revert-layerは、同じオリジン・重要度の同じレイヤーの宣言を除く
+*/
+fn cascaded_value<'a>(
+    mut candidates: Vec<&CascadedDeclaration<'a>>,
+    sheets: &[CascadeSheet],
+) -> Option<&'a Declaration> {
+    loop {
+        let last = candidates.pop()?;
+        match revert_kind(&last.declaration.value) {
+            None => return Some(last.declaration),
+            Some("revert") => {
+                let origin = sheets[last.order.0].origin;
+                candidates.retain(|cascaded| sheets[cascaded.order.0].origin != origin);
+            }
+            Some(_) => candidates
+                .retain(|cascaded| (cascaded.rank, cascaded.layer) != (last.rank, last.layer)),
         }
     }
-    return values;
+}
+
+/// A rule that matched an element, as listed by `explain_style`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleExplanation {
+    /// The matching selector of the rule with the highest specificity.
+    pub selector: Selector,
+    /// `::before` or `::after` for rules that style a pseudo-element of the node.
+    pub pseudo_element: Option<PseudoElement>,
+    pub specificity: Specificity,
+    pub origin: Origin,
+    pub layer: LayerKey,
+    pub declarations: Vec<DeclarationExplanation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeclarationExplanation {
+    pub declaration: Declaration,
+    pub status: DeclarationStatus,
+}
+
+/// Whether a declaration of a matched rule won the cascade for its property.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeclarationStatus {
+    Applied,
+    /// Lost to the given declaration of a rule with higher precedence.
+    Overridden(Declaration),
+    /// Won with `revert` or `revert-layer`, so the property takes the value of
+    /// the given declaration from an earlier origin or layer, or is unset if
+    /// there is none.
+    Reverted(Option<Declaration>),
+}
+
+/// Lists the rules that match `node`, an element in the tree at `root`, from the
+/// highest precedence to the lowest (like the styles pane of developer tools),
+/// with whether each declaration applied or which declaration overrode it.
+/// Rules for the node's `::before` and `::after` follow its own rules.
+/// Returns nothing if `node` is not an element of the tree.
+pub fn explain_style(
+    root: &Node,
+    node: &Node,
+    stylesheets: &[&Stylesheet],
+    media: MediaEnvironment,
+) -> Vec<RuleExplanation> {
+    let sheets = cascade_sheets(stylesheets, &media);
+    let descendant_states = DescendantStates::new(root);
+    let root = ElementRef {
+        node: root,
+        parent: None,
+        index: 0,
+        descendant_states: &descendant_states,
+    };
+    find_element(&root, node, &mut |element| {
+        explain_element(element, &sheets)
+    })
+    .unwrap_or_default()
+}
+
+fn explain_element(element: &ElementRef, sheets: &[CascadeSheet]) -> Vec<RuleExplanation> {
+    let mut ancestors = CountingBloomFilter::default();
+    for ancestor in element.ancestors() {
+        for hash in element_hashes(ancestor) {
+            ancestors.insert_hash(hash);
+        }
+    }
+    // 疑似要素は要素とは別にカスケードされる
+    [
+        None,
+        Some(PseudoElement::Before),
+        Some(PseudoElement::After),
+    ]
+    .iter()
+    .flat_map(|&pseudo_element| explain_rules(element, sheets, pseudo_element, &ancestors))
+    .collect()
+}
+
+fn explain_rules(
+    element: &ElementRef,
+    sheets: &[CascadeSheet],
+    pseudo_element: Option<PseudoElement>,
+    ancestors: &CountingBloomFilter,
+) -> Vec<RuleExplanation> {
+    let mut rules = matching_rules(element, sheets, pseudo_element, ancestors);
+    let winners = cascade_winners(&cascade_order(&rules), sheets);
+    rules.sort_by(|a, b| {
+        let key = |matched: &MatchedRule| {
+            (
+                cascade_rank(matched.origin, false),
+                matched.layer.clone(),
+                matched.specificity,
+                matched.order,
+            )
+        };
+        key(b).cmp(&key(a))
+    });
+    rules
+        .iter()
+        .map(|matched| RuleExplanation {
+            selector: matched.selector.clone(),
+            pseudo_element,
+            specificity: matched.specificity,
+            origin: matched.origin,
+            layer: matched.layer.clone(),
+            declarations: matched
+                .rule
+                .declarations
+                .iter()
+                .map(|declaration| {
+                    let winner = &winners[&*declaration.name];
+                    let status = if winner
+                        .value
+                        .is_some_and(|value| std::ptr::eq(value, declaration))
+                    {
+                        DeclarationStatus::Applied
+                    } else if std::ptr::eq(winner.declaration, declaration) {
+                        DeclarationStatus::Reverted(winner.value.cloned())
+                    } else {
+                        DeclarationStatus::Overridden(winner.declaration.clone())
+                    };
+                    DeclarationExplanation {
+                        declaration: declaration.clone(),
+                        status,
+                    }
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+// 祖先のフィルターに入れる、要素のid、クラス、タグ名のハッシュ
+fn element_hashes(element: &ElementRef) -> Vec<u32> {
+    let elem = match element.data() {
+        Some(elem) => elem,
+        None => return Vec::new(),
+    };
+    let mut hashes = vec![bloom_filter::tag_hash(&elem.tag_name)];
+    hashes.extend(elem.id().map(|id| bloom_filter::id_hash(id)));
+    hashes.extend(
+        elem.classes()
+            .into_iter()
+            .filter(|class| !class.is_empty())
+            .map(bloom_filter::class_hash),
+    );
+    hashes
+}
+
+// 木の中からtargetの要素を探し、親への参照を持つElementRefでfを呼ぶ
+fn find_element<T>(
+    element: &ElementRef,
+    target: &Node,
+    f: &mut dyn FnMut(&ElementRef) -> T,
+) -> Option<T> {
+    element.data()?;
+    if std::ptr::eq(element.node, target) {
+        return Some(f(element));
+    }
+    (0..element.node.children.len())
+        .find_map(|index| find_element(&element.child(index), target, f))
+}
+
+fn revert_kind(value: &Value) -> Option<&str> {
+    match *value {
+        Value::Keyword(ref keyword) if keyword == "revert" || keyword == "revert-layer" => {
+            Some(keyword)
+        }
+        _ => None,
+    }
+}
+
+// 計算値の解決に使う親要素（疑似要素の場合は生成元の要素）の情報
+struct ParentStyle<'p> {
+    style: Option<&'p ComputedStyle>,
+    context: LengthContext,
+}
+
+// 色を値に取るプロパティ
+const COLOR_PROPERTIES: [&str; 11] = [
+    "color",
+    "background-color",
+    "border-top-color",
+    "border-right-color",
+    "border-bottom-color",
+    "border-left-color",
+    "outline-color",
+    "text-decoration-color",
+    "column-rule-color",
+    "caret-color",
+    "fill",
+];
+
+// 指定値を計算値にし、この要素の計算値とLengthContextを返す
+fn compute_values(
+    mut css_properties: CSSPropertyMap,
+    parent_style: &ParentStyle,
+    is_root: bool,
+) -> (ComputedStyle, LengthContext) {
+    let removed = resolve_custom_properties(&mut css_properties, parent_style.style);
+    // カスタムプロパティを宣言していない要素では、var()は親要素から継承した値を参照する
+    let inherited = parent_style.style.filter(|_| removed.is_none());
+    substitute_variables(&mut css_properties, inherited);
+    resolve_inheritance(&mut css_properties, parent_style.style);
+    let context = compute_lengths(&mut css_properties, &parent_style.context, is_root);
+    compute_colors(&mut css_properties, parent_style.style);
+    let mut style = ComputedStyle::from_properties(css_properties, parent_style.style);
+    for name in removed.iter().flatten() {
+        style.remove_inherited(name);
+    }
+    (style, context)
+}
+
+/*
+ カスタムプロパティの計算値を求める
+ カスタムプロパティは常に継承され、initialと循環参照は無効な値（プロパティなし）になる
+ 宣言がなければ親要素の値をそのまま共有するのでNoneを返し、
+ あればすべての値を設定して、親要素にあってこの要素で無効になった名前を返す
+*/
+fn resolve_custom_properties(
+    css_properties: &mut CSSPropertyMap,
+    parent_style: Option<&ComputedStyle>,
+) -> Option<Vec<String>> {
+    let names: Vec<String> = css_properties
+        .keys()
+        .filter(|name| is_custom_property(name))
+        .cloned()
+        .collect();
+    if names.is_empty() {
+        return None;
+    }
+    let mut raw: HashMap<String, String> = HashMap::new();
+    if let Some(parent) = parent_style {
+        for (name, value) in parent.inherited.iter() {
+            if let Value::Unparsed { ref css, .. } = *value {
+                if is_custom_property(name) {
+                    raw.insert(name.clone(), css.clone());
+                }
+            }
+        }
+    }
+    let inherited: Vec<String> = raw.keys().cloned().collect();
+    for name in names {
+        match css_properties.remove(&name) {
+            Some(Value::Unparsed { css, .. }) => {
+                raw.insert(name, css);
+            }
+            Some(Value::Keyword(ref keyword)) if keyword == "initial" => {
+                raw.remove(&name);
+            }
+            // inherit, unsetは親要素の値をそのまま使う
+            _ => {}
+        }
+    }
+    for (name, css) in variable::resolve_custom_properties(&raw) {
+        css_properties.insert(
+            name,
+            Value::Unparsed {
+                css,
+                shorthand: None,
+            },
+        );
+    }
+    Some(
+        inherited
+            .into_iter()
+            .filter(|name| !css_properties.contains_key(name))
+            .collect(),
+    )
+}
+
+/*
+ var()を含むプロパティの値を置換してからパースする
+ 置換できない場合は計算値の時点で無効となり、unsetと同じになる
+*/
+fn substitute_variables(css_properties: &mut CSSPropertyMap, inherited: Option<&ComputedStyle>) {
+    let pending: Vec<(String, String, Option<String>)> = css_properties
+        .iter()
+        .filter(|&(name, _)| !is_custom_property(name))
+        .filter_map(|(name, value)| match *value {
+            Value::Unparsed {
+                ref css,
+                ref shorthand,
+            } => Some((name.clone(), css.clone(), shorthand.clone())),
+            _ => None,
+        })
+        .collect();
+    for (name, css, shorthand) in pending {
+        let mut lookup = |reference: &str| {
+            let value = match inherited {
+                Some(style) => style.inherited.get(reference),
+                None => css_properties.get(reference),
+            };
+            match value {
+                Some(Value::Unparsed { css, .. }) => Some(css.clone()),
+                _ => None,
+            }
+        };
+        let value = variable::substitute(&css, &mut lookup)
+            .and_then(|css| {
+                // 置換した結果が値として読めなければ、var()が無効な場合と同じ
+                let mut parser = new_css_parser(css);
+                let value = parser.parse_value_list().ok();
+                value.filter(|_| parser.eof())
+            })
+            .and_then(|value| match shorthand {
+                // ショートハンドを展開し直して、このロングハンドの値を取り出す
+                Some(shorthand) => expand_shorthand(Declaration {
+                    name: shorthand,
+                    value,
+                    important: false,
+                    location: Default::default(),
+                })
+                .into_iter()
+                .find(|declaration| declaration.name == name)
+                .map(|declaration| declaration.value),
+                None => Some(value),
+            })
+            .unwrap_or_else(|| Value::Keyword("unset".to_string()));
+        css_properties.insert(name, value);
+    }
+}
+
+/*
+ inherit, initial, unsetを解決し、指定されていない型付きの継承プロパティに親要素の計算値を設定する
+ 型付きでない継承プロパティは親要素のマップを共有するので設定しない
+ ルート要素では親要素の代わりに初期値を使う
+*/
+fn resolve_inheritance(css_properties: &mut CSSPropertyMap, parent_style: Option<&ComputedStyle>) {
+    let initial_value =
+        |name: &str| property::lookup(name).map(|definition| definition.initial.clone());
+    let inherited_value = |name: &str| {
+        parent_style
+            .and_then(|style| style.get(name))
+            .or_else(|| initial_value(name))
+    };
+
+    let names: Vec<String> = css_properties.keys().cloned().collect();
+    for name in names {
+        let keyword = match css_properties.get(&name) {
+            Some(Value::Keyword(keyword)) => keyword.clone(),
+            _ => continue,
+        };
+        let inherited = property::lookup(&name).is_some_and(|definition| definition.inherited);
+        let resolved = match &*keyword {
+            "inherit" => inherited_value(&name),
+            "unset" if inherited => inherited_value(&name),
+            "initial" | "unset" => initial_value(&name),
+            _ => continue,
+        };
+        match resolved {
+            Some(value) => css_properties.insert(name, value),
+            None => css_properties.remove(&name),
+        };
+    }
+
+    for definition in property::inherited_properties() {
+        if ComputedStyle::has_field(definition.name)
+            && !css_properties.contains_key(definition.name)
+        {
+            if let Some(value) = inherited_value(definition.name) {
+                css_properties.insert(definition.name.to_string(), value);
+            }
+        }
+    }
+}
+
+/*
+ 色名をColorに変換し、currentcolorをこの要素のcolorの値に置き換える
+ color自身のcurrentcolorは親要素のcolorを指す
+*/
+fn compute_colors(css_properties: &mut CSSPropertyMap, parent_style: Option<&ComputedStyle>) {
+    let parent_color = match parent_style {
+        Some(style) => style.color,
+        None => Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        },
+    };
+    let current_color = match css_properties.get("color") {
+        Some(value) => color_value(value, parent_color).unwrap_or(parent_color),
+        None => parent_color,
+    };
+    for name in COLOR_PROPERTIES.iter() {
+        let basis = if *name == "color" {
+            parent_color
+        } else {
+            current_color
+        };
+        if let Some(value) = css_properties.get_mut(*name) {
+            if let Some(color) = color_value(value, basis) {
+                *value = Value::ColorValue(color);
+            }
+        }
+    }
+}
+
+fn color_value(value: &Value, current_color: Color) -> Option<Color> {
+    match *value {
+        Value::ColorValue(color) => Some(color),
+        Value::Keyword(ref keyword) if keyword.eq_ignore_ascii_case("currentcolor") => {
+            Some(current_color)
+        }
+        Value::Keyword(ref keyword) => color::from_keyword(keyword),
+        _ => None,
+    }
+}
+
+/*
+ em, rem, vw などの相対単位をpxに変換した計算値にする
+ %はレイアウト時に包含ブロックを基準に解決するため残す
+ font-sizeは親要素のフォントサイズを基準に先に解決し、この要素のコンテキストを返す
+*/
+fn compute_lengths(
+    css_properties: &mut CSSPropertyMap,
+    parent_context: &LengthContext,
+    is_root: bool,
+) -> LengthContext {
+    let font_size = css_properties
+        .get("font-size")
+        .and_then(|value| font_size_px(value, parent_context))
+        .unwrap_or(parent_context.font_size);
+    if css_properties.contains_key("font-size") {
+        css_properties.insert("font-size".to_string(), Value::Length(font_size, Unit::Px));
+    }
+
+    let mut context = LengthContext {
+        font_size,
+        percentage_base: 0.0,
+        ..*parent_context
+    };
+    if is_root {
+        context.root_font_size = font_size;
+    }
+    for (name, value) in css_properties.iter_mut() {
+        if name != "font-size" {
+            *value = compute_value(value, &context);
+        }
+    }
+    context
+}
+
+fn compute_value(value: &Value, context: &LengthContext) -> Value {
+    match *value {
+        Value::Length(length, unit) if unit != Unit::Px && unit != Unit::Percent => {
+            Value::Length(length * unit.px_per_unit(context), Unit::Px)
+        }
+        Value::List(ref values) => Value::List(
+            values
+                .iter()
+                .map(|value| compute_value(value, context))
+                .collect(),
+        ),
+        Value::Math(ref expression) => expression.compute(context).into_value(),
+        ref value => value.clone(),
+    }
+}
+
+// https://www.w3.org/TR/css-fonts-4/#font-size-prop
+fn font_size_px(value: &Value, parent_context: &LengthContext) -> Option<f32> {
+    let parent = parent_context.font_size;
+    match *value {
+        Value::Length(..) | Value::Math(_) => Some(value.resolve_px(&LengthContext {
+            percentage_base: parent,
+            ..*parent_context
+        })),
+        Value::Keyword(ref keyword) => match &**keyword {
+            "xx-small" => Some(DEFAULT_FONT_SIZE * 3.0 / 5.0),
+            "x-small" => Some(DEFAULT_FONT_SIZE * 3.0 / 4.0),
+            "small" => Some(DEFAULT_FONT_SIZE * 8.0 / 9.0),
+            "medium" => Some(DEFAULT_FONT_SIZE),
+            "large" => Some(DEFAULT_FONT_SIZE * 6.0 / 5.0),
+            "x-large" => Some(DEFAULT_FONT_SIZE * 3.0 / 2.0),
+            "xx-large" => Some(DEFAULT_FONT_SIZE * 2.0),
+            "xxx-large" => Some(DEFAULT_FONT_SIZE * 3.0),
+            "smaller" => Some(parent / 1.2),
+            "larger" => Some(parent * 1.2),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use computed_style::LengthPercentageOrAuto;
+    use dom::dom::AttrMap;
+    use html::html_parser::new_html_parser;
+    use html::interface::HTMLParserTrait;
+
+    fn parse_html(html: &str) -> Node {
+        new_html_parser(html.to_string()).parse()
+    }
+
+    fn parse_css(css: &str) -> Stylesheet {
+        new_css_parser(css.to_string()).parse()
+    }
+
+    // スタイルの付いた木がDOMを借用するので、テストの間はDOMを解放しない
+    fn style(html: &str, css: &str) -> StyledNode<'static> {
+        let root = Box::leak(Box::new(parse_html(html)));
+        style_tree(root, &[&parse_css(css)], MediaEnvironment::default())
+    }
+
+    #[test]
+    fn test_logical_pseudo_classes() {
+        use style_sheet::util::Unit::Px;
+
+        let html = "<div class=\"card\"><img></img><p class=\"note\">a</p></div><div class=\"card\"><p>b</p></div>";
+        let css = ":where(div.card) { width: 1px; } div:has(> img) { width: 2px; } div :not(.note, img) { width: 3px; }";
+        let styled = style(html, css);
+
+        let with_img = &styled.children[0];
+        let without_img = &styled.children[1];
+        assert_eq!(with_img.value("width"), Some(Value::Length(2.0, Px)));
+        assert_eq!(without_img.value("width"), Some(Value::Length(1.0, Px)));
+        assert_eq!(
+            with_img.children[1].style.width,
+            LengthPercentageOrAuto::Auto
+        );
+        assert_eq!(
+            without_img.children[0].value("width"),
+            Some(Value::Length(3.0, Px))
+        );
+    }
+
+    #[test]
+    fn test_element_state_pseudo_classes() {
+        use style_sheet::util::Unit::Px;
+
+        let html = "<form><input id=\"name\"></input><a id=\"home\" href=\"/\"></a></form>";
+        let css = "form:focus-within { width: 1px; } input:focus { width: 2px; } a:hover { width: 3px; } a:link { height: 4px; }";
+        let mut root = parse_html(html);
+        root.find_by_id_mut("name")
+            .unwrap()
+            .set_state(ElementState::FOCUS, true);
+        root.find_by_id_mut("home")
+            .unwrap()
+            .set_state(ElementState::HOVER | ElementState::VISITED, true);
+        let stylesheet = parse_css(css);
+        let styled = style_tree(&root, &[&stylesheet], MediaEnvironment::default());
+
+        assert_eq!(styled.value("width"), Some(Value::Length(1.0, Px)));
+        assert_eq!(
+            styled.children[0].value("width"),
+            Some(Value::Length(2.0, Px))
+        );
+        assert_eq!(
+            styled.children[1].value("width"),
+            Some(Value::Length(3.0, Px))
+        );
+        assert_eq!(
+            styled.children[1].style.height,
+            LengthPercentageOrAuto::Auto
+        );
+    }
+
+    #[test]
+    fn test_pseudo_element_rules() {
+        let css = "p { color: red } a::after { content: \"!\" }";
+        let stylesheet = parse_css(css);
+        let sheets = cascade_sheets(&[&stylesheet], &MediaEnvironment::default());
+        // ユーザーエージェントのスタイルシートには疑似要素のルールがない
+        assert!(sheets[0].pseudo_elements.is_empty());
+        assert_eq!(sheets[1].pseudo_elements, vec![PseudoElement::After]);
+
+        let root = parse_html("<p><a></a></p>");
+        let styled = style_tree(&root, &[&stylesheet], MediaEnvironment::default());
+        assert!(styled.before.is_none() && styled.after.is_none());
+        assert!(styled.children[0].before.is_none());
+        assert_eq!(
+            styled.children[0].after.as_ref().unwrap().generated_content,
+            Some("!".to_string())
+        );
+    }
+
+    #[test]
+    fn test_relative_lengths() {
+        let html = "<html><div><p></p></div></html>";
+        let css = "html { font-size: 20px } div { font-size: 150%; width: 50vw } \
+               p { margin-left: 2em; margin-right: 1rem; padding-left: 72pt; width: 50% }";
+        let root = parse_html(html);
+        let stylesheet = parse_css(css);
+        let media = MediaEnvironment {
+            width: 800.0,
+            height: 600.0,
+            ..Default::default()
+        };
+        let styled = style_tree(&root, &[&stylesheet], media);
+
+        let div = &styled.children[0];
+        let p = &div.children[0];
+        assert_eq!(div.value("font-size"), Some(Value::Length(30.0, Unit::Px)));
+        assert_eq!(div.value("width"), Some(Value::Length(400.0, Unit::Px)));
+        assert_eq!(p.value("margin-left"), Some(Value::Length(60.0, Unit::Px)));
+        assert_eq!(p.value("margin-right"), Some(Value::Length(20.0, Unit::Px)));
+        assert_eq!(p.value("padding-left"), Some(Value::Length(96.0, Unit::Px)));
+        assert_eq!(p.value("width"), Some(Value::Length(50.0, Unit::Percent)));
+    }
+
+    #[test]
+    fn test_cascade_origins_and_importance() {
+        let html = "<html><head></head><p id=\"a\" class=\"b\"></p></html>";
+        let user_css = "p { color: green !important; margin-top: 4px }";
+        let author_css = "#a { color: red; padding-left: 1px } \
+                      p { padding-left: 2px !important; margin-top: 3px } \
+                      .b { color: blue !important }";
+        let root = parse_html(html);
+        let mut user = parse_css(user_css);
+        user.origin = Origin::User;
+        let author = parse_css(author_css);
+        let styled = style_tree(&root, &[&user, &author], MediaEnvironment::default());
+
+        let head = &styled.children[0];
+        let p = &styled.children[1];
+        assert!(matches!(head.display(), Display::None));
+        assert!(matches!(p.display(), Display::Block));
+        // ユーザーの!importantは作成者の!importantより優先される
+        assert_eq!(
+            p.value("color"),
+            Some(Value::ColorValue(Color {
+                r: 0,
+                g: 128,
+                b: 0,
+                a: 255
+            }))
+        );
+        // !importantは詳細度より優先される
+        assert_eq!(p.value("padding-left"), Some(Value::Length(2.0, Unit::Px)));
+        // 通常の宣言では作成者がユーザーエージェントとユーザーより優先される
+        assert_eq!(p.value("margin-top"), Some(Value::Length(3.0, Unit::Px)));
+    }
+
+    #[test]
+    fn test_revert_important() {
+        let html = "<div><p></p><ul></ul><h1></h1></div>";
+        let user_css = "p { color: green } \
+                    h1 { color: green; color: revert !important; margin-top: revert !important }";
+        let author_css = "p { color: red; color: revert !important; margin-top: 1px } \
+                      p { margin-top: revert !important } \
+                      ul { padding-left: 1px !important; padding-left: revert !important } \
+                      h1 { color: red; margin-top: 2px !important }";
+        let root = parse_html(html);
+        let mut user = parse_css(user_css);
+        user.origin = Origin::User;
+        let author = parse_css(author_css);
+        let styled = style_tree(&root, &[&user, &author], MediaEnvironment::default());
+
+        let color = |node: &StyledNode| match node.value("color") {
+            Some(Value::ColorValue(color)) => (color.r, color.g, color.b),
+            value => panic!("{:?} is not a color", value),
+        };
+        let p = &styled.children[0];
+        let ul = &styled.children[1];
+        let h1 = &styled.children[2];
+        // 作成者の!importantなrevertは、作成者の通常の宣言ではなくユーザーの宣言に戻す
+        assert_eq!(color(p), (0, 128, 0));
+        assert_eq!(p.value("margin-top"), Some(Value::Length(16.0, Unit::Px)));
+        assert_eq!(
+            ul.value("padding-left"),
+            Some(Value::Length(40.0, Unit::Px))
+        );
+        // ユーザーの!importantなrevertは、ユーザーの宣言だけを除き、作成者の宣言は残す
+        assert_eq!(color(h1), (255, 0, 0));
+        assert_eq!(h1.value("margin-top"), Some(Value::Length(2.0, Unit::Px)));
+    }
+
+    #[test]
+    fn test_inheritance_and_css_wide_keywords() {
+        let html =
+            "<body><p>text</p><h1 class=\"plain\"></h1><ul class=\"flat\"></ul><div></div></body>";
+        let css = "body { color: red; font-size: 20px; margin-left: 3px } \
+               p { margin-left: inherit } \
+               h1.plain { font-size: unset; color: initial } \
+               ul.flat { padding-left: revert; margin-top: revert } \
+               div { margin-left: unset }";
+        let styled = style(html, css);
+
+        let red = Some(Value::ColorValue(Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        }));
+        let p = &styled.children[0];
+        let text = &p.children[0];
+        let h1 = &styled.children[1];
+        let ul = &styled.children[2];
+        let div = &styled.children[3];
+        assert_eq!(p.value("color"), red);
+        assert_eq!(text.value("color"), red);
+        assert_eq!(text.value("font-size"), Some(Value::Length(20.0, Unit::Px)));
+        assert_eq!(p.value("margin-left"), Some(Value::Length(3.0, Unit::Px)));
+        // unsetは継承プロパティなら親の値、そうでなければ初期値になる
+        assert_eq!(h1.value("font-size"), Some(Value::Length(20.0, Unit::Px)));
+        assert_eq!(div.value("margin-left"), Some(Value::Length(0.0, Unit::Px)));
+        assert_eq!(
+            h1.value("color"),
+            Some(Value::ColorValue(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            }))
+        );
+        // revertはユーザーエージェントスタイルシートの値に戻す
+        assert_eq!(
+            ul.value("padding-left"),
+            Some(Value::Length(40.0, Unit::Px))
+        );
+        assert_eq!(ul.value("margin-top"), Some(Value::Length(20.0, Unit::Px)));
+    }
+
+    #[test]
+    fn test_custom_properties() {
+        let html = "<html><div class=\"card\"><p></p></div></html>";
+        let css = "html { --space: 2px; --theme: var(--brand, #00ff00); --loop: var(--loop); \
+                      --garbage: 3px @ } \
+               .card { --space: 5px; margin: var(--space) 1px; color: var(--theme) } \
+               p { padding-left: var(--space); width: var(--missing); color: var(--loop, red); \
+                   margin-left: var(--garbage) }";
+        let styled = style(html, css);
+
+        let card = &styled.children[0];
+        let p = &card.children[0];
+        assert_eq!(
+            card.value("--space"),
+            Some(Value::Unparsed {
+                css: "5px".to_string(),
+                shorthand: None
+            })
+        );
+        assert_eq!(card.value("margin-top"), Some(Value::Length(5.0, Unit::Px)));
+        assert_eq!(
+            card.value("margin-right"),
+            Some(Value::Length(1.0, Unit::Px))
+        );
+        assert_eq!(
+            card.value("color"),
+            Some(Value::ColorValue(Color {
+                r: 0,
+                g: 255,
+                b: 0,
+                a: 255
+            }))
+        );
+        // カスタムプロパティは継承される
+        assert_eq!(p.value("padding-left"), Some(Value::Length(5.0, Unit::Px)));
+        // 計算値の時点で無効な値はunsetになる
+        assert_eq!(p.style.width, LengthPercentageOrAuto::Auto);
+        assert_eq!(p.value("margin-left"), Some(Value::Length(0.0, Unit::Px)));
+        // 循環参照したカスタムプロパティは無効な値になり、フォールバックが使われる
+        assert_eq!(p.value("--loop"), None);
+        assert_eq!(
+            p.value("color"),
+            Some(Value::ColorValue(Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255
+            }))
+        );
+    }
+
+    #[test]
+    fn test_cascade_layers() {
+        let html = "<html><p class=\"a\"></p></html>";
+        let css = "@layer reset, components; \
+               @layer components { p { margin-top: 2px; padding-left: 2px !important } } \
+               @layer reset { \
+                   p { margin-top: 1px; margin-bottom: 1px; margin-right: 7px; \
+                       padding-left: 1px !important; padding-right: 1px } } \
+               p { padding-right: 3px; margin-right: revert-layer } \
+               @layer { p { margin-bottom: 4px } } \
+               @layer components.buttons { p.a { margin-left: 5px } } \
+               @layer components { p { margin-left: 6px } }";
+        let styled = style(html, css);
+        let p = &styled.children[0];
+        let px = |length| Some(Value::Length(length, Unit::Px));
+
+        // 後に宣言されたレイヤーが、出現順に関わらず優先される
+        assert_eq!(p.value("margin-top"), px(2.0));
+        assert_eq!(p.value("margin-bottom"), px(4.0));
+        // レイヤー外のスタイルはすべてのレイヤーより優先される
+        assert_eq!(p.value("padding-right"), px(3.0));
+        // 親レイヤー直下のルールはサブレイヤーより優先され、詳細度は比較されない
+        assert_eq!(p.value("margin-left"), px(6.0));
+        // !importantでは先のレイヤーが優先される
+        assert_eq!(p.value("padding-left"), px(1.0));
+        assert_eq!(p.value("margin-right"), px(7.0));
+    }
+
+    #[test]
+    fn test_source_locations() {
+        use style_sheet::source_location::set_file;
+
+        let html = "<html><p class=\"a\"></p></html>";
+        let css = "/* 見出し\n   以外 */\np {\n  margin: 1px;\n}\n\n  .a, div > .b {\n    margin-left: 2px;\n  }\n";
+        let root = parse_html(html);
+        let mut stylesheet = parse_css(css);
+        set_file(&mut stylesheet.rules, "main.css");
+
+        let rule = stylesheet.rules[1].as_style().unwrap();
+        assert_eq!(rule.location().unwrap().to_string(), "main.css:7:3");
+        // セレクターは詳細度の順に並んでいる
+        assert_eq!(rule.selectors[0].location().to_string(), "main.css:7:7");
+        assert_eq!(rule.declarations[0].location.to_string(), "main.css:8:5");
+
+        let styled = style_tree(&root, &[&stylesheet], MediaEnvironment::default());
+        let p = &styled.children[0];
+        let source = |name| p.source(name).map(ToString::to_string);
+        assert_eq!(source("margin-left"), Some("main.css:8:5".to_string()));
+        // ショートハンドから展開したロングハンドはショートハンドの位置になる
+        assert_eq!(source("margin-top"), Some("main.css:4:3".to_string()));
+        assert_eq!(source("padding-left"), None);
+    }
+
+    #[test]
+    fn test_explain_style() {
+        let html = "<html><div><p class=\"a\"></p></div></html>";
+        let css = "p { color: blue; margin-left: 3px !important } \
+               div .a, .a { color: red; margin-left: 4px } \
+               em { color: green }";
+        let root = parse_html(html);
+        let stylesheet = parse_css(css);
+        let p = &root.children[0].children[0];
+        let explanation = explain_style(&root, p, &[&stylesheet], MediaEnvironment::default());
+
+        let selectors: Vec<_> = explanation
+            .iter()
+            .map(|rule| (rule.origin, rule.specificity))
+            .collect();
+        // 優先度の高いルールから並び、ユーザーエージェントのルールが最後に来る
+        assert_eq!(selectors[0], (Origin::Author, (0, 1, 1)));
+        assert_eq!(selectors[1], (Origin::Author, (0, 0, 1)));
+        assert_eq!(selectors.last().unwrap().0, Origin::UserAgent);
+        assert_eq!(
+            explanation[0].selector,
+            stylesheet.rules[1].as_style().unwrap().selectors[0]
+        );
+
+        let status = |explanation: &[RuleExplanation], rule: usize, name: &str| {
+            explanation[rule]
+                .declarations
+                .iter()
+                .find(|explained| explained.declaration.name == name)
+                .map(|explained| explained.status.clone())
+                .unwrap()
+        };
+        let author_p = stylesheet.rules[0].as_style().unwrap();
+        let author_a = stylesheet.rules[1].as_style().unwrap();
+        assert_eq!(status(&explanation, 0, "color"), DeclarationStatus::Applied);
+        assert_eq!(
+            status(&explanation, 1, "color"),
+            DeclarationStatus::Overridden(author_a.declarations[0].clone())
+        );
+        // !importantな宣言は詳細度の高いルールの宣言にも勝つ
+        assert_eq!(
+            status(&explanation, 0, "margin-left"),
+            DeclarationStatus::Overridden(author_p.declarations[1].clone())
+        );
+        assert_eq!(
+            status(&explanation, 1, "margin-left"),
+            DeclarationStatus::Applied
+        );
+
+        // 木に含まれない要素やテキストには何もマッチしない
+        let text = parse_html("<p>text</p>");
+        assert!(
+            explain_style(&root, &text, &[&stylesheet], MediaEnvironment::default()).is_empty()
+        );
+
+        // revertで勝った宣言は、戻した先の宣言を値にする
+        let css = ".a { margin-top: 2px; color: green } \
+               div .a { margin-top: revert; color: revert } \
+               .a::before { content: \"x\" }";
+        let stylesheet = parse_css(css);
+        let explanation = explain_style(&root, p, &[&stylesheet], MediaEnvironment::default());
+        let author_a = stylesheet.rules[0].as_style().unwrap();
+        let reverting = stylesheet.rules[1].as_style().unwrap();
+        let user_agent_margin = explanation
+            .iter()
+            .filter(|rule| rule.origin == Origin::UserAgent)
+            .flat_map(|rule| rule.declarations.iter())
+            .find(|explained| explained.declaration.name == "margin-top")
+            .unwrap();
+        assert_eq!(user_agent_margin.status, DeclarationStatus::Applied);
+        assert_eq!(
+            status(&explanation, 0, "margin-top"),
+            DeclarationStatus::Reverted(Some(user_agent_margin.declaration.clone()))
+        );
+        // 戻す先がなければunsetと同じ
+        assert_eq!(
+            status(&explanation, 0, "color"),
+            DeclarationStatus::Reverted(None)
+        );
+        assert_eq!(
+            status(&explanation, 1, "margin-top"),
+            DeclarationStatus::Overridden(reverting.declarations[0].clone())
+        );
+        assert_eq!(explanation[1].selector, author_a.selectors[0]);
+
+        // 疑似要素のルールは要素のルールの後に並ぶ
+        let before = explanation.last().unwrap();
+        assert_eq!(before.pseudo_element, Some(PseudoElement::Before));
+        assert_eq!(
+            status(&explanation, explanation.len() - 1, "content"),
+            DeclarationStatus::Applied
+        );
+        assert!(explanation[..explanation.len() - 1]
+            .iter()
+            .all(|rule| rule.pseudo_element.is_none()));
+    }
+
+    #[test]
+    fn test_ancestor_filter() {
+        let html =
+            "<html><div class=\"x\"><ul><li></li></ul></div><ul id=\"y\"><li></li></ul></html>";
+        let css = ".x li { margin-left: 1px } #y > li { margin-right: 2px } \
+               div + ul li { margin-top: 3px } .x ~ li { margin-bottom: 4px }";
+        let styled = style(html, css);
+        let first = &styled.children[0].children[0].children[0];
+        let second = &styled.children[1].children[0];
+        let px = |length| Some(Value::Length(length, Unit::Px));
+
+        assert_eq!(first.value("margin-left"), px(1.0));
+        assert_eq!(first.value("margin-right"), px(0.0));
+        // 兄弟の部分木を抜けた後は、その祖先はフィルターから取り除かれている
+        assert_eq!(second.value("margin-left"), px(0.0));
+        assert_eq!(second.value("margin-right"), px(2.0));
+        // 兄弟コンビネーターの左側は祖先として要求しない
+        assert_eq!(second.value("margin-top"), px(3.0));
+        assert_eq!(second.value("margin-bottom"), px(0.0));
+    }
+
+    #[test]
+    fn test_style_sharing() {
+        let html = "<html><ul><li class=\"a\"></li><li class=\"a\"></li><li class=\"b\"></li>\
+                <li class=\"a\"></li><li class=\"a\"><em></em></li></ul></html>";
+        let mut root = parse_html(html);
+        let px = |length| Some(Value::Length(length, Unit::Px));
+
+        let stylesheet = parse_css(
+            ".a { margin-left: 1px } .b { margin-left: 2px } li:hover { margin-top: 3px }",
+        );
+        let (styled, statistics) =
+            style_tree_with_statistics(&root, &[&stylesheet], MediaEnvironment::default());
+        let items = &styled.children[0].children;
+        // html, ul, 最初の.a, .b, em がキャッシュに見つからない
+        assert_eq!(statistics, StyleSharingStatistics { hits: 3, misses: 5 });
+        assert_eq!(items[3].value("margin-left"), px(1.0));
+        assert_eq!(items[2].value("margin-left"), px(2.0));
+
+        // 子孫が:hoverの要素（html, ul, 最後のli）は、:hoverが伝播するので共有しない
+        root.children[0].children[4].children[0].set_state(ElementState::HOVER, true);
+        let (styled, statistics) =
+            style_tree_with_statistics(&root, &[&stylesheet], MediaEnvironment::default());
+        assert_eq!(statistics, StyleSharingStatistics { hits: 2, misses: 3 });
+        assert_eq!(styled.children[0].children[4].value("margin-top"), px(3.0));
+        assert_eq!(styled.children[0].children[3].value("margin-top"), px(0.0));
+
+        // 兄弟コンビネーターがあると、兄弟でもマッチするルールが変わりうる
+        let stylesheet = parse_css(".b + .a { margin-left: 4px }");
+        let (styled, statistics) =
+            style_tree_with_statistics(&root, &[&stylesheet], MediaEnvironment::default());
+        assert_eq!(statistics, StyleSharingStatistics::default());
+        assert_eq!(styled.children[0].children[3].value("margin-left"), px(4.0));
+    }
+
+    #[test]
+    fn test_parallel_style_tree() {
+        // 線形合同法による再現可能な乱数
+        struct Random(u64);
+        impl Random {
+            fn next(&mut self, bound: usize) -> usize {
+                self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1);
+                (self.0 >> 33) as usize % bound
+            }
+        }
+        fn generate(random: &mut Random, depth: usize) -> Node {
+            if depth == 0 || random.next(8) == 0 {
+                return Node::text("text".to_string());
+            }
+            let tags = ["div", "p", "ul", "li", "span", "em"];
+            let mut attributes = AttrMap::new();
+            attributes.insert("class".to_string(), format!("c{}", random.next(4)));
+            if random.next(5) == 0 {
+                attributes.insert("id".to_string(), format!("i{}", random.next(3)));
+            }
+            let children = (0..random.next(6))
+                .map(|_| generate(random, depth - 1))
+                .collect();
+            let mut node = Node::elem(
+                tags[random.next(tags.len())].to_string(),
+                attributes,
+                children,
+            );
+            if random.next(20) == 0 {
+                node.set_state(ElementState::HOVER, true);
+            }
+            node
+        }
+        fn assert_same(sequential: &StyledNode, parallel: &StyledNode) {
+            assert!(std::ptr::eq(sequential.node, parallel.node));
+            assert_eq!(sequential.style, parallel.style);
+            assert_eq!(sequential.generated_content, parallel.generated_content);
+            let offsets = |styled: &StyledNode| -> HashMap<String, usize> {
+                styled
+                    .sources
+                    .iter()
+                    .map(|(name, location)| (name.clone(), location.offset))
+                    .collect()
+            };
+            assert_eq!(offsets(sequential), offsets(parallel));
+            for (a, b) in [
+                (&sequential.before, &parallel.before),
+                (&sequential.after, &parallel.after),
+            ] {
+                assert_eq!(a.is_some(), b.is_some());
+                if let (Some(a), Some(b)) = (a, b) {
+                    assert_same(a, b);
+                }
+            }
+            assert_eq!(sequential.children.len(), parallel.children.len());
+            for (a, b) in sequential.children.iter().zip(&parallel.children) {
+                assert_same(a, b);
+            }
+        }
+
+        let css = "div { margin-left: 1em; font-size: 12px } .c1 { font-size: 1.5em } \
+               ul > li { padding-left: 2px } div .c2 { color: red !important } \
+               #i0 { color: blue } li:hover { margin-top: 3px } \
+               .c3 { &.c3 span { margin-right: 4px } } \
+               li::before { content: counter(item) \". \"; counter-increment: item } \
+               ul { counter-reset: item }";
+        // 兄弟コンビネーターと:has()はスタイル共有を止める
+        let structural = "li + li { margin-bottom: 5px } div:has(> em) { padding-top: 6px }";
+        let mut random = Random(1);
+        for &extra in &["", structural] {
+            let stylesheet = parse_css(&format!("{} {}", css, extra));
+            for _ in 0..3 {
+                let children = (0..6).map(|_| generate(&mut random, 6)).collect();
+                let root = Node::elem("html".to_string(), AttrMap::new(), children);
+                // 上の方の要素とスレッドで計算する部分木に分かれる
+                assert!(split_depth(&root, 4 * TASKS_PER_THREAD) > 1);
+                let sequential = style_tree(&root, &[&stylesheet], MediaEnvironment::default());
+                for &threads in &[2, 4] {
+                    let parallel = style_tree_parallel(
+                        &root,
+                        &[&stylesheet],
+                        MediaEnvironment::default(),
+                        threads,
+                    );
+                    assert_same(&sequential, &parallel);
+                }
+            }
+        }
+
+        // 一つだけ大きな部分木は、スレッドが取ったときに分け直される
+        let stylesheet = parse_css(&format!("{} {}", css, structural));
+        let mut children: Vec<Node> = (0..4 * TASKS_PER_THREAD)
+            .map(|_| Node::text("text".to_string()))
+            .collect();
+        children.push(Node::elem(
+            "div".to_string(),
+            AttrMap::new(),
+            (0..6).map(|_| generate(&mut random, 6)).collect(),
+        ));
+        let root = Node::elem("html".to_string(), AttrMap::new(), children);
+        assert_eq!(split_depth(&root, 4 * TASKS_PER_THREAD), 1);
+        assert!(larger_than(
+            root.children.last().unwrap(),
+            count_nodes(&root) / (4 * TASKS_PER_THREAD)
+        ));
+        let sequential = style_tree(&root, &[&stylesheet], MediaEnvironment::default());
+        let parallel = style_tree_parallel(&root, &[&stylesheet], MediaEnvironment::default(), 4);
+        assert_same(&sequential, &parallel);
+    }
+
+    #[test]
+    fn test_restyle() {
+        fn assert_same(full: &StyledNode, incremental: &StyledNode) {
+            assert_eq!(full.style, incremental.style);
+            assert_eq!(full.generated_content, incremental.generated_content);
+            for (a, b) in [
+                (&full.before, &incremental.before),
+                (&full.after, &incremental.after),
+            ] {
+                assert_eq!(a.is_some(), b.is_some());
+                if let (Some(a), Some(b)) = (a, b) {
+                    assert_same(a, b);
+                }
+            }
+            assert_eq!(full.children.len(), incremental.children.len());
+            for (a, b) in full.children.iter().zip(&incremental.children) {
+                assert_same(a, b);
+            }
+        }
+
+        let html = "<html><body><ul id=\"list\"><li class=\"item\">a</li><li class=\"item\">b</li>\
+                <li class=\"item\">c</li></ul><p>text</p></body></html>";
+        let mut root = parse_html(html);
+        let stylesheet = parse_css(
+            ".item { margin-left: 1px } .item.selected { color: red } \
+                          .selected + li { margin-top: 3px } .open li { padding-left: 2px } \
+                          ul:hover { padding-top: 5px } .big { font-size: 20px } \
+                          li::before { content: counter(item); counter-increment: item }",
+        );
+        let media = MediaEnvironment::default();
+        let mut cache = StyleCache::default();
+        // 変更後のDOMをすべて計算し直した結果と比べる
+        let mut restyle = |root: &mut Node, stylesheet: &Stylesheet| {
+            let (styled, statistics) = cache.restyle_with_statistics(root, &[stylesheet], media);
+            assert_same(&style_tree(styled.node, &[stylesheet], media), &styled);
+            statistics.restyled
+        };
+        fn list(root: &mut Node) -> &mut Node {
+            root.find_by_id_mut("list").unwrap()
+        }
+
+        assert_eq!(restyle(&mut root, &stylesheet), 11);
+        assert_eq!(restyle(&mut root, &stylesheet), 0);
+        // どのセレクターも参照しないクラス
+        root.children[0].children[1].set_attribute("class", "unused");
+        assert_eq!(restyle(&mut root, &stylesheet), 0);
+        // 要素自身と後続の兄弟の部分木。計算値が変わった要素は子も計算し直す
+        list(&mut root).children[0].set_attribute("class", "item selected");
+        assert_eq!(restyle(&mut root, &stylesheet), 6);
+        // :hoverは祖先に伝播する
+        list(&mut root).children[2].set_state(ElementState::HOVER, true);
+        assert_eq!(restyle(&mut root, &stylesheet), 6);
+        // 子孫コンビネーターの左側なら子孫すべて
+        list(&mut root).set_attribute("class", "open");
+        assert_eq!(restyle(&mut root, &stylesheet), 7);
+        // 子の追加や削除で祖先の:hoverが変わりうるので、文書全体
+        let mut attributes = AttrMap::new();
+        attributes.insert("class".to_string(), "item".to_string());
+        list(&mut root).insert_child(1, Node::elem("li".to_string(), attributes, Vec::new()));
+        list(&mut root).remove_child(3);
+        assert_eq!(restyle(&mut root, &stylesheet), 10);
+        // ルートのフォントサイズはremを通じてすべての要素に及ぶ
+        root.set_attribute("class", "big");
+        assert_eq!(restyle(&mut root, &stylesheet), 10);
+
+        // スタイルシートの変更では、変わったルールにマッチしうる要素だけ
+        // （前に挿入すると後ろのルールの位置が変わり、それらも変わったルールになる）
+        let changed = parse_css(
+            ".item { margin-left: 1px } .item.selected { color: red } \
+                       .selected + li { margin-top: 3px } .open li { padding-left: 2px } \
+                       ul:hover { padding-top: 5px } .big { font-size: 20px } \
+                       li::before { content: counter(item); counter-increment: item } \
+                       p { color: blue }",
+        );
+        assert_eq!(restyle(&mut root, &changed), 2);
+        assert!(root.dirty.is_clean());
+        // :has()の中の:hoverも子孫の状態を参照する
+        list(&mut root).children[0].set_state(ElementState::HOVER, true);
+        restyle(
+            &mut root,
+            &parse_css("body:has(ul:hover) p { padding-top: 7px }"),
+        );
+
+        // 変更のない部分木はたどらず、前回の計算値を共有する
+        let previous = cache.restyle(&mut root, &[&changed], media).children[0]
+            .style
+            .clone();
+        list(&mut root).children[2].set_attribute("class", "item selected");
+        let (styled, statistics) = cache.restyle_with_statistics(&mut root, &[&changed], media);
+        assert_eq!(
+            statistics,
+            RestyleStatistics {
+                restyled: 2,
+                reused: 8
+            }
+        );
+        assert!(Arc::ptr_eq(&styled.children[0].style, &previous));
+    }
 }
@@ -0,0 +1,164 @@
+// 祖先要素のid、クラス、タグ名を記録するカウンティングブルームフィルター
+// 子孫・子コンビネーターで必要な祖先がいないセレクターを、祖先をたどらずに除外する
+// https://wiki.mozilla.org/Servo/StyleUpdateOnDOMChange
+
+use style_sheet::selector::{Combinator, Selector};
+use style_sheet::simple_selector::SimpleSelector;
+
+const KEY_BITS: u32 = 12;
+const KEY_MASK: u32 = (1 << KEY_BITS) - 1;
+
+/// The most ancestor keys of a selector that are checked against the filter.
+pub const ANCESTOR_HASHES: usize = 4;
+
+/// A counting Bloom filter of element keys. Keys can be removed again, so the
+/// filter can follow a depth-first walk of the tree: insert an element's keys
+/// before styling its children and remove them afterwards.
+#[derive(Clone)]
+pub struct CountingBloomFilter {
+    counters: Box<[u8; 1 << KEY_BITS]>,
+}
+
+impl Default for CountingBloomFilter {
+    fn default() -> CountingBloomFilter {
+        CountingBloomFilter {
+            counters: Box::new([0; 1 << KEY_BITS]),
+        }
+    }
+}
+
+impl CountingBloomFilter {
+    pub fn insert_hash(&mut self, hash: u32) {
+        for index in indices(hash) {
+            let counter = &mut self.counters[index];
+            *counter = counter.saturating_add(1);
+        }
+    }
+
+    pub fn remove_hash(&mut self, hash: u32) {
+        for index in indices(hash) {
+            let counter = &mut self.counters[index];
+            // 溢れたカウンターは以後変えない（減らすと偽陰性になりうる）
+            if *counter != u8::MAX {
+                *counter -= 1;
+            }
+        }
+    }
+
+    /// False only if no inserted key has this hash; true may be a false positive.
+    pub fn might_contain_hash(&self, hash: u32) -> bool {
+        indices(hash).iter().all(|&index| self.counters[index] != 0)
+    }
+
+    /// Whether every non-zero hash of `hashes` might be in the filter.
+    pub fn might_contain_all(&self, hashes: &[u32; ANCESTOR_HASHES]) -> bool {
+        hashes
+            .iter()
+            .take_while(|&&hash| hash != 0)
+            .all(|&hash| self.might_contain_hash(hash))
+    }
+}
+
+fn indices(hash: u32) -> [usize; 2] {
+    [
+        (hash & KEY_MASK) as usize,
+        ((hash >> KEY_BITS) & KEY_MASK) as usize,
+    ]
+}
+
+// FNV-1a。種類ごとの接頭辞で、同じ名前のタグ・クラス・idを区別する
+// 0はANCESTOR_HASHESの空きを表すので使わない
+fn hash_key(prefix: u8, name: &str) -> u32 {
+    let hash = std::iter::once(prefix)
+        .chain(name.bytes())
+        .fold(0x811c_9dc5u32, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        });
+    hash.max(1)
+}
+
+pub fn tag_hash(tag_name: &str) -> u32 {
+    hash_key(b'<', tag_name)
+}
+
+pub fn id_hash(id: &str) -> u32 {
+    hash_key(b'#', id)
+}
+
+pub fn class_hash(class: &str) -> u32 {
+    hash_key(b'.', class)
+}
+
+/// The hashes of the ids, classes and tag names that an element matching
+/// `selector` must have among its ancestors, padded with zeros. Compounds
+/// joined by sibling combinators are siblings rather than ancestors and skipped.
+pub fn ancestor_hashes(selector: &Selector) -> [u32; ANCESTOR_HASHES] {
+    let mut hashes = Vec::new();
+    collect_ancestor_hashes(selector, false, &mut hashes);
+    let mut result = [0; ANCESTOR_HASHES];
+    for (slot, hash) in result.iter_mut().zip(hashes) {
+        *slot = hash;
+    }
+    result
+}
+
+fn collect_ancestor_hashes(selector: &Selector, is_ancestor: bool, hashes: &mut Vec<u32>) {
+    match *selector {
+        Selector::Simple(ref compound) => {
+            if is_ancestor {
+                compound_hashes(compound, hashes);
+            }
+        }
+        Selector::Complex(ref left, combinator, ref compound) => {
+            if is_ancestor {
+                compound_hashes(compound, hashes);
+            }
+            let left_is_ancestor = match combinator {
+                Combinator::Descendant | Combinator::Child => true,
+                Combinator::NextSibling | Combinator::SubsequentSibling => false,
+            };
+            collect_ancestor_hashes(left, left_is_ancestor, hashes);
+        }
+    }
+}
+
+// idやクラスのほうが絞り込めるので先に入れる
+fn compound_hashes(compound: &SimpleSelector, hashes: &mut Vec<u32>) {
+    hashes.extend(compound.id.iter().map(|id| id_hash(id)));
+    hashes.extend(compound.class.iter().map(|class| class_hash(class)));
+    hashes.extend(compound.tag_name.iter().map(|tag_name| tag_hash(tag_name)));
+}
+
+#[test]
+fn test_counting_bloom_filter() {
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+    use style_sheet::media_query::MediaEnvironment;
+
+    let mut filter = CountingBloomFilter::default();
+    filter.insert_hash(tag_hash("div"));
+    filter.insert_hash(class_hash("nav"));
+    filter.insert_hash(class_hash("nav"));
+    filter.remove_hash(class_hash("nav"));
+    assert!(filter.might_contain_hash(tag_hash("div")));
+    assert!(filter.might_contain_hash(class_hash("nav")));
+    assert!(!filter.might_contain_hash(id_hash("nav")));
+    filter.remove_hash(class_hash("nav"));
+    assert!(!filter.might_contain_hash(class_hash("nav")));
+
+    let stylesheet =
+        new_css_parser("div.nav > ul li {} h1 + p {} .a ~ b > c {}".to_string()).parse();
+    let selectors: Vec<&Selector> = stylesheet
+        .style_rules(&MediaEnvironment::default())
+        .into_iter()
+        .map(|rule| &rule.selectors[0])
+        .collect();
+    assert_eq!(
+        ancestor_hashes(selectors[0]),
+        [tag_hash("ul"), class_hash("nav"), tag_hash("div"), 0]
+    );
+    // 兄弟は祖先ではない
+    assert_eq!(ancestor_hashes(selectors[1]), [0; ANCESTOR_HASHES]);
+    assert_eq!(ancestor_hashes(selectors[2]), [tag_hash("b"), 0, 0, 0]);
+    assert!(!filter.might_contain_all(&ancestor_hashes(selectors[0])));
+}
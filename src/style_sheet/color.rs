@@ -0,0 +1,435 @@
+// CSS Color 4 の色構文を Color に変換する
+// https://www.w3.org/TR/css-color-4/
+
+use style_sheet::util::{Color, Unit, Value};
+
+/// Parses the digits of a `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa` color.
+pub fn from_hex(digits: &str) -> Option<Color> {
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize, len: usize| {
+        let hex = &digits[i * len..(i + 1) * len];
+        let value = u8::from_str_radix(hex, 16).unwrap();
+        if len == 1 {
+            value * 17
+        } else {
+            value
+        }
+    };
+    match digits.len() {
+        3 | 4 => Some(Color {
+            r: channel(0, 1),
+            g: channel(1, 1),
+            b: channel(2, 1),
+            a: if digits.len() == 4 {
+                channel(3, 1)
+            } else {
+                255
+            },
+        }),
+        6 | 8 => Some(Color {
+            r: channel(0, 2),
+            g: channel(1, 2),
+            b: channel(2, 2),
+            a: if digits.len() == 8 {
+                channel(3, 2)
+            } else {
+                255
+            },
+        }),
+        _ => None,
+    }
+}
+
+/// Looks up a named color or `transparent`. `currentcolor` is resolved during the cascade.
+pub fn from_keyword(name: &str) -> Option<Color> {
+    let name = name.to_ascii_lowercase();
+    if name == "transparent" {
+        return Some(Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        });
+    }
+    NAMED_COLORS
+        .binary_search_by(|&(named, ..)| named.cmp(&*name))
+        .ok()
+        .map(|i| {
+            let (_, r, g, b) = NAMED_COLORS[i];
+            Color { r, g, b, a: 255 }
+        })
+}
+
+/// The shortest name of an opaque color, e.g. `red` for `rgb(255, 0, 0)`.
+pub fn name(color: Color) -> Option<&'static str> {
+    if color.a != 255 {
+        return None;
+    }
+    NAMED_COLORS
+        .iter()
+        .filter(|&&(_, r, g, b)| (r, g, b) == (color.r, color.g, color.b))
+        .map(|&(name, ..)| name)
+        .min_by_key(|name| name.len())
+}
+
+/// Whether `name` is one of the color functions `from_function` converts.
+pub fn is_color_function(name: &str) -> bool {
+    matches!(name, "rgb" | "rgba" | "hsl" | "hsla" | "hwb")
+}
+
+/// Converts `rgb()`, `rgba()`, `hsl()`, `hsla()` and `hwb()` in either the legacy
+/// comma-separated or the modern space-separated syntax. Out-of-range channels
+/// are clamped; `None` means the arguments are not valid for the function.
+pub fn from_function(name: &str, arguments: &[Value]) -> Option<Color> {
+    let (channels, alpha, legacy) = components(arguments)?;
+    if legacy && !valid_legacy_components(name, &channels, alpha.as_ref()) {
+        return None;
+    }
+    let alpha = match alpha {
+        Some(value) => alpha_value(&value)?,
+        None => 1.0,
+    };
+    let (r, g, b) = match (name, &channels[..]) {
+        ("rgb", [r, g, b]) | ("rgba", [r, g, b]) => {
+            (rgb_channel(r)?, rgb_channel(g)?, rgb_channel(b)?)
+        }
+        ("hsl", [h, s, l]) | ("hsla", [h, s, l]) => {
+            hsl_to_rgb(hue(h)?, percentage(s)?, percentage(l)?)
+        }
+        ("hwb", [h, w, b]) => hwb_to_rgb(hue(h)?, percentage(w)?, percentage(b)?),
+        _ => return None,
+    };
+    Some(Color {
+        r: r.round() as u8,
+        g: g.round() as u8,
+        b: b.round() as u8,
+        a: (alpha * 255.0).round() as u8,
+    })
+}
+
+// 引数を色成分とアルファ値に分け、旧構文かどうかも返す
+// 旧構文: rgb(255, 0, 0, 0.5)  新構文: rgb(255 0 0 / 50%)
+fn components(arguments: &[Value]) -> Option<(Vec<Value>, Option<Value>, bool)> {
+    match *arguments {
+        [Value::List(ref values)] => {
+            let mut parts = values.split(|value| *value == Value::Delimiter('/'));
+            let channels = parts.next()?.to_vec();
+            let alpha = match parts.next() {
+                Some([alpha]) => Some(alpha.clone()),
+                Some(_) => return None,
+                None => None,
+            };
+            if parts.next().is_some() {
+                return None;
+            }
+            Some((channels, alpha, false))
+        }
+        [ref r, ref g, ref b] => Some((vec![r.clone(), g.clone(), b.clone()], None, true)),
+        [ref r, ref g, ref b, ref a] => {
+            Some((vec![r.clone(), g.clone(), b.clone()], Some(a.clone()), true))
+        }
+        _ => None,
+    }
+}
+
+// 旧構文では none を使えず、rgb() の成分は数値か百分率のどちらかに揃え、
+// hsl() の彩度と明度は百分率で書く。hwb() には旧構文がない
+fn valid_legacy_components(name: &str, channels: &[Value], alpha: Option<&Value>) -> bool {
+    if channels.iter().chain(alpha).any(is_none) {
+        return false;
+    }
+    let is_percentage = |value: &Value| matches!(*value, Value::Length(_, Unit::Percent));
+    match name {
+        "rgb" | "rgba" => {
+            channels.iter().all(is_percentage)
+                || channels
+                    .iter()
+                    .all(|value| matches!(*value, Value::Number(_)))
+        }
+        "hsl" | "hsla" => channels.iter().skip(1).all(is_percentage),
+        _ => false,
+    }
+}
+
+fn is_none(value: &Value) -> bool {
+    matches!(*value, Value::Keyword(ref keyword) if keyword == "none")
+}
+
+// 0.0〜255.0
+fn rgb_channel(value: &Value) -> Option<f32> {
+    match *value {
+        Value::Number(n) => Some(n.clamp(0.0, 255.0)),
+        Value::Length(n, Unit::Percent) => Some((n * 2.55).clamp(0.0, 255.0)),
+        _ if is_none(value) => Some(0.0),
+        _ => None,
+    }
+}
+
+// 0.0〜1.0
+fn percentage(value: &Value) -> Option<f32> {
+    match *value {
+        Value::Length(n, Unit::Percent) | Value::Number(n) => Some((n / 100.0).clamp(0.0, 1.0)),
+        _ if is_none(value) => Some(0.0),
+        _ => None,
+    }
+}
+
+fn alpha_value(value: &Value) -> Option<f32> {
+    match *value {
+        Value::Number(n) => Some(n.clamp(0.0, 1.0)),
+        Value::Length(n, Unit::Percent) => Some((n / 100.0).clamp(0.0, 1.0)),
+        _ if is_none(value) => Some(0.0),
+        _ => None,
+    }
+}
+
+// 度数 0.0〜360.0
+fn hue(value: &Value) -> Option<f32> {
+    match *value {
+        Value::Number(degrees) | Value::Angle(degrees) => Some(degrees.rem_euclid(360.0)),
+        _ if is_none(value) => Some(0.0),
+        _ => None,
+    }
+}
+
+// https://www.w3.org/TR/css-color-4/#hsl-to-rgb
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> (f32, f32, f32) {
+    let f = |n: f32| {
+        let k = (n + hue / 30.0) % 12.0;
+        let a = saturation * lightness.min(1.0 - lightness);
+        (lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)) * 255.0
+    };
+    (f(0.0), f(8.0), f(4.0))
+}
+
+// https://www.w3.org/TR/css-color-4/#hwb-to-rgb
+fn hwb_to_rgb(hue: f32, whiteness: f32, blackness: f32) -> (f32, f32, f32) {
+    if whiteness + blackness >= 1.0 {
+        let gray = whiteness / (whiteness + blackness) * 255.0;
+        return (gray, gray, gray);
+    }
+    let (r, g, b) = hsl_to_rgb(hue, 1.0, 0.5);
+    let scale = |c: f32| c * (1.0 - whiteness - blackness) + whiteness * 255.0;
+    (scale(r), scale(g), scale(b))
+}
+
+// 名前順にソート済み（binary_searchで引く）
+const NAMED_COLORS: [(&str, u8, u8, u8); 148] = [
+    ("aliceblue", 0xf0, 0xf8, 0xff),
+    ("antiquewhite", 0xfa, 0xeb, 0xd7),
+    ("aqua", 0x00, 0xff, 0xff),
+    ("aquamarine", 0x7f, 0xff, 0xd4),
+    ("azure", 0xf0, 0xff, 0xff),
+    ("beige", 0xf5, 0xf5, 0xdc),
+    ("bisque", 0xff, 0xe4, 0xc4),
+    ("black", 0x00, 0x00, 0x00),
+    ("blanchedalmond", 0xff, 0xeb, 0xcd),
+    ("blue", 0x00, 0x00, 0xff),
+    ("blueviolet", 0x8a, 0x2b, 0xe2),
+    ("brown", 0xa5, 0x2a, 0x2a),
+    ("burlywood", 0xde, 0xb8, 0x87),
+    ("cadetblue", 0x5f, 0x9e, 0xa0),
+    ("chartreuse", 0x7f, 0xff, 0x00),
+    ("chocolate", 0xd2, 0x69, 0x1e),
+    ("coral", 0xff, 0x7f, 0x50),
+    ("cornflowerblue", 0x64, 0x95, 0xed),
+    ("cornsilk", 0xff, 0xf8, 0xdc),
+    ("crimson", 0xdc, 0x14, 0x3c),
+    ("cyan", 0x00, 0xff, 0xff),
+    ("darkblue", 0x00, 0x00, 0x8b),
+    ("darkcyan", 0x00, 0x8b, 0x8b),
+    ("darkgoldenrod", 0xb8, 0x86, 0x0b),
+    ("darkgray", 0xa9, 0xa9, 0xa9),
+    ("darkgreen", 0x00, 0x64, 0x00),
+    ("darkgrey", 0xa9, 0xa9, 0xa9),
+    ("darkkhaki", 0xbd, 0xb7, 0x6b),
+    ("darkmagenta", 0x8b, 0x00, 0x8b),
+    ("darkolivegreen", 0x55, 0x6b, 0x2f),
+    ("darkorange", 0xff, 0x8c, 0x00),
+    ("darkorchid", 0x99, 0x32, 0xcc),
+    ("darkred", 0x8b, 0x00, 0x00),
+    ("darksalmon", 0xe9, 0x96, 0x7a),
+    ("darkseagreen", 0x8f, 0xbc, 0x8f),
+    ("darkslateblue", 0x48, 0x3d, 0x8b),
+    ("darkslategray", 0x2f, 0x4f, 0x4f),
+    ("darkslategrey", 0x2f, 0x4f, 0x4f),
+    ("darkturquoise", 0x00, 0xce, 0xd1),
+    ("darkviolet", 0x94, 0x00, 0xd3),
+    ("deeppink", 0xff, 0x14, 0x93),
+    ("deepskyblue", 0x00, 0xbf, 0xff),
+    ("dimgray", 0x69, 0x69, 0x69),
+    ("dimgrey", 0x69, 0x69, 0x69),
+    ("dodgerblue", 0x1e, 0x90, 0xff),
+    ("firebrick", 0xb2, 0x22, 0x22),
+    ("floralwhite", 0xff, 0xfa, 0xf0),
+    ("forestgreen", 0x22, 0x8b, 0x22),
+    ("fuchsia", 0xff, 0x00, 0xff),
+    ("gainsboro", 0xdc, 0xdc, 0xdc),
+    ("ghostwhite", 0xf8, 0xf8, 0xff),
+    ("gold", 0xff, 0xd7, 0x00),
+    ("goldenrod", 0xda, 0xa5, 0x20),
+    ("gray", 0x80, 0x80, 0x80),
+    ("green", 0x00, 0x80, 0x00),
+    ("greenyellow", 0xad, 0xff, 0x2f),
+    ("grey", 0x80, 0x80, 0x80),
+    ("honeydew", 0xf0, 0xff, 0xf0),
+    ("hotpink", 0xff, 0x69, 0xb4),
+    ("indianred", 0xcd, 0x5c, 0x5c),
+    ("indigo", 0x4b, 0x00, 0x82),
+    ("ivory", 0xff, 0xff, 0xf0),
+    ("khaki", 0xf0, 0xe6, 0x8c),
+    ("lavender", 0xe6, 0xe6, 0xfa),
+    ("lavenderblush", 0xff, 0xf0, 0xf5),
+    ("lawngreen", 0x7c, 0xfc, 0x00),
+    ("lemonchiffon", 0xff, 0xfa, 0xcd),
+    ("lightblue", 0xad, 0xd8, 0xe6),
+    ("lightcoral", 0xf0, 0x80, 0x80),
+    ("lightcyan", 0xe0, 0xff, 0xff),
+    ("lightgoldenrodyellow", 0xfa, 0xfa, 0xd2),
+    ("lightgray", 0xd3, 0xd3, 0xd3),
+    ("lightgreen", 0x90, 0xee, 0x90),
+    ("lightgrey", 0xd3, 0xd3, 0xd3),
+    ("lightpink", 0xff, 0xb6, 0xc1),
+    ("lightsalmon", 0xff, 0xa0, 0x7a),
+    ("lightseagreen", 0x20, 0xb2, 0xaa),
+    ("lightskyblue", 0x87, 0xce, 0xfa),
+    ("lightslategray", 0x77, 0x88, 0x99),
+    ("lightslategrey", 0x77, 0x88, 0x99),
+    ("lightsteelblue", 0xb0, 0xc4, 0xde),
+    ("lightyellow", 0xff, 0xff, 0xe0),
+    ("lime", 0x00, 0xff, 0x00),
+    ("limegreen", 0x32, 0xcd, 0x32),
+    ("linen", 0xfa, 0xf0, 0xe6),
+    ("magenta", 0xff, 0x00, 0xff),
+    ("maroon", 0x80, 0x00, 0x00),
+    ("mediumaquamarine", 0x66, 0xcd, 0xaa),
+    ("mediumblue", 0x00, 0x00, 0xcd),
+    ("mediumorchid", 0xba, 0x55, 0xd3),
+    ("mediumpurple", 0x93, 0x70, 0xdb),
+    ("mediumseagreen", 0x3c, 0xb3, 0x71),
+    ("mediumslateblue", 0x7b, 0x68, 0xee),
+    ("mediumspringgreen", 0x00, 0xfa, 0x9a),
+    ("mediumturquoise", 0x48, 0xd1, 0xcc),
+    ("mediumvioletred", 0xc7, 0x15, 0x85),
+    ("midnightblue", 0x19, 0x19, 0x70),
+    ("mintcream", 0xf5, 0xff, 0xfa),
+    ("mistyrose", 0xff, 0xe4, 0xe1),
+    ("moccasin", 0xff, 0xe4, 0xb5),
+    ("navajowhite", 0xff, 0xde, 0xad),
+    ("navy", 0x00, 0x00, 0x80),
+    ("oldlace", 0xfd, 0xf5, 0xe6),
+    ("olive", 0x80, 0x80, 0x00),
+    ("olivedrab", 0x6b, 0x8e, 0x23),
+    ("orange", 0xff, 0xa5, 0x00),
+    ("orangered", 0xff, 0x45, 0x00),
+    ("orchid", 0xda, 0x70, 0xd6),
+    ("palegoldenrod", 0xee, 0xe8, 0xaa),
+    ("palegreen", 0x98, 0xfb, 0x98),
+    ("paleturquoise", 0xaf, 0xee, 0xee),
+    ("palevioletred", 0xdb, 0x70, 0x93),
+    ("papayawhip", 0xff, 0xef, 0xd5),
+    ("peachpuff", 0xff, 0xda, 0xb9),
+    ("peru", 0xcd, 0x85, 0x3f),
+    ("pink", 0xff, 0xc0, 0xcb),
+    ("plum", 0xdd, 0xa0, 0xdd),
+    ("powderblue", 0xb0, 0xe0, 0xe6),
+    ("purple", 0x80, 0x00, 0x80),
+    ("rebeccapurple", 0x66, 0x33, 0x99),
+    ("red", 0xff, 0x00, 0x00),
+    ("rosybrown", 0xbc, 0x8f, 0x8f),
+    ("royalblue", 0x41, 0x69, 0xe1),
+    ("saddlebrown", 0x8b, 0x45, 0x13),
+    ("salmon", 0xfa, 0x80, 0x72),
+    ("sandybrown", 0xf4, 0xa4, 0x60),
+    ("seagreen", 0x2e, 0x8b, 0x57),
+    ("seashell", 0xff, 0xf5, 0xee),
+    ("sienna", 0xa0, 0x52, 0x2d),
+    ("silver", 0xc0, 0xc0, 0xc0),
+    ("skyblue", 0x87, 0xce, 0xeb),
+    ("slateblue", 0x6a, 0x5a, 0xcd),
+    ("slategray", 0x70, 0x80, 0x90),
+    ("slategrey", 0x70, 0x80, 0x90),
+    ("snow", 0xff, 0xfa, 0xfa),
+    ("springgreen", 0x00, 0xff, 0x7f),
+    ("steelblue", 0x46, 0x82, 0xb4),
+    ("tan", 0xd2, 0xb4, 0x8c),
+    ("teal", 0x00, 0x80, 0x80),
+    ("thistle", 0xd8, 0xbf, 0xd8),
+    ("tomato", 0xff, 0x63, 0x47),
+    ("turquoise", 0x40, 0xe0, 0xd0),
+    ("violet", 0xee, 0x82, 0xee),
+    ("wheat", 0xf5, 0xde, 0xb3),
+    ("white", 0xff, 0xff, 0xff),
+    ("whitesmoke", 0xf5, 0xf5, 0xf5),
+    ("yellow", 0xff, 0xff, 0x00),
+    ("yellowgreen", 0x9a, 0xcd, 0x32),
+];
+
+#[test]
+fn test_color_syntax() {
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+
+    let rgba = |r, g, b, a| Value::ColorValue(Color { r, g, b, a });
+    let stylesheet = new_css_parser(
+        "a { a: #f80; b: #11223380; c: rgb(255 0 0 / 50%); d: rgba(0, 128, 255, 0.25); \
+         e: hsl(120deg 100% 25%); f: hwb(0.5turn 0% 0%); g: hsl(0, 0%, 100%) }"
+            .to_string(),
+    )
+    .parse();
+    let values: Vec<Value> = stylesheet.rules[0]
+        .as_style()
+        .unwrap()
+        .declarations
+        .iter()
+        .map(|d| d.value.clone())
+        .collect();
+    assert_eq!(
+        values,
+        vec![
+            rgba(255, 136, 0, 255),
+            rgba(17, 34, 51, 128),
+            rgba(255, 0, 0, 128),
+            rgba(0, 128, 255, 64),
+            rgba(0, 128, 0, 255),
+            rgba(0, 255, 255, 255),
+            rgba(255, 255, 255, 255),
+        ]
+    );
+    assert_eq!(
+        from_keyword("RebeccaPurple"),
+        Some(Color {
+            r: 0x66,
+            g: 0x33,
+            b: 0x99,
+            a: 255
+        })
+    );
+    assert_eq!(from_keyword("transparent").map(|c| c.a), Some(0));
+}
+
+#[test]
+fn test_invalid_colors() {
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+
+    let (stylesheet, errors) = new_css_parser(
+        "a { a: rgb(10%,20,30); b: rgb(10 20 30 40); c: rgb(1e40, 0, 0); \
+         d: hsl(1e40 100% 50%); e: rgb(none, 0, 0); f: hwb(0, 0%, 0%); \
+         g: rgb(10% 20 30); h: #12345; i: hsl(120, 50, 50%); j: rgb(300 -20 0 / 2) }"
+            .to_string(),
+    )
+    .parse_with_errors();
+    let declarations = &stylesheet.rules[0].as_style().unwrap().declarations;
+    let names: Vec<&str> = declarations.iter().map(|d| &*d.name).collect();
+    // 無効な色を含む宣言は捨てられ、範囲外の成分は切り詰められる
+    assert_eq!(names, vec!["c", "d", "g", "j"]);
+    assert_eq!(errors.len(), 6);
+    let rgba = |r, g, b, a| Value::ColorValue(Color { r, g, b, a });
+    assert_eq!(declarations[0].value, rgba(255, 0, 0, 255));
+    assert!(matches!(declarations[1].value, Value::ColorValue(_)));
+    assert_eq!(declarations[2].value, rgba(26, 20, 30, 255));
+    assert_eq!(declarations[3].value, rgba(255, 0, 0, 255));
+}
@@ -1,71 +1,979 @@
 use parser::interface::DefaultParserTrait;
-use parser::Parser;
+use parser::{ParseError, Parser};
+use style_sheet::color;
 use style_sheet::declaration::Declaration;
-use style_sheet::interface::{CSSParserTrait, SelectorTrait};
-use style_sheet::rule::Rule;
-use style_sheet::selector::Selector;
+use style_sheet::interface::{CSSParserTrait, SelectorTrait, ValueTrait};
+use style_sheet::layer::LayerName;
+use style_sheet::math::{MathExpression, MATH_FUNCTIONS};
+use style_sheet::media_query::{
+    Comparison, MediaCondition, MediaFeature, MediaQuery, MediaQueryList, MediaType, MediaValue,
+};
+use style_sheet::property::CSS_WIDE_KEYWORDS;
+use style_sheet::pseudo_class::PseudoClass;
+use style_sheet::pseudo_element::PseudoElement;
+use style_sheet::rule::{CSSRule, ImportRule, LayerRule, MediaRule, Rule, SupportsRule};
+use style_sheet::selector::{Combinator, RelativeSelector, Selector};
+use style_sheet::shorthand::expand_shorthand;
 use style_sheet::simple_selector::SimpleSelector;
-use style_sheet::style_sheet::Stylesheet;
-use style_sheet::util::{Color, Unit, Unit::*, Value};
+use style_sheet::source_location::{resolve_lines, SourceLocation};
+use style_sheet::style_sheet::{Origin, Stylesheet};
+use style_sheet::supports::SupportsCondition;
+use style_sheet::util::{Unit, Value};
+use style_sheet::variable::{contains_var, is_custom_property};
 
 pub fn new_css_parser(source: String) -> impl CSSParserTrait {
-    return Parser {
-        position: 0,
-        source,
-    };
+    Parser::new(blank_out_comments(source))
+}
+
+/*
+ コメントを同じバイト数の空白に置き換える
+ 改行は残すので、ソース上の位置は変わらない
+*/
+fn blank_out_comments(source: String) -> String {
+    if !source.contains("/*") {
+        return source;
+    }
+    let mut result = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(open_quote) => {
+                result.push(c);
+                if c == '\\' {
+                    result.extend(chars.next());
+                } else if c == open_quote {
+                    quote = None;
+                }
+            }
+            None if c == '/' && chars.peek() == Some(&'*') => {
+                chars.next();
+                result.push_str("  ");
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    match c {
+                        '\n' => result.push('\n'),
+                        _ => result.extend(std::iter::repeat_n(' ', c.len_utf8())),
+                    }
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            None => {
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+                result.push(c);
+            }
+        }
+    }
+    result
 }
 
 impl CSSParserTrait for Parser {
     fn parse(&mut self) -> Stylesheet {
-        let mut parser = Parser {
-            position: 0,
-            source: self.source.clone(),
+        self.parse_with_errors().0
+    }
+
+    /// `parse`, also returning the invalid rules and declarations that were dropped.
+    fn parse_with_errors(&mut self) -> (Stylesheet, Vec<ParseError>) {
+        let mut parser = Parser::new(self.source.clone());
+        let mut rules = parser.parse_rules();
+        // 対応する `{` のない `}` は読み飛ばして続ける
+        while !parser.eof() {
+            let error = parser.unexpected::<()>("stylesheet").unwrap_err();
+            parser.errors.push(error);
+            parser.consume_char().unwrap();
+            rules.extend(parser.parse_rules());
+        }
+        resolve_lines(&mut rules, &self.source);
+        let stylesheet = Stylesheet {
+            rules,
+            origin: Origin::Author,
         };
-        Stylesheet {
-            rules: parser.parse_rules(),
+        (stylesheet, parser.errors)
+    }
+
+    /// An error at the current position.
+    fn error<T>(&self, message: String) -> Result<T, ParseError> {
+        Err(ParseError {
+            offset: self.position,
+            message,
+        })
+    }
+
+    /// An error for the next character, which cannot start anything in `context`.
+    fn unexpected<T>(&self, context: &str) -> Result<T, ParseError> {
+        match self.next_char() {
+            Ok(c) => self.error(format!("unexpected {} in {}", c, context)),
+            Err(_) => self.error(format!("unexpected end of input in {}", context)),
+        }
+    }
+
+    /// Consume `expected` if it is the next character.
+    fn expect_char(&mut self, expected: char, context: &str) -> Result<(), ParseError> {
+        if self.next_char().ok() == Some(expected) {
+            self.consume_char().unwrap();
+            Ok(())
+        } else {
+            self.unexpected(context)
+        }
+    }
+
+    /// The location of the next character. Only the byte offset is known while
+    /// parsing; `parse` fills in the line and column.
+    fn source_location(&self) -> SourceLocation {
+        SourceLocation {
+            offset: self.position,
+            ..Default::default()
         }
     }
 
-    fn parse_rules(&mut self) -> Vec<Rule> {
+    fn parse_rules(&mut self) -> Vec<CSSRule> {
         let mut rules = Vec::new();
         loop {
             self.consume_whitespace();
-            if self.eof() {
+            // 入れ子になったルールは `}` で終わる
+            if self.eof() || self.next_char().unwrap() == '}' {
                 break;
             }
-            rules.push(self.parse_rule());
+            if self.next_char().unwrap() == '@' {
+                let start = self.position;
+                match self.parse_at_rule() {
+                    // @import の前に書けるのは @import と @layer 文だけ
+                    Some(CSSRule::Import(_))
+                        if rules.iter().any(|rule| {
+                            !matches!(*rule, CSSRule::Import(_) | CSSRule::LayerStatement(_))
+                        }) =>
+                    {
+                        self.errors.push(ParseError {
+                            offset: start,
+                            message: "@import after other rules".to_string(),
+                        });
+                    }
+                    rule => rules.extend(rule),
+                }
+            } else {
+                rules.extend(self.parse_rule());
+            }
         }
         rules
     }
 
+    /// Parse an at-rule such as `@media`. Unsupported at-rules are skipped.
+    fn parse_at_rule(&mut self) -> Option<CSSRule> {
+        assert_eq!(self.consume_char().unwrap(), '@');
+        let name = self.parse_identifier().to_ascii_lowercase();
+        self.consume_whitespace();
+        match &*name {
+            "media" => {
+                let queries = self.parse_media_query_list();
+                match self.parse_nested_rules() {
+                    Ok(rules) => Some(CSSRule::Media(MediaRule { queries, rules })),
+                    Err(error) => self.invalid_at_rule(error),
+                }
+            }
+            "supports" => {
+                let rule = self.parse_supports_condition().and_then(|condition| {
+                    let rules = self.parse_nested_rules()?;
+                    Ok(CSSRule::Supports(SupportsRule { condition, rules }))
+                });
+                match rule {
+                    Ok(rule) => Some(rule),
+                    Err(error) => self.invalid_at_rule(error),
+                }
+            }
+            "layer" => match self.parse_layer_rule() {
+                Ok(rule) => Some(rule),
+                Err(error) => self.invalid_at_rule(error),
+            },
+            "import" => match self.parse_import_rule() {
+                Ok(import_rule) => Some(CSSRule::Import(import_rule)),
+                Err(error) => self.invalid_at_rule(error),
+            },
+            _ => {
+                self.skip_at_rule();
+                None
+            }
+        }
+    }
+
+    /// Record an error in an at-rule and skip the rest of it.
+    fn invalid_at_rule(&mut self, error: ParseError) -> Option<CSSRule> {
+        self.errors.push(error);
+        self.skip_at_rule();
+        None
+    }
+
+    /// Parse the `{ <rules> }` block of a conditional group rule.
+    /// A block left open at the end of the source is closed there.
+    fn parse_nested_rules(&mut self) -> Result<Vec<CSSRule>, ParseError> {
+        self.consume_whitespace();
+        self.expect_char('{', "at-rule")?;
+        let rules = self.parse_rules();
+        if !self.eof() {
+            self.consume_char().unwrap();
+        }
+        Ok(rules)
+    }
+
+    /// Parse the prelude of `@import` up to and including its `;`.
+    fn parse_import_rule(&mut self) -> Result<ImportRule, ParseError> {
+        let url = match self.next_char() {
+            Ok('"' | '\'') => self.parse_string(),
+            _ if self.consume_keyword("url") => self.parse_url()?,
+            _ => return self.unexpected("@import"),
+        };
+        self.consume_whitespace();
+        let layer = if self.consume_keyword("layer") {
+            if self.next_char().ok() == Some('(') {
+                self.consume_char().unwrap();
+                self.consume_whitespace();
+                let name = self.parse_layer_name()?;
+                self.consume_whitespace();
+                self.expect_char(')', "@import")?;
+                Some(name)
+            } else {
+                Some(LayerName::default())
+            }
+        } else {
+            None
+        };
+        self.consume_whitespace();
+        let supports = if self.consume_keyword("supports") {
+            if self.next_char().ok() != Some('(') {
+                return self.unexpected("@import");
+            }
+            Some(self.parse_supports_in_parens()?)
+        } else {
+            None
+        };
+        self.consume_whitespace();
+        let queries = self.parse_media_query_list();
+        match self.next_char() {
+            Ok(';') => {
+                self.consume_char().unwrap();
+            }
+            Err(_) => {}
+            Ok(_) => return self.unexpected("@import"),
+        }
+        Ok(ImportRule {
+            url,
+            layer,
+            supports,
+            queries,
+            stylesheet: None,
+        })
+    }
+
+    /// Parse `@layer` as a block with an optional name, or as a statement
+    /// listing the names to declare.
+    fn parse_layer_rule(&mut self) -> Result<CSSRule, ParseError> {
+        let mut names = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if !names.is_empty() {
+                match self.next_char() {
+                    Ok(',') => {
+                        self.consume_char().unwrap();
+                        self.consume_whitespace();
+                    }
+                    Ok('{' | ';') | Err(_) => break,
+                    _ => return self.unexpected("@layer"),
+                }
+            } else if matches!(self.next_char(), Ok('{' | ';') | Err(_)) {
+                break;
+            }
+            names.push(self.parse_layer_name()?);
+        }
+        match self.next_char() {
+            Ok('{') if names.len() <= 1 => Ok(CSSRule::Layer(LayerRule {
+                name: names.pop().unwrap_or_default(),
+                rules: self.parse_nested_rules()?,
+            })),
+            Ok('{') => self.error("a @layer block takes at most one name".to_string()),
+            _ if names.is_empty() => self.unexpected("@layer"),
+            _ => {
+                if !self.eof() {
+                    self.consume_char().unwrap();
+                }
+                Ok(CSSRule::LayerStatement(names))
+            }
+        }
+    }
+
+    /// Parse a dotted layer name such as `framework.reset`.
+    fn parse_layer_name(&mut self) -> Result<LayerName, ParseError> {
+        let mut segments = Vec::new();
+        loop {
+            let segment = self.parse_identifier();
+            if segment.is_empty() {
+                return self.unexpected("layer name");
+            }
+            segments.push(segment);
+            if self.next_char().ok() != Some('.') {
+                return Ok(LayerName(segments));
+            }
+            self.consume_char().unwrap();
+        }
+    }
+
+    /// Skip the rest of an at-rule: up to a `;` or past its `{ ... }` block.
+    fn skip_at_rule(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char().unwrap() {
+                '"' | '\'' => {
+                    self.parse_string();
+                    continue;
+                }
+                ';' if depth == 0 => {
+                    self.consume_char().unwrap();
+                    break;
+                }
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth <= 0 {
+                        self.consume_char().unwrap();
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            self.consume_char().unwrap();
+        }
+    }
+
+    /// Consume `keyword` if it is the next identifier (ASCII case-insensitive).
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        let start = self.position;
+        if self.parse_identifier().eq_ignore_ascii_case(keyword) {
+            true
+        } else {
+            self.position = start;
+            false
+        }
+    }
+
     // Parse a rule set: `<selectors> { <declarations> }`.
-    fn parse_rule(&mut self) -> Rule {
-        Rule {
-            selectors: self.parse_selector(),
-            declarations: self.parse_declarations(),
+    // Nested rules are flattened and follow the rule itself.
+    fn parse_rule(&mut self) -> Vec<CSSRule> {
+        match self.parse_selector() {
+            Ok(selectors) => self.parse_style_block(&selectors),
+            Err(error) => {
+                // セレクターが一つでも無効なら、ルール全体を捨てる
+                self.errors.push(error);
+                self.skip_rule();
+                Vec::new()
+            }
+        }
+    }
+
+    /// Skip the rest of an invalid style rule, up to the end of its `{ ... }` block.
+    /// Stops before a `}` that closes the enclosing block.
+    fn skip_rule(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char().unwrap() {
+                '"' | '\'' => {
+                    self.parse_string();
+                    continue;
+                }
+                '{' => depth += 1,
+                '}' if depth == 0 => break,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.consume_char().unwrap();
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            self.consume_char().unwrap();
+        }
+    }
+
+    /// Parse the `{ ... }` block of a style rule for `selectors`. Declarations form
+    /// a rule for `selectors`; nested style rules and conditional group rules become
+    /// rules of their own, in order of appearance.
+    fn parse_style_block(&mut self, selectors: &[Selector]) -> Vec<CSSRule> {
+        assert_eq!(self.consume_char().unwrap(), '{');
+        let mut rules = Vec::new();
+        let mut declarations = Vec::new();
+        loop {
+            self.consume_whitespace();
+            match self.next_char() {
+                // 閉じていないブロックはソースの終わりで閉じる
+                Err(_) => break,
+                Ok('}') => {
+                    self.consume_char().unwrap();
+                    break;
+                }
+                Ok('@') => {
+                    flush_declarations(&mut rules, &mut declarations, selectors);
+                    rules.extend(self.parse_nested_group_rule(selectors));
+                }
+                _ if self.at_nested_rule() => {
+                    flush_declarations(&mut rules, &mut declarations, selectors);
+                    match self.parse_nested_selector_list(selectors) {
+                        Ok(nested) => rules.extend(self.parse_style_block(&nested)),
+                        Err(error) => {
+                            self.errors.push(error);
+                            self.skip_rule();
+                        }
+                    }
+                }
+                _ => {
+                    let start = self.position;
+                    match self.parse_declaration() {
+                        Ok(declaration) => declarations.extend(expand_shorthand(declaration)),
+                        Err(error) => {
+                            // 無効な宣言だけを捨てて、次の宣言から続ける
+                            self.errors.push(error);
+                            self.position = start;
+                            self.skip_declaration();
+                        }
+                    }
+                }
+            }
+        }
+        flush_declarations(&mut rules, &mut declarations, selectors);
+        // 空のブロックは宣言のないルールとして残す。入れ子のルールしかない親のルールは作らない
+        if rules.is_empty() {
+            rules.push(CSSRule::Style(Rule {
+                selectors: selectors.to_vec(),
+                declarations: Vec::new(),
+            }));
+        }
+        rules
+    }
+
+    /// Parse `@media`, `@supports` or `@layer` nested in a style rule; its block
+    /// holds declarations for the parent selectors as well as nested rules.
+    fn parse_nested_group_rule(&mut self, selectors: &[Selector]) -> Option<CSSRule> {
+        assert_eq!(self.consume_char().unwrap(), '@');
+        let name = self.parse_identifier().to_ascii_lowercase();
+        self.consume_whitespace();
+        match &*name {
+            "media" => {
+                let queries = self.parse_media_query_list();
+                if self.next_char().ok() != Some('{') {
+                    let error = self.unexpected::<()>("@media").unwrap_err();
+                    return self.invalid_at_rule(error);
+                }
+                Some(CSSRule::Media(MediaRule {
+                    queries,
+                    rules: self.parse_style_block(selectors),
+                }))
+            }
+            "supports" => {
+                let condition = self.parse_supports_condition().and_then(|condition| {
+                    self.consume_whitespace();
+                    match self.next_char() {
+                        Ok('{') => Ok(condition),
+                        _ => self.unexpected("@supports"),
+                    }
+                });
+                match condition {
+                    Ok(condition) => Some(CSSRule::Supports(SupportsRule {
+                        condition,
+                        rules: self.parse_style_block(selectors),
+                    })),
+                    Err(error) => self.invalid_at_rule(error),
+                }
+            }
+            "layer" => {
+                // 入れ子の @layer はブロックだけ
+                let name = match self.next_char() {
+                    Ok('{') => Ok(LayerName::default()),
+                    _ => self.parse_layer_name().and_then(|name| {
+                        self.consume_whitespace();
+                        match self.next_char() {
+                            Ok('{') => Ok(name),
+                            _ => self.unexpected("@layer"),
+                        }
+                    }),
+                };
+                match name {
+                    Ok(name) => Some(CSSRule::Layer(LayerRule {
+                        name,
+                        rules: self.parse_style_block(selectors),
+                    })),
+                    Err(error) => self.invalid_at_rule(error),
+                }
+            }
+            _ => {
+                self.skip_at_rule();
+                None
+            }
+        }
+    }
+
+    /// Whether the next item of a style block is a nested rule rather than a
+    /// declaration, i.e. reaches a `{` before a `;` or `}`.
+    fn at_nested_rule(&mut self) -> bool {
+        if self.starts_with("--") {
+            return false;
+        }
+        let start = self.position;
+        let mut depth = 0;
+        let mut nested = false;
+        while !self.eof() {
+            match self.next_char().unwrap() {
+                '"' | '\'' => {
+                    self.parse_string();
+                    continue;
+                }
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth -= 1,
+                '{' if depth == 0 => {
+                    nested = true;
+                    break;
+                }
+                ';' | '}' if depth == 0 => break,
+                _ => {}
+            }
+            self.consume_char().unwrap();
+        }
+        self.position = start;
+        nested
+    }
+
+    /// Parse the selector list of a nested rule and resolve it against `parents`.
+    /// A selector without `&`, or one starting with a combinator, is relative to
+    /// the parent: `a` means `& a` and `> a` means `& > a`.
+    fn parse_nested_selector_list(
+        &mut self,
+        parents: &[Selector],
+    ) -> Result<Vec<Selector>, ParseError> {
+        let relative_selectors = self.parse_relative_selector_list()?;
+        if self.next_char().ok() != Some('{') {
+            return self.unexpected("selector list");
+        }
+        let mut selectors: Vec<Selector> = relative_selectors
+            .into_iter()
+            .map(|relative| {
+                let explicit = relative.selector.contains_nesting()
+                    && relative.combinator == Combinator::Descendant;
+                let mut selector = if explicit {
+                    relative.selector
+                } else {
+                    // 補った `&` は続くセレクターの位置にあるとみなす
+                    let nesting = SimpleSelector {
+                        nesting: true,
+                        location: relative.selector.location().clone(),
+                        ..Default::default()
+                    };
+                    relative.selector.prepend(nesting, relative.combinator)
+                };
+                selector.resolve_nesting(parents);
+                selector
+            })
+            .collect();
+        selectors.sort_by_key(|s| std::cmp::Reverse(s.specificity()));
+        Ok(selectors)
+    }
+
+    // Methods for parsing media queries:
+
+    /// Parse a comma-separated `<media-query-list>` up to the `{` or `;`.
+    /// A malformed query becomes `not all`; the rest of the list is kept.
+    fn parse_media_query_list(&mut self) -> MediaQueryList {
+        let mut queries = Vec::new();
+        loop {
+            self.consume_whitespace();
+            match self.next_char() {
+                Err(_) | Ok('{') | Ok(';') => break,
+                Ok(',') => {
+                    self.consume_char().unwrap();
+                }
+                _ => {
+                    let start = self.position;
+                    let query = self.parse_media_query().and_then(|query| {
+                        self.consume_whitespace();
+                        match self.next_char() {
+                            Err(_) | Ok(',') | Ok('{') | Ok(';') => Ok(query),
+                            _ => self.unexpected("media query"),
+                        }
+                    });
+                    queries.push(query.unwrap_or_else(|error| {
+                        self.errors.push(error);
+                        self.position = start;
+                        self.skip_media_query();
+                        MediaQuery::not_all()
+                    }));
+                }
+            }
+        }
+        MediaQueryList(queries)
+    }
+
+    /// Skip a malformed media query, up to the `,`, `{` or `;` that ends it.
+    fn skip_media_query(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char().unwrap() {
+                ',' | '{' | ';' if depth == 0 => break,
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            self.consume_char().unwrap();
+        }
+    }
+
+    /// `[not | only]? <media-type> [and <media-condition>]?` or `<media-condition>`.
+    fn parse_media_query(&mut self) -> Result<MediaQuery, ParseError> {
+        let start = self.position;
+        let mut negated = false;
+        let mut word = self.parse_identifier();
+        self.consume_whitespace();
+        if word.eq_ignore_ascii_case("not") || word.eq_ignore_ascii_case("only") {
+            if self.next_char().ok() == Some('(') {
+                // `not (width)` はメディア条件
+                word.clear();
+            } else {
+                negated = word.eq_ignore_ascii_case("not");
+                word = self.parse_identifier();
+                if word.is_empty() {
+                    return self.unexpected("media query");
+                }
+            }
+        }
+        if word.is_empty() {
+            self.position = start;
+            return Ok(MediaQuery {
+                negated: false,
+                media_type: MediaType::All,
+                condition: Some(self.parse_media_condition()?),
+            });
+        }
+        self.consume_whitespace();
+        let condition = if self.consume_keyword("and") {
+            // メディアタイプの後の条件は and でしかつなげない
+            match self.parse_media_condition()? {
+                MediaCondition::Or(_) => return self.error("or after a media type".to_string()),
+                condition => Some(condition),
+            }
+        } else {
+            None
+        };
+        Ok(MediaQuery {
+            negated,
+            media_type: MediaType::from_name(&word),
+            condition,
+        })
+    }
+
+    /// `not <in-parens>` or `<in-parens> [ [and | or] <in-parens> ]*`.
+    fn parse_media_condition(&mut self) -> Result<MediaCondition, ParseError> {
+        self.consume_whitespace();
+        if self.consume_keyword("not") {
+            return Ok(MediaCondition::Not(Box::new(self.parse_media_in_parens()?)));
+        }
+        let mut conditions = vec![self.parse_media_in_parens()?];
+        let mut conjunction = None;
+        loop {
+            self.consume_whitespace();
+            let keyword = if self.consume_keyword("and") {
+                "and"
+            } else if self.consume_keyword("or") {
+                "or"
+            } else {
+                break;
+            };
+            if conjunction.is_some_and(|conjunction| conjunction != keyword) {
+                return self.error("and and or mixed without parentheses".to_string());
+            }
+            conjunction = Some(keyword);
+            conditions.push(self.parse_media_in_parens()?);
+        }
+        Ok(match conjunction {
+            None => conditions.swap_remove(0),
+            Some("and") => MediaCondition::And(conditions),
+            Some(_) => MediaCondition::Or(conditions),
+        })
+    }
+
+    /// `( <media-condition> )` or `( <media-feature> )`.
+    fn parse_media_in_parens(&mut self) -> Result<MediaCondition, ParseError> {
+        self.consume_whitespace();
+        self.expect_char('(', "media condition")?;
+        self.consume_whitespace();
+        let start = self.position;
+        let nested = self.next_char().ok() == Some('(') || self.consume_keyword("not");
+        self.position = start;
+        let condition = if nested {
+            self.parse_media_condition()?
+        } else {
+            MediaCondition::Feature(self.parse_media_feature()?)
+        };
+        self.consume_whitespace();
+        self.expect_char(')', "media condition")?;
+        Ok(condition)
+    }
+
+    /// `<name>`, `<name>: <value>` or a range such as `400px <= width < 700px`.
+    fn parse_media_feature(&mut self) -> Result<MediaFeature, ParseError> {
+        if starts_with_number(&self.source[self.position..]) {
+            let value = self.parse_media_value()?;
+            let comparison = self.parse_comparison()?;
+            self.consume_whitespace();
+            let name = self.parse_identifier().to_ascii_lowercase();
+            if name.is_empty() {
+                return self.unexpected("media feature");
+            }
+            let mut comparisons = vec![(comparison.flip(), value)];
+            self.consume_whitespace();
+            if matches!(self.next_char(), Ok('<' | '>')) {
+                let comparison = self.parse_comparison()?;
+                comparisons.push((comparison, self.parse_media_value()?));
+            }
+            return Ok(MediaFeature { name, comparisons });
+        }
+
+        let name = self.parse_identifier().to_ascii_lowercase();
+        if name.is_empty() {
+            return self.unexpected("media feature");
+        }
+        self.consume_whitespace();
+        Ok(match self.next_char() {
+            Ok(':') => {
+                self.consume_char().unwrap();
+                let value = self.parse_media_value()?;
+                let (name, comparison) = if let Some(name) = name.strip_prefix("min-") {
+                    (name.to_string(), Comparison::GreaterOrEqual)
+                } else if let Some(name) = name.strip_prefix("max-") {
+                    (name.to_string(), Comparison::LessOrEqual)
+                } else {
+                    (name, Comparison::Equal)
+                };
+                MediaFeature {
+                    name,
+                    comparisons: vec![(comparison, value)],
+                }
+            }
+            Ok('<' | '>' | '=') => {
+                let comparison = self.parse_comparison()?;
+                MediaFeature {
+                    name,
+                    comparisons: vec![(comparison, self.parse_media_value()?)],
+                }
+            }
+            _ => MediaFeature {
+                name,
+                comparisons: Vec::new(),
+            },
+        })
+    }
+
+    /// `<`, `<=`, `>`, `>=` or `=`.
+    fn parse_comparison(&mut self) -> Result<Comparison, ParseError> {
+        self.consume_whitespace();
+        let comparison = match self.next_char() {
+            Ok('=') => Comparison::Equal,
+            Ok('<') => Comparison::Less,
+            Ok('>') => Comparison::Greater,
+            _ => return self.unexpected("media feature"),
+        };
+        self.consume_char().unwrap();
+        if comparison == Comparison::Equal || self.next_char().ok() != Some('=') {
+            return Ok(comparison);
+        }
+        self.consume_char().unwrap();
+        Ok(match comparison {
+            Comparison::Less => Comparison::LessOrEqual,
+            _ => Comparison::GreaterOrEqual,
+        })
+    }
+
+    /// A length, resolution, ratio, number or keyword in a media feature.
+    fn parse_media_value(&mut self) -> Result<MediaValue, ParseError> {
+        self.consume_whitespace();
+        if !starts_with_number(&self.source[self.position..]) {
+            let keyword = self.parse_identifier().to_ascii_lowercase();
+            if keyword.is_empty() {
+                return self.unexpected("media feature");
+            }
+            return Ok(MediaValue::Keyword(keyword));
+        }
+        let number = self.parse_float();
+        let unit = self.parse_identifier().to_ascii_lowercase();
+        Ok(match &*unit {
+            "" => {
+                // `16/9` のような比
+                let start = self.position;
+                self.consume_whitespace();
+                if self.next_char().ok() == Some('/') {
+                    self.consume_char().unwrap();
+                    self.consume_whitespace();
+                    if !starts_with_number(&self.source[self.position..]) {
+                        return self.unexpected("media feature");
+                    }
+                    MediaValue::Number(number / self.parse_float())
+                } else {
+                    self.position = start;
+                    MediaValue::Number(number)
+                }
+            }
+            "dppx" | "x" => MediaValue::Resolution(number),
+            "dpi" => MediaValue::Resolution(number / 96.0),
+            "dpcm" => MediaValue::Resolution(number * 2.54 / 96.0),
+            _ => match Unit::from_name(&unit) {
+                // メディアクエリの相対単位は初期値を基準にする
+                Some(unit) => MediaValue::Length(Value::Length(number, unit).to_px()),
+                None => MediaValue::Keyword(format!("{}{}", number, unit)),
+            },
+        })
+    }
+
+    // Methods for parsing feature queries:
+
+    /// `not <in-parens>` or `<in-parens> [ [and | or] <in-parens> ]*`.
+    fn parse_supports_condition(&mut self) -> Result<SupportsCondition, ParseError> {
+        self.consume_whitespace();
+        if self.consume_keyword("not") {
+            return Ok(SupportsCondition::Not(Box::new(
+                self.parse_supports_in_parens()?,
+            )));
+        }
+        let mut conditions = vec![self.parse_supports_in_parens()?];
+        let mut conjunction = None;
+        loop {
+            self.consume_whitespace();
+            let keyword = if self.consume_keyword("and") {
+                "and"
+            } else if self.consume_keyword("or") {
+                "or"
+            } else {
+                break;
+            };
+            if conjunction.is_some_and(|conjunction| conjunction != keyword) {
+                return self.error("and and or mixed without parentheses".to_string());
+            }
+            conjunction = Some(keyword);
+            conditions.push(self.parse_supports_in_parens()?);
+        }
+        Ok(match conjunction {
+            None => conditions.swap_remove(0),
+            Some("and") => SupportsCondition::And(conditions),
+            Some(_) => SupportsCondition::Or(conditions),
+        })
+    }
+
+    /// `selector(...)`, a nested condition, or a declaration in parentheses.
+    /// Anything else in parentheses is kept as an unknown condition.
+    fn parse_supports_in_parens(&mut self) -> Result<SupportsCondition, ParseError> {
+        self.consume_whitespace();
+        if self.consume_keyword("selector") {
+            let selector = self.parse_parenthesized()?;
+            return Ok(SupportsCondition::Selector(selector.trim().to_string()));
+        }
+        let start = self.position;
+        self.expect_char('(', "@supports")?;
+        self.consume_whitespace();
+        let nested = self.next_char().ok() == Some('(') || self.consume_keyword("not");
+        if nested {
+            self.position = start + 1;
+            let condition = self.parse_supports_condition()?;
+            self.consume_whitespace();
+            self.expect_char(')', "@supports")?;
+            return Ok(condition);
+        }
+        self.position = start;
+        let contents = self.parse_parenthesized()?;
+        let mut parser = Parser::new(contents.clone());
+        parser.consume_whitespace();
+        let is_declaration = !parser.parse_identifier().is_empty() && {
+            parser.consume_whitespace();
+            parser.next_char().ok() == Some(':')
+        };
+        if !is_declaration {
+            return Ok(SupportsCondition::Unknown(contents));
+        }
+        parser.position = 0;
+        parser.consume_whitespace();
+        Ok(match parser.parse_declaration() {
+            Ok(declaration) => SupportsCondition::Declaration(declaration),
+            // 値をパースできない宣言はサポートされていない
+            Err(_) => SupportsCondition::Unknown(contents),
+        })
+    }
+
+    /// Consume a `( ... )` block and return its contents verbatim.
+    fn parse_parenthesized(&mut self) -> Result<String, ParseError> {
+        self.expect_char('(', "parentheses")?;
+        let start = self.position;
+        let mut depth = 0;
+        loop {
+            match self.next_char() {
+                Err(_) => return self.unexpected("parentheses"),
+                Ok('"' | '\'') => {
+                    self.parse_string();
+                    continue;
+                }
+                Ok('(') => depth += 1,
+                Ok(')') if depth == 0 => break,
+                Ok(')') => depth -= 1,
+                _ => {}
+            }
+            self.consume_char().unwrap();
         }
+        let contents = self.source[start..self.position].to_string();
+        self.consume_char().unwrap();
+        Ok(contents)
     }
 
-    fn parse_simple_selector(&mut self) -> SimpleSelector {
+    /// Parse a compound selector such as `li.item:hover`.
+    fn parse_simple_selector(&mut self) -> Result<SimpleSelector, ParseError> {
+        let start = self.position;
         let mut selector = SimpleSelector {
             tag_name: None,
             id: None,
             class: Vec::new(),
+            pseudo_classes: Vec::new(),
+            pseudo_element: None,
+            nesting: false,
+            location: self.source_location(),
         };
 
         while !self.eof() {
             match self.next_char().unwrap() {
                 '#' => {
-                    self.consume_char();
+                    self.consume_char().unwrap();
                     selector.id = Some(self.parse_identifier());
                 }
                 '.' => {
-                    self.consume_char();
+                    self.consume_char().unwrap();
                     selector.class.push(self.parse_identifier());
                 }
                 '*' => {
                     // ユニバーサルセレクター
-                    self.consume_char();
+                    self.consume_char().unwrap();
+                }
+                '&' => {
+                    // 入れ子セレクター
+                    self.consume_char().unwrap();
+                    selector.nesting = true;
+                }
+                ':' => {
+                    self.consume_char().unwrap();
+                    // `::before`, plus the legacy single-colon `:before` / `:after`
+                    if self.next_char().ok() == Some(':')
+                        || self.starts_with("before")
+                        || self.starts_with("after")
+                    {
+                        if self.next_char().unwrap() == ':' {
+                            self.consume_char().unwrap();
+                        }
+                        selector.pseudo_element = Some(self.parse_pseudo_element()?);
+                    } else {
+                        selector.pseudo_classes.push(self.parse_pseudo_class()?);
+                    }
                 }
                 c if valid_identifier_char(c) => {
                     selector.tag_name = Some(self.parse_identifier());
@@ -74,102 +982,530 @@ impl CSSParserTrait for Parser {
             }
         }
 
-        selector
+        if self.position == start {
+            return self.unexpected("selector");
+        }
+        Ok(selector)
     }
 
-    fn parse_selector(&mut self) -> Vec<Selector> {
+    /// Parse compound selectors joined by combinators, e.g. `ul.nav > li a`.
+    fn parse_complex_selector(&mut self) -> Result<Selector, ParseError> {
+        let mut selector = Selector::Simple(self.parse_simple_selector()?);
+        loop {
+            let had_whitespace = self.next_char().is_ok_and(char::is_whitespace);
+            self.consume_whitespace();
+            let combinator = match self.next_char() {
+                Ok(c) => match parse_combinator(c) {
+                    Some(combinator) => {
+                        self.consume_char().unwrap();
+                        self.consume_whitespace();
+                        combinator
+                    }
+                    None if c == ',' || c == '{' || c == ')' => break,
+                    None if had_whitespace => Combinator::Descendant,
+                    None => return self.unexpected("selector"),
+                },
+                Err(_) => break,
+            };
+            let right = self.parse_simple_selector()?;
+            selector = Selector::Complex(Box::new(selector), combinator, right);
+        }
+        Ok(selector)
+    }
+
+    /// Parse a comma-separated selector list, stopping before `{` or `)`.
+    fn parse_selector_list(&mut self) -> Result<Vec<Selector>, ParseError> {
         let mut selectors = Vec::new();
         loop {
-            selectors.push(Selector::Simple(self.parse_simple_selector()));
             self.consume_whitespace();
-            match self.next_char().unwrap() {
-                ',' => {
-                    self.consume_char();
-                    self.consume_whitespace();
+            selectors.push(self.parse_complex_selector()?);
+            self.consume_whitespace();
+            match self.next_char() {
+                Ok(',') => {
+                    self.consume_char().unwrap();
                 }
-                '{' => break, // start of declarations
-                c => panic!("Unexpected character {} in selector list", c),
+                _ => break,
             }
         }
-        selectors.sort_by(|a, b| b.specificity().cmp(&a.specificity()));
-        return selectors;
+        Ok(selectors)
     }
 
-    /// Parse a list of declarations enclosed in `{ ... }`.
-    fn parse_declarations(&mut self) -> Vec<Declaration> {
-        assert_eq!(self.consume_char().unwrap(), '{');
-        let mut declarations = Vec::new();
+    /// Parse the relative selector list of `:has()`, e.g. `> img, + p`.
+    fn parse_relative_selector_list(&mut self) -> Result<Vec<RelativeSelector>, ParseError> {
+        let mut selectors = Vec::new();
         loop {
             self.consume_whitespace();
-            if self.next_char().unwrap() == '}' {
-                self.consume_char();
-                break;
+            let combinator = match self.next_char().ok().and_then(parse_combinator) {
+                Some(combinator) => {
+                    self.consume_char().unwrap();
+                    self.consume_whitespace();
+                    combinator
+                }
+                None => Combinator::Descendant,
+            };
+            selectors.push(RelativeSelector {
+                combinator,
+                selector: self.parse_complex_selector()?,
+            });
+            self.consume_whitespace();
+            match self.next_char() {
+                Ok(',') => {
+                    self.consume_char().unwrap();
+                }
+                _ => break,
             }
-            declarations.push(self.parse_declaration());
         }
-        declarations
+        Ok(selectors)
+    }
+
+    /// Parse a pseudo-class after its leading `:`.
+    fn parse_pseudo_class(&mut self) -> Result<PseudoClass, ParseError> {
+        let name = self.parse_identifier().to_ascii_lowercase();
+        if self.next_char().ok() != Some('(') {
+            return match PseudoClass::from_name(&name) {
+                Some(pseudo_class) => Ok(pseudo_class),
+                None => self.error(format!("unknown pseudo-class :{}", name)),
+            };
+        }
+
+        self.consume_char().unwrap();
+        let pseudo_class = match &*name {
+            "not" => PseudoClass::Not(self.parse_selector_list()?),
+            "is" | "matches" => PseudoClass::Is(self.parse_selector_list()?),
+            "where" => PseudoClass::Where(self.parse_selector_list()?),
+            "has" => PseudoClass::Has(self.parse_relative_selector_list()?),
+            _ => return self.error(format!("unknown pseudo-class :{}()", name)),
+        };
+        self.expect_char(')', "pseudo-class")?;
+        Ok(pseudo_class)
+    }
+
+    /// Parse a pseudo-element name after its leading `::`.
+    fn parse_pseudo_element(&mut self) -> Result<PseudoElement, ParseError> {
+        let name = self.parse_identifier().to_ascii_lowercase();
+        match PseudoElement::from_name(&name) {
+            Some(pseudo_element) => Ok(pseudo_element),
+            None => self.error(format!("unknown pseudo-element ::{}", name)),
+        }
     }
 
-    /// Parse one `<property>: <value>;` declaration.
-    fn parse_declaration(&mut self) -> Declaration {
+    /// Parse the selector list of a style rule up to its `{`.
+    fn parse_selector(&mut self) -> Result<Vec<Selector>, ParseError> {
+        let mut selectors = self.parse_selector_list()?;
+        if self.next_char().ok() != Some('{') {
+            return self.unexpected("selector list");
+        }
+        selectors.sort_by_key(|s| std::cmp::Reverse(s.specificity()));
+        Ok(selectors)
+    }
+
+    /// Parse one `<property>: <value> [!important];` declaration.
+    /// The trailing `;` may be omitted on the last declaration of a block.
+    fn parse_declaration(&mut self) -> Result<Declaration, ParseError> {
+        let location = self.source_location();
         let property_name = self.parse_identifier();
+        if property_name.is_empty() {
+            return self.unexpected("declaration");
+        }
         self.consume_whitespace();
-        assert_eq!(self.consume_char().unwrap(), ':');
-        self.consume_whitespace();
-        let value = self.parse_value();
+        self.expect_char(':', "declaration")?;
         self.consume_whitespace();
-        assert_eq!(self.consume_char().unwrap(), ';');
+        let start = self.position;
+        let css = self.parse_raw_value();
+        // カスタムプロパティとvar()を含む値は、置換した後にパースする
+        let value = if is_custom_property(&property_name) || contains_var(&css) {
+            if CSS_WIDE_KEYWORDS.contains(&&*css) {
+                Value::Keyword(css)
+            } else {
+                Value::Unparsed {
+                    css,
+                    shorthand: None,
+                }
+            }
+        } else {
+            self.position = start;
+            self.parse_value_list()?
+        };
+        let important = self.next_char().ok() == Some('!');
+        if important {
+            self.consume_char().unwrap();
+            self.consume_whitespace();
+            let keyword = self.parse_identifier();
+            if !keyword.eq_ignore_ascii_case("important") {
+                return self.error(format!("unexpected !{} in declaration", keyword));
+            }
+            self.consume_whitespace();
+        }
+        match self.next_char() {
+            Ok(';') => {
+                self.consume_char().unwrap();
+            }
+            Ok('}') | Err(_) => {}
+            Ok(_) => return self.unexpected("declaration"),
+        }
 
-        Declaration {
+        Ok(Declaration {
             name: property_name,
-            value: value,
+            value,
+            important,
+            location,
+        })
+    }
+
+    /// Skip an invalid declaration from its start up to and including its `;`.
+    /// Stops before the `}` that closes the block.
+    fn skip_declaration(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char().unwrap() {
+                ';' if depth == 0 => {
+                    self.consume_char().unwrap();
+                    break;
+                }
+                '}' if depth == 0 => break,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                '"' | '\'' => {
+                    self.parse_string();
+                    continue;
+                }
+                _ => {}
+            }
+            self.consume_char().unwrap();
         }
     }
 
     // Methods for parsing values:
 
-    fn parse_value(&mut self) -> Value {
-        match self.next_char().unwrap() {
-            '0'...'9' => self.parse_length(),
-            '#' => self.parse_color(),
-            _ => Value::Keyword(self.parse_identifier()),
+    /// Capture the declaration value verbatim, up to a `;`, `}` or `!` outside
+    /// any brackets or strings. Surrounding whitespace is trimmed.
+    fn parse_raw_value(&mut self) -> String {
+        let start = self.position;
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char().unwrap() {
+                ';' | '}' | '!' if depth == 0 => break,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                '"' | '\'' => {
+                    self.parse_string();
+                    continue;
+                }
+                _ => {}
+            }
+            self.consume_char().unwrap();
         }
+        self.source[start..self.position].trim().to_string()
     }
 
-    fn parse_length(&mut self) -> Value {
-        Value::Length(self.parse_float(), self.parse_unit())
+    /// Parse the component values up to the end of the declaration.
+    /// A single component is returned as is, several (including `,` and `/`
+    /// delimiters) are wrapped in `Value::List`.
+    fn parse_value_list(&mut self) -> Result<Value, ParseError> {
+        let mut values = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                break;
+            }
+            match self.next_char().unwrap() {
+                ';' | '}' | '!' => break,
+                c @ (',' | '/') => {
+                    self.consume_char().unwrap();
+                    values.push(Value::Delimiter(c));
+                }
+                _ => values.push(self.parse_value()?),
+            }
+        }
+        Ok(if values.len() == 1 {
+            values.swap_remove(0)
+        } else {
+            Value::List(values)
+        })
     }
 
-    fn parse_float(&mut self) -> f32 {
-        let s = self.consume_while(|c| match c {
-            '0'...'9' | '.' => true,
-            _ => false,
-        });
-        s.parse().unwrap()
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        Ok(match self.next_char().unwrap() {
+            '#' => self.parse_color()?,
+            '"' | '\'' => Value::Str(self.parse_string()),
+            _ if starts_with_number(&self.source[self.position..]) => self.parse_length(),
+            _ => {
+                let name = self.parse_identifier();
+                if name.is_empty() {
+                    return self.unexpected("value");
+                }
+                let function = name.to_ascii_lowercase();
+                if function == "url" && self.next_char().ok() == Some('(') {
+                    Value::Function(function, vec![Value::Str(self.parse_url()?)])
+                } else if MATH_FUNCTIONS.contains(&&*function) && self.next_char().ok() == Some('(')
+                {
+                    let expression = self.parse_math_function(&function)?;
+                    if expression.math_type().is_none() {
+                        return self.error(format!("mismatched types in {}()", function));
+                    }
+                    expression.simplify().into_value()
+                } else if self.next_char().ok() == Some('(') {
+                    let arguments = self.parse_arguments()?;
+                    if !color::is_color_function(&function) {
+                        Value::Function(function, arguments)
+                    } else if let Some(color) = color::from_function(&function, &arguments) {
+                        Value::ColorValue(color)
+                    } else {
+                        return self.error(format!("invalid {}() color", function));
+                    }
+                } else {
+                    Value::Keyword(name)
+                }
+            }
+        })
     }
 
-    fn parse_unit(&mut self) -> Unit {
-        match &*self.parse_identifier().to_ascii_lowercase() {
-            "px" => Px,
-            _ => panic!("unrecognized unit"),
+    /// Parse the comma-separated arguments of a function such as `attr(x)` or `counter(a, b)`.
+    fn parse_arguments(&mut self) -> Result<Vec<Value>, ParseError> {
+        self.expect_char('(', "function")?;
+        let mut arguments = Vec::new();
+        let mut argument = Vec::new();
+        loop {
+            self.consume_whitespace();
+            match self.next_char() {
+                Err(_) => return self.unexpected("function"),
+                Ok(c @ (',' | ')')) => {
+                    self.consume_char().unwrap();
+                    match argument.len() {
+                        0 => {}
+                        1 => arguments.push(argument.swap_remove(0)),
+                        _ => arguments.push(Value::List(argument.split_off(0))),
+                    }
+                    if c == ')' {
+                        break;
+                    }
+                }
+                Ok('/') => {
+                    self.consume_char().unwrap();
+                    argument.push(Value::Delimiter('/'));
+                }
+                _ => argument.push(self.parse_value()?),
+            }
         }
+        Ok(arguments)
     }
 
-    fn parse_color(&mut self) -> Value {
-        assert_eq!(self.consume_char().unwrap(), '#');
-        Value::ColorValue(Color {
-            r: self.parse_hex_pair(),
-            g: self.parse_hex_pair(),
-            b: self.parse_hex_pair(),
-            a: 255,
+    /// Parse the argument of `url(...)`, which may be quoted or a raw URL.
+    fn parse_url(&mut self) -> Result<String, ParseError> {
+        self.expect_char('(', "url()")?;
+        self.consume_whitespace();
+        let url = match self.next_char() {
+            Ok('"' | '\'') => self.parse_string(),
+            _ => self.consume_while(|c| c != ')' && !c.is_whitespace()),
+        };
+        self.consume_whitespace();
+        self.expect_char(')', "url()")?;
+        Ok(url)
+    }
+
+    /// Parse the arguments of `calc()`, `min()`, `max()` or `clamp()`.
+    fn parse_math_function(&mut self, name: &str) -> Result<MathExpression, ParseError> {
+        self.expect_char('(', "math function")?;
+        let mut arguments = vec![self.parse_calc_sum()?];
+        loop {
+            self.consume_whitespace();
+            match self.next_char() {
+                Ok(',') => {
+                    self.consume_char().unwrap();
+                    arguments.push(self.parse_calc_sum()?);
+                }
+                Ok(')') => {
+                    self.consume_char().unwrap();
+                    break;
+                }
+                _ => return self.unexpected("math function"),
+            }
+        }
+        Ok(match (name, arguments.len()) {
+            ("calc", 1) => arguments.swap_remove(0),
+            ("min", _) => MathExpression::Min(arguments),
+            ("max", _) => MathExpression::Max(arguments),
+            ("clamp", 3) => {
+                let max = arguments.pop().unwrap();
+                let value = arguments.pop().unwrap();
+                let min = arguments.pop().unwrap();
+                MathExpression::Clamp(Box::new(min), Box::new(value), Box::new(max))
+            }
+            _ => return self.error(format!("wrong number of arguments to {}()", name)),
+        })
+    }
+
+    /// `<product> [ [ + | - ] <product> ]*`
+    fn parse_calc_sum(&mut self) -> Result<MathExpression, ParseError> {
+        let mut terms = vec![self.parse_calc_product()?];
+        loop {
+            self.consume_whitespace();
+            match self.next_char() {
+                Ok('+') => {
+                    self.consume_char().unwrap();
+                    terms.push(self.parse_calc_product()?);
+                }
+                Ok('-') => {
+                    self.consume_char().unwrap();
+                    terms.push(self.parse_calc_product()?.negate());
+                }
+                _ => break,
+            }
+        }
+        Ok(match terms.len() {
+            1 => terms.swap_remove(0),
+            _ => MathExpression::Sum(terms),
+        })
+    }
+
+    /// `<value> [ [ * | / ] <value> ]*`
+    fn parse_calc_product(&mut self) -> Result<MathExpression, ParseError> {
+        let mut factors = vec![self.parse_calc_value()?];
+        loop {
+            self.consume_whitespace();
+            match self.next_char() {
+                Ok('*') => {
+                    self.consume_char().unwrap();
+                    factors.push(self.parse_calc_value()?);
+                }
+                Ok('/') => {
+                    self.consume_char().unwrap();
+                    factors.push(MathExpression::Invert(Box::new(self.parse_calc_value()?)));
+                }
+                _ => break,
+            }
+        }
+        Ok(match factors.len() {
+            1 => factors.swap_remove(0),
+            _ => MathExpression::Product(factors),
         })
     }
 
-    /// Parse two hexadecimal digits.
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.source[self.position..self.position + 2];
-        self.position += 2;
-        u8::from_str_radix(s, 16).unwrap()
+    /// A number, length, constant, parenthesized sum or nested math function.
+    fn parse_calc_value(&mut self) -> Result<MathExpression, ParseError> {
+        self.consume_whitespace();
+        if self.next_char().ok() == Some('(') {
+            self.consume_char().unwrap();
+            let sum = self.parse_calc_sum()?;
+            self.consume_whitespace();
+            self.expect_char(')', "math function")?;
+            return Ok(sum);
+        }
+        let start = self.position;
+        if starts_with_number(&self.source[self.position..]) {
+            return match self.parse_length() {
+                Value::Number(n) => Ok(MathExpression::Number(n)),
+                Value::Length(length, unit) => Ok(MathExpression::Length(length, unit)),
+                _ => {
+                    let value = self.source[start..self.position].to_string();
+                    self.error(format!("unsupported {} in math function", value))
+                }
+            };
+        }
+        let name = self.parse_identifier().to_ascii_lowercase();
+        match &*name {
+            "pi" => Ok(MathExpression::Number(std::f32::consts::PI)),
+            "e" => Ok(MathExpression::Number(std::f32::consts::E)),
+            // 無限大は計算値で表せる最大の値になる
+            "infinity" => Ok(MathExpression::Number(f32::INFINITY)),
+            "-infinity" => Ok(MathExpression::Number(f32::NEG_INFINITY)),
+            "nan" => Ok(MathExpression::Number(f32::NAN)),
+            _ if MATH_FUNCTIONS.contains(&&*name) => self.parse_math_function(&name),
+            "" => self.unexpected("math function"),
+            _ => self.error(format!("unexpected {} in math function", name)),
+        }
+    }
+
+    /// Parse a number followed by an optional unit or `%`.
+    /// Unitless numbers such as `counter-increment: item 2` become `Value::Number`;
+    /// an unknown unit yields a keyword that no property accepts.
+    fn parse_length(&mut self) -> Value {
+        let number = self.parse_float();
+        match self.next_char() {
+            Ok('%') => {
+                self.consume_char().unwrap();
+                Value::Length(number, Unit::Percent)
+            }
+            Ok(c) if c.is_alphabetic() => {
+                let unit = self.parse_identifier();
+                match (Unit::from_name(&unit), angle_in_degrees(number, &unit)) {
+                    (Some(unit), _) => Value::Length(number, unit),
+                    (None, Some(degrees)) => Value::Angle(degrees),
+                    (None, None) => Value::Keyword(format!("{}{}", number, unit)),
+                }
+            }
+            _ => Value::Number(number),
+        }
+    }
+
+    fn parse_float(&mut self) -> f32 {
+        let sign = match self.next_char().unwrap() {
+            '-' => -1.0,
+            _ => 1.0,
+        };
+        if matches!(self.next_char().unwrap(), '-' | '+') {
+            self.consume_char().unwrap();
+        }
+        // 小数点は一つまで（`1.2.3` は `1.2` と `.3`）
+        let mut s = self.consume_while(|c| c.is_ascii_digit());
+        if self.next_char().ok() == Some('.') && starts_with_number(&self.source[self.position..]) {
+            s.push(self.consume_char().unwrap());
+            s.push_str(&self.consume_while(|c| c.is_ascii_digit()));
+        }
+        // 指数（`1e3`、`2.5E-2`）。`1em` の e は単位
+        let exponent = self.source[self.position..].strip_prefix(['e', 'E']);
+        if exponent
+            .map(|exponent| exponent.strip_prefix(['-', '+']).unwrap_or(exponent))
+            .is_some_and(|digits| digits.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            s.push(self.consume_char().unwrap());
+            if matches!(self.next_char().unwrap(), '-' | '+') {
+                s.push(self.consume_char().unwrap());
+            }
+            s.push_str(&self.consume_while(|c| c.is_ascii_digit()));
+        }
+        // 表せない大きさの値は最も近い有限の値にする
+        (sign * s.parse::<f32>().unwrap()).clamp(f32::MIN, f32::MAX)
+    }
+
+    /// Parse a quoted string, resolving CSS escapes such as `\201C`.
+    fn parse_string(&mut self) -> String {
+        let open_quote = self.consume_char().unwrap();
+        let mut value = String::new();
+        // 閉じていない文字列はソースの終わりで閉じる
+        while !self.eof() {
+            match self.consume_char().unwrap() {
+                c if c == open_quote => break,
+                '\\' if self.eof() => {}
+                '\\' => {
+                    let mut hex = String::new();
+                    while hex.len() < 6 && self.next_char().is_ok_and(|c| c.is_ascii_hexdigit()) {
+                        hex.push(self.consume_char().unwrap());
+                    }
+                    if hex.is_empty() {
+                        value.push(self.consume_char().unwrap());
+                    } else {
+                        let code = u32::from_str_radix(&hex, 16).unwrap();
+                        value.push(std::char::from_u32(code).unwrap_or('\u{FFFD}'));
+                        if self.next_char().is_ok_and(char::is_whitespace) {
+                            self.consume_char().unwrap();
+                        }
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+        value
+    }
+
+    /// Parse a `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa` hex color.
+    fn parse_color(&mut self) -> Result<Value, ParseError> {
+        assert_eq!(self.consume_char().unwrap(), '#');
+        let digits = self.consume_while(valid_identifier_char);
+        match color::from_hex(&digits) {
+            Some(color) => Ok(Value::ColorValue(color)),
+            None => self.error(format!("invalid hex color #{}", digits)),
+        }
     }
 
     fn parse_identifier(&mut self) -> String {
@@ -177,6 +1513,300 @@ impl CSSParserTrait for Parser {
     }
 }
 
+// 入れ子のルールの前に書かれた宣言を、親セレクターのルールとして追加する
+fn flush_declarations(
+    rules: &mut Vec<CSSRule>,
+    declarations: &mut Vec<Declaration>,
+    selectors: &[Selector],
+) {
+    if !declarations.is_empty() {
+        rules.push(CSSRule::Style(Rule {
+            selectors: selectors.to_vec(),
+            declarations: std::mem::take(declarations),
+        }));
+    }
+}
+
+fn parse_combinator(c: char) -> Option<Combinator> {
+    match c {
+        '>' => Some(Combinator::Child),
+        '+' => Some(Combinator::NextSibling),
+        '~' => Some(Combinator::SubsequentSibling),
+        _ => None,
+    }
+}
+
+fn angle_in_degrees(number: f32, unit: &str) -> Option<f32> {
+    match &*unit.to_ascii_lowercase() {
+        "deg" => Some(number),
+        "grad" => Some(number * 0.9),
+        "rad" => Some(number.to_degrees()),
+        "turn" => Some(number * 360.0),
+        _ => None,
+    }
+}
+
+// `12`, `.5`, `-3` and `+1` start a number; `-webkit-box` does not.
+fn starts_with_number(rest: &str) -> bool {
+    let mut chars = rest.chars();
+    match chars.next() {
+        Some('0'..='9') => true,
+        Some('.') => chars.next().is_some_and(|c| c.is_ascii_digit()),
+        Some('-') | Some('+') => match chars.next() {
+            Some('0'..='9') => true,
+            Some('.') => chars.next().is_some_and(|c| c.is_ascii_digit()),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
 fn valid_identifier_char(c: char) -> bool {
     c.is_alphanumeric() || c == '-' || c == '_'
 }
+
+#[test]
+fn test_logical_pseudo_class_specificity() {
+    let stylesheet = new_css_parser(
+        "a:where(#x, .y) {} a:is(#x, .y) {} a:not(.y b) {} a:has(> #x .y) {}".to_string(),
+    )
+    .parse();
+    let specificities: Vec<_> = stylesheet
+        .rules
+        .iter()
+        .map(|rule| rule.as_style().unwrap().selectors[0].specificity())
+        .collect();
+    assert_eq!(
+        specificities,
+        vec![(0, 0, 1), (1, 0, 1), (0, 1, 2), (1, 1, 1)]
+    );
+}
+
+#[test]
+fn test_comments() {
+    // コメントは空白として読み飛ばし、文字列の中の /* はコメントとみなさない
+    let css = "/* reset */ a /* x */ { width: /* y */ 1px; /* z */ } \
+               /* { */ b { content: \"/* not a comment */\" } /* 日本語\n */ p { width: 2px }";
+    let stylesheet = new_css_parser(css.to_string()).parse();
+    let declarations = |index: usize| {
+        stylesheet.rules[index]
+            .as_style()
+            .unwrap()
+            .declarations
+            .iter()
+            .map(|declaration| declaration.value.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(stylesheet.rules.len(), 3);
+    assert_eq!(declarations(0), vec![Value::Length(1.0, Unit::Px)]);
+    assert_eq!(
+        declarations(1),
+        vec![Value::Str("/* not a comment */".to_string())]
+    );
+    assert_eq!(declarations(2), vec![Value::Length(2.0, Unit::Px)]);
+}
+
+#[test]
+fn test_nesting() {
+    use style_sheet::serialize::{serialize, Format};
+    use style_sheet::source_location::clear_locations;
+
+    let css = ".card { color: red; \
+                   & .title { color: blue } \
+                   > p, &:hover { margin-top: 1px } \
+                   .dark & { color: white } \
+                   @media (min-width: 600px) { padding-left: 2px; a { color: green } } \
+                   margin-left: 3px }";
+    // 位置は書き方で変わるので、位置を消して比べる
+    let parse = |css: String| {
+        let mut stylesheet = new_css_parser(css).parse();
+        clear_locations(&mut stylesheet.rules);
+        stylesheet
+    };
+    let stylesheet = parse(css.to_string());
+    let selectors = |css: &str| -> Vec<Selector> {
+        parse(format!("{} {{}}", css)).rules[0]
+            .as_style()
+            .unwrap()
+            .selectors
+            .clone()
+    };
+    let style = |index: usize| stylesheet.rules[index].as_style().unwrap();
+
+    // 入れ子のルールは親のルールの後に平坦化され、& は :is(<親セレクター>) になる
+    assert_eq!(stylesheet.rules.len(), 6);
+    assert_eq!(style(0).selectors, selectors(".card"));
+    assert_eq!(style(1).selectors, selectors(":is(.card) .title"));
+    assert_eq!(
+        style(2).selectors,
+        selectors(":hover:is(.card), :is(.card) > p")
+    );
+    assert_eq!(style(3).selectors, selectors(".dark :is(.card)"));
+    match stylesheet.rules[4] {
+        CSSRule::Media(ref media_rule) => {
+            let nested: Vec<_> = media_rule
+                .rules
+                .iter()
+                .map(|rule| rule.as_style().unwrap().selectors.clone())
+                .collect();
+            assert_eq!(nested, vec![selectors(".card"), selectors(":is(.card) a")]);
+        }
+        ref rule => panic!("{:?} is not a @media rule", rule),
+    }
+    // 入れ子のルールの後の宣言は、親セレクターの別のルールになる
+    assert_eq!(style(5).selectors, selectors(".card"));
+    assert_eq!(style(5).declarations[0].name, "margin-left");
+    assert_eq!(style(2).selectors[1].specificity(), (0, 1, 1));
+
+    // 入れ子のルールしかない親は空のルールを作らない
+    let serialized =
+        |css: &str| serialize(&new_css_parser(css.to_string()).parse(), Format::Minified);
+    assert_eq!(serialized("div { span { x: y } }"), ":is(div) span{x:y}");
+    assert_eq!(
+        serialized("div { @media print { span { x: y } } }"),
+        "@media print{:is(div) span{x:y}}"
+    );
+    assert_eq!(serialized("div {}"), "div{}");
+}
+
+#[test]
+fn test_invalid_selectors() {
+    let css = "a:unknown-pseudo { color: red } b { color: blue } \
+               c::nope, d { color: red } e > { color: red } f:not(:frob) {} \
+               .x { g:frob { color: red } width: 1px }";
+    let (stylesheet, errors) = new_css_parser(css.to_string()).parse_with_errors();
+    let selectors: Vec<String> = stylesheet
+        .rules
+        .iter()
+        .map(|rule| {
+            format!(
+                "{:?}",
+                rule.as_style().unwrap().selectors[0].rightmost().class
+            )
+        })
+        .collect();
+    // 無効なセレクターを含むルールは、入れ子のルールも含めて捨てられる
+    assert_eq!(stylesheet.rules.len(), 2);
+    assert_eq!(selectors[1], "[\"x\"]");
+    assert_eq!(
+        stylesheet.rules[1].as_style().unwrap().declarations[0].name,
+        "width"
+    );
+    let messages: Vec<&str> = errors.iter().map(|error| &*error.message).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "unknown pseudo-class :unknown-pseudo",
+            "unknown pseudo-element ::nope",
+            "unexpected { in selector",
+            "unknown pseudo-class :frob",
+            "unknown pseudo-class :frob",
+        ]
+    );
+    assert_eq!(
+        &css[errors[0].offset - 14..errors[0].offset],
+        "unknown-pseudo"
+    );
+}
+
+#[test]
+fn test_invalid_declarations() {
+    let css = "a { color: red; width: @; : 1px; height: 1px !foo; top: f(1px, @); left: 0 }";
+    let (stylesheet, errors) = new_css_parser(css.to_string()).parse_with_errors();
+    let names: Vec<&str> = stylesheet.rules[0]
+        .as_style()
+        .unwrap()
+        .declarations
+        .iter()
+        .map(|declaration| &*declaration.name)
+        .collect();
+    // 無効な宣言だけが捨てられ、同じブロックの残りの宣言は使われる
+    assert_eq!(names, vec!["color", "left"]);
+    let messages: Vec<&str> = errors.iter().map(|error| &*error.message).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "unexpected @ in value",
+            "unexpected : in declaration",
+            "unexpected !foo in declaration",
+            "unexpected @ in value",
+        ]
+    );
+}
+
+#[test]
+fn test_invalid_imports() {
+    use style_sheet::serialize::{serialize, Format};
+
+    let css = "@import foo.css; @import url(a.css) layer(a b); @import url(b.css) layer() screen; \
+               @import url(c.css) supports(display: block) screen; \
+               @import 'd.css' supports(display: ruby); \
+               a {} @import 'e.css';";
+    let (stylesheet, errors) = new_css_parser(css.to_string()).parse_with_errors();
+    let imports: Vec<&ImportRule> = stylesheet
+        .rules
+        .iter()
+        .filter_map(|rule| match *rule {
+            CSSRule::Import(ref import_rule) => Some(import_rule),
+            _ => None,
+        })
+        .collect();
+    // 無効な @import は捨てられ、supports() の条件は評価される
+    assert_eq!(imports.len(), 2);
+    assert!(imports[0].supports.as_ref().unwrap().matches());
+    assert!(!imports[1].supports.as_ref().unwrap().matches());
+    assert_eq!(stylesheet.rules.len(), 3);
+    assert!(serialize(&stylesheet, Format::Pretty).starts_with(
+        "@import url(\"c.css\") supports(display: block) screen;\n\
+         \n\
+         @import url(\"d.css\") supports(display: ruby);\n"
+    ));
+    let messages: Vec<&str> = errors.iter().map(|error| &*error.message).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "unexpected f in @import",
+            "unexpected b in @import",
+            "unexpected ) in layer name",
+            "@import after other rules",
+        ]
+    );
+}
+
+#[test]
+fn test_invalid_layer_rules() {
+    let css = "@layer a/b; @layer \"x\"; @layer a, b { p { width: 1px } } @layer ; \
+               @layer c, d; @layer e { p { width: 2px } } \
+               p { @layer f/g { width: 3px } width: 4px }";
+    let (stylesheet, errors) = new_css_parser(css.to_string()).parse_with_errors();
+    // 無効な @layer は読み飛ばされ、続くルールは使われる
+    assert_eq!(stylesheet.rules.len(), 3);
+    assert_eq!(
+        stylesheet.rules[0],
+        CSSRule::LayerStatement(vec![
+            LayerName(vec!["c".to_string()]),
+            LayerName(vec!["d".to_string()])
+        ])
+    );
+    match stylesheet.rules[1] {
+        CSSRule::Layer(ref layer_rule) => {
+            assert_eq!(layer_rule.name, LayerName(vec!["e".to_string()]))
+        }
+        ref rule => panic!("{:?} is not a @layer block", rule),
+    }
+    assert_eq!(
+        stylesheet.rules[2].as_style().unwrap().declarations[0].name,
+        "width"
+    );
+    let messages: Vec<&str> = errors.iter().map(|error| &*error.message).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "unexpected / in @layer",
+            "unexpected \" in layer name",
+            "a @layer block takes at most one name",
+            "unexpected ; in @layer",
+            "unexpected / in @layer",
+        ]
+    );
+}
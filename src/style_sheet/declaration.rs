@@ -1,6 +1,19 @@
+use style_sheet::source_location::SourceLocation;
 use style_sheet::util::Value;
 #[derive(Debug, Clone, PartialEq)]
 pub struct Declaration {
     pub name: String,
     pub value: Value,
+    /// `!important`
+    pub important: bool,
+    /// Where the declaration was written; longhands expanded from a shorthand
+    /// share the location of the shorthand.
+    pub location: SourceLocation,
+}
+
+impl Declaration {
+    /// Whether `self` and `other` are the same declaration, wherever they were written.
+    pub fn same_content(&self, other: &Declaration) -> bool {
+        self.name == other.name && self.value == other.value && self.important == other.important
+    }
 }
@@ -0,0 +1,201 @@
+// @import の解決
+// 読み込んだシートは @import ルールの中に保持し、カスケードではその位置に展開される
+// https://www.w3.org/TR/css-cascade-4/#at-import
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use style_sheet::css_parser::new_css_parser;
+use style_sheet::interface::CSSParserTrait;
+use style_sheet::rule::CSSRule;
+use style_sheet::source_location::{line_column, set_file};
+use style_sheet::style_sheet::{Origin, Stylesheet};
+
+/// A problem found while loading stylesheets; loading carries on without the
+/// offending import.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// The sheet that contains the failing `@import`, or the sheet that
+    /// could not be read.
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+/// Reads and parses the stylesheet at `path`, then resolves its imports.
+pub fn load_style_sheet(path: &Path, origin: Origin) -> (Stylesheet, Vec<Diagnostic>) {
+    let mut loader = Loader {
+        origin,
+        stack: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    let stylesheet = loader.load(path, path).unwrap_or(Stylesheet {
+        rules: Vec::new(),
+        origin,
+    });
+    (stylesheet, loader.diagnostics)
+}
+
+/// Loads every `@import` of `stylesheet`, resolving urls relative to `path`,
+/// the file the sheet was read from. Locations in the sheet are recorded as
+/// being in `path`.
+pub fn resolve_imports(stylesheet: &mut Stylesheet, path: &Path) -> Vec<Diagnostic> {
+    set_file(&mut stylesheet.rules, &path.display().to_string());
+    let mut loader = Loader {
+        origin: stylesheet.origin,
+        stack: fs::canonicalize(path).into_iter().collect(),
+        diagnostics: Vec::new(),
+    };
+    loader.resolve(&mut stylesheet.rules, path);
+    loader.diagnostics
+}
+
+struct Loader {
+    // 読み込んだシートはすべて読み込み元と同じオリジンになる
+    origin: Origin,
+    // 読み込み中のシート（循環の検出用）
+    stack: Vec<PathBuf>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Loader {
+    fn load(&mut self, path: &Path, importer: &Path) -> Option<Stylesheet> {
+        let canonical = match fs::canonicalize(path) {
+            Ok(canonical) => canonical,
+            Err(error) => return self.report(importer, format!("{}: {}", path.display(), error)),
+        };
+        if self.stack.contains(&canonical) {
+            return self.report(importer, format!("circular import of {}", path.display()));
+        }
+        let source = match fs::read_to_string(&canonical) {
+            Ok(source) => source,
+            Err(error) => return self.report(importer, format!("{}: {}", path.display(), error)),
+        };
+        let (mut stylesheet, errors) = new_css_parser(source.clone()).parse_with_errors();
+        for error in errors {
+            let (line, column) = line_column(&source, error.offset);
+            self.report::<()>(path, format!("{}:{}: {}", line, column, error.message));
+        }
+        stylesheet.origin = self.origin;
+        set_file(&mut stylesheet.rules, &path.display().to_string());
+
+        self.stack.push(canonical);
+        self.resolve(&mut stylesheet.rules, path);
+        self.stack.pop();
+
+        Some(stylesheet)
+    }
+
+    fn resolve(&mut self, rules: &mut [CSSRule], path: &Path) {
+        for rule in rules {
+            if let CSSRule::Import(ref mut import_rule) = *rule {
+                if import_rule.url.contains("://") {
+                    self.report::<()>(
+                        path,
+                        format!(
+                            "cannot import {}: only local files are supported",
+                            import_rule.url
+                        ),
+                    );
+                    continue;
+                }
+                let target = path
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .join(&import_rule.url);
+                import_rule.stylesheet = self.load(&target, path).map(Box::new);
+            }
+        }
+    }
+
+    fn report<T>(&mut self, path: &Path, message: String) -> Option<T> {
+        self.diagnostics.push(Diagnostic {
+            path: path.to_path_buf(),
+            message,
+        });
+        None
+    }
+}
+
+#[test]
+fn test_resolve_imports() {
+    use style_sheet::media_query::{MediaEnvironment, MediaType};
+
+    let directory = std::env::temp_dir().join(format!("browser-import-{}", std::process::id()));
+    fs::create_dir_all(directory.join("parts")).unwrap();
+    let files = [
+        (
+            "main.css",
+            "@import url(parts/base.css); @import 'print.css' print; \
+             @import \"missing.css\"; a { width: 4px }",
+        ),
+        (
+            "parts/base.css",
+            "@import url(\"../main.css\"); a { width: 1px }",
+        ),
+        (
+            "print.css",
+            "@import url(parts/base.css) layer(base);\nb:frob { width: 3px } a { width: 2px }",
+        ),
+    ];
+    for &(name, css) in &files {
+        fs::write(directory.join(name), css).unwrap();
+    }
+
+    let main = directory.join("main.css");
+    let (stylesheet, diagnostics) = load_style_sheet(&main, Origin::User);
+    let widths = |media: MediaEnvironment| -> Vec<String> {
+        stylesheet
+            .style_rules(&media)
+            .iter()
+            .map(|rule| format!("{:?}", rule.declarations[0].value))
+            .collect()
+    };
+    let print = MediaEnvironment {
+        media_type: MediaType::Print,
+        ..Default::default()
+    };
+
+    // 読み込まれたルールは @import の位置に入る
+    assert_eq!(
+        widths(MediaEnvironment::default()),
+        vec!["Length(1.0, Px)", "Length(4.0, Px)"]
+    );
+    assert_eq!(
+        widths(print),
+        vec![
+            "Length(1.0, Px)",
+            "Length(1.0, Px)",
+            "Length(2.0, Px)",
+            "Length(4.0, Px)"
+        ]
+    );
+    match stylesheet.rules[1] {
+        CSSRule::Import(ref import_rule) => {
+            assert_eq!(
+                import_rule.stylesheet.as_ref().unwrap().origin,
+                Origin::User
+            )
+        }
+        ref rule => panic!("{:?} is not an @import", rule),
+    }
+
+    // base.css は main.css を読み込み返すので、どちらの経路でも循環になる
+    let messages: Vec<String> = diagnostics.iter().map(|d| d.message.clone()).collect();
+    // 無効なルールは読み込んだシートの位置つきで報告される
+    assert_eq!(diagnostics.len(), 4, "{:?}", messages);
+    assert!(messages[0].starts_with("circular import"));
+    assert_eq!(messages[1], "2:7: unknown pseudo-class :frob");
+    assert_eq!(diagnostics[1].path, directory.join("print.css"));
+    assert!(messages[2].starts_with("circular import"));
+    assert!(messages[3].contains("missing.css"));
+    assert_eq!(diagnostics[3].path, main);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -1,10 +1,20 @@
 use parser::interface::DefaultParserTrait;
+use parser::ParseError;
 use style_sheet::declaration::Declaration;
-use style_sheet::rule::Rule;
-use style_sheet::selector::{Selector, Specificity};
+use style_sheet::layer::LayerName;
+use style_sheet::math::MathExpression;
+use style_sheet::media_query::{
+    Comparison, MediaCondition, MediaFeature, MediaQuery, MediaQueryList, MediaValue,
+};
+use style_sheet::pseudo_class::PseudoClass;
+use style_sheet::pseudo_element::PseudoElement;
+use style_sheet::rule::{CSSRule, ImportRule};
+use style_sheet::selector::{RelativeSelector, Selector, Specificity};
 use style_sheet::simple_selector::SimpleSelector;
+use style_sheet::source_location::SourceLocation;
 use style_sheet::style_sheet::Stylesheet;
-use style_sheet::util::{Unit, Value};
+use style_sheet::supports::SupportsCondition;
+use style_sheet::util::{LengthContext, Value};
 
 pub trait SelectorTrait {
     fn specificity(&self) -> Specificity;
@@ -15,22 +25,68 @@ pub trait RuleTrait {}
 pub trait ColorTrait {}
 
 pub trait ValueTrait {
+    /// Converts a length to px, assuming the default font size and no percentage base.
     fn to_px(&self) -> f32;
+    fn resolve_px(&self, context: &LengthContext) -> f32;
 }
 
 pub trait CSSParserTrait: DefaultParserTrait {
     fn parse(&mut self) -> Stylesheet;
-    fn parse_rules(&mut self) -> Vec<Rule>;
-    fn parse_rule(&mut self) -> Rule;
-    fn parse_simple_selector(&mut self) -> SimpleSelector;
-    fn parse_selector(&mut self) -> Vec<Selector>;
-    fn parse_declarations(&mut self) -> Vec<Declaration>;
-    fn parse_declaration(&mut self) -> Declaration;
-    fn parse_value(&mut self) -> Value;
+    fn parse_with_errors(&mut self) -> (Stylesheet, Vec<ParseError>);
+    fn error<T>(&self, message: String) -> Result<T, ParseError>;
+    fn unexpected<T>(&self, context: &str) -> Result<T, ParseError>;
+    fn expect_char(&mut self, expected: char, context: &str) -> Result<(), ParseError>;
+    fn source_location(&self) -> SourceLocation;
+    fn parse_rules(&mut self) -> Vec<CSSRule>;
+    fn parse_at_rule(&mut self) -> Option<CSSRule>;
+    fn invalid_at_rule(&mut self, error: ParseError) -> Option<CSSRule>;
+    fn parse_nested_rules(&mut self) -> Result<Vec<CSSRule>, ParseError>;
+    fn parse_import_rule(&mut self) -> Result<ImportRule, ParseError>;
+    fn parse_layer_rule(&mut self) -> Result<CSSRule, ParseError>;
+    fn parse_layer_name(&mut self) -> Result<LayerName, ParseError>;
+    fn skip_at_rule(&mut self);
+    fn consume_keyword(&mut self, keyword: &str) -> bool;
+    fn parse_rule(&mut self) -> Vec<CSSRule>;
+    fn skip_rule(&mut self);
+    fn parse_style_block(&mut self, selectors: &[Selector]) -> Vec<CSSRule>;
+    fn parse_nested_group_rule(&mut self, selectors: &[Selector]) -> Option<CSSRule>;
+    fn at_nested_rule(&mut self) -> bool;
+    fn parse_nested_selector_list(
+        &mut self,
+        parents: &[Selector],
+    ) -> Result<Vec<Selector>, ParseError>;
+    fn parse_media_query_list(&mut self) -> MediaQueryList;
+    fn skip_media_query(&mut self);
+    fn parse_media_query(&mut self) -> Result<MediaQuery, ParseError>;
+    fn parse_media_condition(&mut self) -> Result<MediaCondition, ParseError>;
+    fn parse_media_in_parens(&mut self) -> Result<MediaCondition, ParseError>;
+    fn parse_media_feature(&mut self) -> Result<MediaFeature, ParseError>;
+    fn parse_comparison(&mut self) -> Result<Comparison, ParseError>;
+    fn parse_media_value(&mut self) -> Result<MediaValue, ParseError>;
+    fn parse_supports_condition(&mut self) -> Result<SupportsCondition, ParseError>;
+    fn parse_supports_in_parens(&mut self) -> Result<SupportsCondition, ParseError>;
+    fn parse_parenthesized(&mut self) -> Result<String, ParseError>;
+    fn parse_simple_selector(&mut self) -> Result<SimpleSelector, ParseError>;
+    fn parse_complex_selector(&mut self) -> Result<Selector, ParseError>;
+    fn parse_selector_list(&mut self) -> Result<Vec<Selector>, ParseError>;
+    fn parse_relative_selector_list(&mut self) -> Result<Vec<RelativeSelector>, ParseError>;
+    fn parse_pseudo_class(&mut self) -> Result<PseudoClass, ParseError>;
+    fn parse_pseudo_element(&mut self) -> Result<PseudoElement, ParseError>;
+    fn parse_selector(&mut self) -> Result<Vec<Selector>, ParseError>;
+    fn parse_declaration(&mut self) -> Result<Declaration, ParseError>;
+    fn skip_declaration(&mut self);
+    fn parse_value_list(&mut self) -> Result<Value, ParseError>;
+    fn parse_raw_value(&mut self) -> String;
+    fn parse_value(&mut self) -> Result<Value, ParseError>;
+    fn parse_arguments(&mut self) -> Result<Vec<Value>, ParseError>;
+    fn parse_url(&mut self) -> Result<String, ParseError>;
+    fn parse_math_function(&mut self, name: &str) -> Result<MathExpression, ParseError>;
+    fn parse_calc_sum(&mut self) -> Result<MathExpression, ParseError>;
+    fn parse_calc_product(&mut self) -> Result<MathExpression, ParseError>;
+    fn parse_calc_value(&mut self) -> Result<MathExpression, ParseError>;
     fn parse_length(&mut self) -> Value;
     fn parse_float(&mut self) -> f32;
-    fn parse_unit(&mut self) -> Unit;
-    fn parse_color(&mut self) -> Value;
-    fn parse_hex_pair(&mut self) -> u8;
+    fn parse_string(&mut self) -> String;
+    fn parse_color(&mut self) -> Result<Value, ParseError>;
     fn parse_identifier(&mut self) -> String;
 }
@@ -0,0 +1,75 @@
+// カスケードレイヤー @layer
+// レイヤーは最初に宣言された順に並び、レイヤーに属さないスタイルは最後の暗黙のレイヤーに入る
+// https://www.w3.org/TR/css-cascade-5/#layering
+
+/// A dotted layer name such as `framework.reset`. Anonymous layers have no segments.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LayerName(pub Vec<String>);
+
+impl LayerName {
+    pub fn is_anonymous(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The position of a rule's layer in the layer order of its origin; normal
+/// declarations in a greater layer win.
+/// Each element is the index of a layer among its siblings. The last element is
+/// `usize::MAX` for the rules directly in the layer, which come after its sublayers.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LayerKey(pub Vec<usize>);
+
+/*
+ 1つのオリジンで宣言されたレイヤーの木
+ 同じオリジンの複数のスタイルシートで共有し、同じ名前のレイヤーは同じレイヤーになる
+*/
+#[derive(Debug, Default)]
+pub struct LayerTree {
+    // 名前のない（匿名の）レイヤーはNone
+    sublayers: Vec<(Option<String>, LayerTree)>,
+}
+
+impl LayerTree {
+    /// Declares `name` inside the layer at `parent` (a key without its last element)
+    /// and returns the index path of the layer. Each anonymous name is a new layer.
+    pub fn declare(&mut self, parent: &[usize], name: &LayerName) -> Vec<usize> {
+        let mut path = parent.to_vec();
+        let mut layer = self.get_mut(parent);
+        if name.is_anonymous() {
+            layer.sublayers.push((None, LayerTree::default()));
+            path.push(layer.sublayers.len() - 1);
+            return path;
+        }
+        for segment in &name.0 {
+            let index = match layer
+                .sublayers
+                .iter()
+                .position(|(name, _)| name.as_ref() == Some(segment))
+            {
+                Some(index) => index,
+                None => {
+                    layer
+                        .sublayers
+                        .push((Some(segment.clone()), LayerTree::default()));
+                    layer.sublayers.len() - 1
+                }
+            };
+            path.push(index);
+            layer = &mut layer.sublayers[index].1;
+        }
+        path
+    }
+
+    fn get_mut(&mut self, path: &[usize]) -> &mut LayerTree {
+        path.iter()
+            .fold(self, |layer, &index| &mut layer.sublayers[index].1)
+    }
+}
+
+/// The key of the rules directly inside the layer at `path`; an empty path gives
+/// the key of unlayered rules, which come after every layer.
+pub fn layer_key(path: &[usize]) -> LayerKey {
+    let mut key = path.to_vec();
+    key.push(usize::MAX);
+    LayerKey(key)
+}
@@ -0,0 +1,407 @@
+// 数学関数 calc(), min(), max(), clamp()
+// パース時に可能な限り単純化し、%を含む式はレイアウト時に包含ブロックを基準に解決する
+// https://www.w3.org/TR/css-values-4/#math
+
+use style_sheet::util::{LengthContext, Unit, Value};
+
+pub const MATH_FUNCTIONS: [&str; 4] = ["calc", "min", "max", "clamp"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MathExpression {
+    Number(f32),
+    Length(f32, Unit),
+    /// Terms added together; `a - b` is stored as `a + (-1 * b)`.
+    Sum(Vec<MathExpression>),
+    Product(Vec<MathExpression>),
+    /// The right-hand side of a `/`.
+    Invert(Box<MathExpression>),
+    Min(Vec<MathExpression>),
+    Max(Vec<MathExpression>),
+    /// `clamp(min, value, max)`
+    Clamp(
+        Box<MathExpression>,
+        Box<MathExpression>,
+        Box<MathExpression>,
+    ),
+}
+
+/// What a math expression evaluates to; percentages count as lengths.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MathType {
+    Number,
+    Length,
+}
+
+impl MathExpression {
+    /// The type of the expression, or `None` if it combines types that do not
+    /// fit together, such as `1px * 1px`, `1px + 1` or `1 / 1px`.
+    pub fn math_type(&self) -> Option<MathType> {
+        let same_type = |expressions: &[MathExpression]| {
+            let first = expressions.first()?.math_type()?;
+            expressions.iter().try_fold(first, |math_type, expression| {
+                Some(math_type).filter(|&math_type| expression.math_type() == Some(math_type))
+            })
+        };
+        match *self {
+            MathExpression::Number(_) => Some(MathType::Number),
+            MathExpression::Length(..) => Some(MathType::Length),
+            MathExpression::Sum(ref arguments)
+            | MathExpression::Min(ref arguments)
+            | MathExpression::Max(ref arguments) => same_type(arguments),
+            MathExpression::Product(ref factors) => {
+                // 長さを掛けられるのは一つだけ
+                let mut lengths = 0;
+                for factor in factors {
+                    if factor.math_type()? == MathType::Length {
+                        lengths += 1;
+                    }
+                }
+                match lengths {
+                    0 => Some(MathType::Number),
+                    1 => Some(MathType::Length),
+                    _ => None,
+                }
+            }
+            // 割る数は数値でなければならない
+            MathExpression::Invert(ref divisor) => divisor
+                .math_type()
+                .filter(|&math_type| math_type == MathType::Number),
+            MathExpression::Clamp(ref min, ref value, ref max) => {
+                let math_type = value.math_type()?;
+                Some(math_type).filter(|&math_type| {
+                    min.math_type() == Some(math_type) && max.math_type() == Some(math_type)
+                })
+            }
+        }
+    }
+
+    /*
+     文脈なしでできる単純化を行う
+     数値同士の演算を畳み込み、和では同じ単位の項をまとめる
+    */
+    pub fn simplify(self) -> MathExpression {
+        match self {
+            MathExpression::Sum(terms) => {
+                let mut number: Option<f32> = None;
+                let mut lengths: Vec<(f32, Unit)> = Vec::new();
+                let mut others = Vec::new();
+                let mut pending: Vec<MathExpression> =
+                    terms.into_iter().map(MathExpression::simplify).collect();
+                while let Some(term) = pending.pop() {
+                    match term {
+                        MathExpression::Number(n) => number = Some(number.unwrap_or(0.0) + n),
+                        MathExpression::Length(length, unit) => {
+                            match lengths.iter_mut().find(|&&mut (_, u)| u == unit) {
+                                Some(entry) => entry.0 += length,
+                                None => lengths.push((length, unit)),
+                            }
+                        }
+                        MathExpression::Sum(inner) => pending.extend(inner),
+                        other => others.push(other),
+                    }
+                }
+                let mut terms: Vec<MathExpression> = number
+                    .map(MathExpression::Number)
+                    .into_iter()
+                    .chain(
+                        lengths
+                            .into_iter()
+                            .rev()
+                            .map(|(length, unit)| MathExpression::Length(length, unit)),
+                    )
+                    .chain(others.into_iter().rev())
+                    .collect();
+                match terms.len() {
+                    1 => terms.swap_remove(0),
+                    _ => MathExpression::Sum(terms),
+                }
+            }
+            MathExpression::Product(factors) => {
+                let mut scale = 1.0;
+                let mut others = Vec::new();
+                let mut pending: Vec<MathExpression> =
+                    factors.into_iter().map(MathExpression::simplify).collect();
+                while let Some(factor) = pending.pop() {
+                    match factor {
+                        MathExpression::Number(n) => scale *= n,
+                        MathExpression::Product(inner) => pending.extend(inner),
+                        other => others.push(other),
+                    }
+                }
+                match others.len() {
+                    0 => MathExpression::Number(scale),
+                    1 => others.swap_remove(0).scale(scale),
+                    _ => {
+                        others.reverse();
+                        if scale != 1.0 {
+                            others.insert(0, MathExpression::Number(scale));
+                        }
+                        MathExpression::Product(others)
+                    }
+                }
+            }
+            MathExpression::Invert(divisor) => match divisor.simplify() {
+                MathExpression::Number(n) => MathExpression::Number(1.0 / n),
+                divisor => MathExpression::Invert(Box::new(divisor)),
+            },
+            MathExpression::Min(arguments) => {
+                let arguments: Vec<_> = arguments.into_iter().map(|a| a.simplify()).collect();
+                fold_comparable(&arguments, f32::min).unwrap_or(MathExpression::Min(arguments))
+            }
+            MathExpression::Max(arguments) => {
+                let arguments: Vec<_> = arguments.into_iter().map(|a| a.simplify()).collect();
+                fold_comparable(&arguments, f32::max).unwrap_or(MathExpression::Max(arguments))
+            }
+            MathExpression::Clamp(min, value, max) => {
+                let (min, value, max) = (min.simplify(), value.simplify(), max.simplify());
+                let arguments = [min, value, max];
+                match comparable_unit(&arguments) {
+                    Some(unit) => {
+                        let [min, value, max] = arguments.map(|argument| argument.magnitude());
+                        leaf(value.min(max).max(min), unit)
+                    }
+                    None => {
+                        let [min, value, max] = arguments;
+                        MathExpression::Clamp(Box::new(min), Box::new(value), Box::new(max))
+                    }
+                }
+            }
+            leaf => leaf,
+        }
+    }
+
+    /// Converts every length except percentages to px, then simplifies.
+    pub fn compute(&self, context: &LengthContext) -> MathExpression {
+        let compute_all = |expressions: &[MathExpression]| {
+            expressions
+                .iter()
+                .map(|expression| expression.compute(context))
+                .collect()
+        };
+        let computed = match *self {
+            MathExpression::Length(length, unit) if unit != Unit::Percent => {
+                MathExpression::Length(length * unit.px_per_unit(context), Unit::Px)
+            }
+            MathExpression::Sum(ref terms) => MathExpression::Sum(compute_all(terms)),
+            MathExpression::Product(ref factors) => MathExpression::Product(compute_all(factors)),
+            MathExpression::Invert(ref divisor) => {
+                MathExpression::Invert(Box::new(divisor.compute(context)))
+            }
+            MathExpression::Min(ref arguments) => MathExpression::Min(compute_all(arguments)),
+            MathExpression::Max(ref arguments) => MathExpression::Max(compute_all(arguments)),
+            MathExpression::Clamp(ref min, ref value, ref max) => MathExpression::Clamp(
+                Box::new(min.compute(context)),
+                Box::new(value.compute(context)),
+                Box::new(max.compute(context)),
+            ),
+            ref leaf => leaf.clone(),
+        };
+        computed.simplify()
+    }
+
+    /// Evaluates the expression in px; percentages resolve against `context.percentage_base`.
+    pub fn resolve_px(&self, context: &LengthContext) -> f32 {
+        finite(self.evaluate_px(context))
+    }
+
+    fn evaluate_px(&self, context: &LengthContext) -> f32 {
+        let resolve_all = |expressions: &[MathExpression]| {
+            expressions
+                .iter()
+                .map(|expression| expression.evaluate_px(context))
+                .collect::<Vec<f32>>()
+        };
+        match *self {
+            MathExpression::Number(n) => n,
+            MathExpression::Length(length, unit) => length * unit.px_per_unit(context),
+            MathExpression::Sum(ref terms) => resolve_all(terms).iter().sum(),
+            MathExpression::Product(ref factors) => resolve_all(factors).iter().product(),
+            MathExpression::Invert(ref divisor) => 1.0 / divisor.evaluate_px(context),
+            MathExpression::Min(ref arguments) => resolve_all(arguments)
+                .into_iter()
+                .fold(f32::INFINITY, f32::min),
+            MathExpression::Max(ref arguments) => resolve_all(arguments)
+                .into_iter()
+                .fold(f32::NEG_INFINITY, f32::max),
+            MathExpression::Clamp(ref min, ref value, ref max) => value
+                .evaluate_px(context)
+                .min(max.evaluate_px(context))
+                .max(min.evaluate_px(context)),
+        }
+    }
+
+    /// Whether any term of the expression is a percentage.
+    pub fn has_percentage(&self) -> bool {
+        match *self {
+            MathExpression::Number(_) => false,
+            MathExpression::Length(_, unit) => unit == Unit::Percent,
+            MathExpression::Sum(ref arguments)
+            | MathExpression::Product(ref arguments)
+            | MathExpression::Min(ref arguments)
+            | MathExpression::Max(ref arguments) => {
+                arguments.iter().any(MathExpression::has_percentage)
+            }
+            MathExpression::Invert(ref divisor) => divisor.has_percentage(),
+            MathExpression::Clamp(ref min, ref value, ref max) => {
+                min.has_percentage() || value.has_percentage() || max.has_percentage()
+            }
+        }
+    }
+
+    /// A fully simplified single number or length becomes a plain `Value`.
+    pub fn into_value(self) -> Value {
+        match self {
+            MathExpression::Number(n) => Value::Number(finite(n)),
+            MathExpression::Length(length, unit) => Value::Length(finite(length), unit),
+            expression => Value::Math(expression),
+        }
+    }
+
+    pub fn negate(self) -> MathExpression {
+        self.scale(-1.0)
+    }
+
+    fn scale(self, factor: f32) -> MathExpression {
+        match self {
+            MathExpression::Number(n) => MathExpression::Number(n * factor),
+            MathExpression::Length(length, unit) => MathExpression::Length(length * factor, unit),
+            MathExpression::Sum(terms) => {
+                MathExpression::Sum(terms.into_iter().map(|term| term.scale(factor)).collect())
+            }
+            expression if factor == 1.0 => expression,
+            expression => MathExpression::Product(vec![MathExpression::Number(factor), expression]),
+        }
+    }
+
+    fn magnitude(&self) -> f32 {
+        match *self {
+            MathExpression::Number(n) | MathExpression::Length(n, _) => n,
+            _ => f32::NAN,
+        }
+    }
+}
+
+// 計算結果の無限大は表せる最大の値に、NaNは0にする
+fn finite(n: f32) -> f32 {
+    if n.is_nan() {
+        0.0
+    } else {
+        n.clamp(f32::MIN, f32::MAX)
+    }
+}
+
+// すべての引数が数値、または同じ単位の長さであればその単位を返す（数値はNone）
+fn comparable_unit(arguments: &[MathExpression]) -> Option<Option<Unit>> {
+    let unit = |argument: &MathExpression| match *argument {
+        MathExpression::Number(_) => Some(None),
+        MathExpression::Length(_, unit) => Some(Some(unit)),
+        _ => None,
+    };
+    let first = unit(arguments.first()?)?;
+    if arguments
+        .iter()
+        .all(|argument| unit(argument) == Some(first))
+    {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+fn fold_comparable(arguments: &[MathExpression], f: fn(f32, f32) -> f32) -> Option<MathExpression> {
+    let unit = comparable_unit(arguments)?;
+    let folded = arguments.iter().map(MathExpression::magnitude).reduce(f)?;
+    Some(leaf(folded, unit))
+}
+
+fn leaf(value: f32, unit: Option<Unit>) -> MathExpression {
+    match unit {
+        Some(unit) => MathExpression::Length(value, unit),
+        None => MathExpression::Number(value),
+    }
+}
+
+#[test]
+fn test_math_functions() {
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::{CSSParserTrait, ValueTrait};
+
+    let parse = |css: &str| new_css_parser(css.to_string()).parse_value_list().unwrap();
+    let context = LengthContext {
+        font_size: 20.0,
+        viewport_width: 1000.0,
+        percentage_base: 300.0,
+        ..Default::default()
+    };
+
+    // 数値と同じ単位の項はパース時に畳み込まれる
+    assert_eq!(parse("calc(2 * (3px + 1px))"), Value::Length(8.0, Unit::Px));
+    assert_eq!(parse("max(1em, 3em / 2)"), Value::Length(1.5, Unit::Em));
+    assert_eq!(parse("calc(10px - 1px * 4)"), Value::Length(6.0, Unit::Px));
+
+    let mixed = match parse("calc(100% - 2em)") {
+        Value::Math(expression) => expression,
+        value => panic!("{:?} should not be simplified", value),
+    };
+    assert_eq!(
+        mixed.compute(&context),
+        MathExpression::Sum(vec![
+            MathExpression::Length(100.0, Unit::Percent),
+            MathExpression::Length(-40.0, Unit::Px),
+        ])
+    );
+    assert_eq!(mixed.resolve_px(&context), 260.0);
+
+    let clamp = match parse("clamp(1rem, 2.5vw, 3rem)") {
+        Value::Math(expression) => expression,
+        value => panic!("{:?} should not be simplified", value),
+    };
+    assert_eq!(
+        clamp.compute(&context),
+        MathExpression::Length(25.0, Unit::Px)
+    );
+    assert_eq!(parse("min(100%, 500px)").resolve_px(&context), 300.0);
+}
+
+#[test]
+fn test_invalid_math_functions() {
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+    use style_sheet::serialize::serialize_value;
+
+    let parse = |css: &str| new_css_parser(css.to_string()).parse_value_list();
+
+    assert_eq!(
+        parse("calc(infinity * 1px)").unwrap(),
+        Value::Length(f32::MAX, Unit::Px)
+    );
+    assert_eq!(
+        parse("calc(-infinity * 1px)").unwrap(),
+        Value::Length(f32::MIN, Unit::Px)
+    );
+    assert_eq!(
+        parse("calc(NaN * 1px)").unwrap(),
+        Value::Length(0.0, Unit::Px)
+    );
+    assert_eq!(
+        parse("calc(1px / 0)").unwrap(),
+        Value::Length(f32::MAX, Unit::Px)
+    );
+    let infinite = parse("calc(100% - infinity * 1px)").unwrap();
+    assert_eq!(serialize_value(&infinite), "calc(100% - infinity * 1px)");
+    // 型の合わない式や知らないトークンを含む式は無効
+    for css in [
+        "calc(foo * 1px)",
+        "calc(1px * 1px)",
+        "calc(1px + 1)",
+        "calc(1 / 1px)",
+        "min(1px, 2)",
+        "clamp(1px, 2, 3px)",
+        "calc(1px + )",
+        "calc(1px",
+        "calc(1deg)",
+    ] {
+        let message = parse(css).err().map(|error| error.message);
+        assert!(message.is_some(), "{} should be invalid", css);
+    }
+}
@@ -0,0 +1,323 @@
+// メディアクエリ
+// https://www.w3.org/TR/mediaqueries-4/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaType {
+    All,
+    Screen,
+    Print,
+    /// 未知のメディアタイプはどの環境にもマッチしない
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+/// What media queries are evaluated against: the output device and the viewport.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MediaEnvironment {
+    /// Screen or Print
+    pub media_type: MediaType,
+    pub width: f32,
+    pub height: f32,
+    /// Device pixels per CSS px (`dppx`)
+    pub resolution: f32,
+    pub prefers_color_scheme: ColorScheme,
+}
+
+impl Default for MediaEnvironment {
+    fn default() -> MediaEnvironment {
+        MediaEnvironment {
+            media_type: MediaType::Screen,
+            width: 0.0,
+            height: 0.0,
+            resolution: 1.0,
+            prefers_color_scheme: ColorScheme::Light,
+        }
+    }
+}
+
+/// A comma-separated list of queries; matches if any query matches.
+/// An empty list matches every environment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQueryList(pub Vec<MediaQuery>);
+
+/// `[not | only]? <media-type> [and <condition>]?` or just `<condition>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    pub negated: bool,
+    pub media_type: MediaType,
+    pub condition: Option<MediaCondition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaCondition {
+    Feature(MediaFeature),
+    Not(Box<MediaCondition>),
+    And(Vec<MediaCondition>),
+    Or(Vec<MediaCondition>),
+}
+
+/// `(width)`, `(min-width: 600px)` or `(600px <= width < 900px)`.
+/// `min-`/`max-` prefixes and ranges are normalized into comparisons of the
+/// feature against a value; a boolean feature has no comparisons.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaFeature {
+    pub name: String,
+    pub comparisons: Vec<(Comparison, MediaValue)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// Lengths are in px and resolutions in dppx; relative lengths are resolved
+/// against the initial font size.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaValue {
+    Number(f32),
+    Length(f32),
+    Resolution(f32),
+    Keyword(String),
+}
+
+impl MediaType {
+    pub fn from_name(name: &str) -> MediaType {
+        match &*name.to_ascii_lowercase() {
+            "all" => MediaType::All,
+            "screen" => MediaType::Screen,
+            "print" => MediaType::Print,
+            _ => MediaType::Unknown,
+        }
+    }
+}
+
+impl Comparison {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Comparison::Equal => "=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+
+    /// `600px < width` is evaluated as `width > 600px`.
+    pub fn flip(self) -> Comparison {
+        match self {
+            Comparison::Equal => Comparison::Equal,
+            Comparison::Less => Comparison::Greater,
+            Comparison::LessOrEqual => Comparison::GreaterOrEqual,
+            Comparison::Greater => Comparison::Less,
+            Comparison::GreaterOrEqual => Comparison::LessOrEqual,
+        }
+    }
+
+    fn compare(self, feature: f32, value: f32) -> bool {
+        match self {
+            Comparison::Equal => feature == value,
+            Comparison::Less => feature < value,
+            Comparison::LessOrEqual => feature <= value,
+            Comparison::Greater => feature > value,
+            Comparison::GreaterOrEqual => feature >= value,
+        }
+    }
+}
+
+impl MediaQueryList {
+    pub fn matches(&self, environment: &MediaEnvironment) -> bool {
+        self.0.is_empty() || self.0.iter().any(|query| query.matches(environment))
+    }
+}
+
+impl MediaQuery {
+    /// `not all`, which never matches; a malformed media query evaluates to this.
+    pub fn not_all() -> MediaQuery {
+        MediaQuery {
+            negated: true,
+            media_type: MediaType::All,
+            condition: None,
+        }
+    }
+
+    pub fn matches(&self, environment: &MediaEnvironment) -> bool {
+        let type_matches = match self.media_type {
+            MediaType::All => true,
+            MediaType::Unknown => false,
+            media_type => media_type == environment.media_type,
+        };
+        let matches = type_matches
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.matches(environment));
+        matches != self.negated
+    }
+}
+
+impl MediaCondition {
+    pub fn matches(&self, environment: &MediaEnvironment) -> bool {
+        match *self {
+            MediaCondition::Feature(ref feature) => feature.matches(environment),
+            MediaCondition::Not(ref condition) => !condition.matches(environment),
+            MediaCondition::And(ref conditions) => conditions
+                .iter()
+                .all(|condition| condition.matches(environment)),
+            MediaCondition::Or(ref conditions) => conditions
+                .iter()
+                .any(|condition| condition.matches(environment)),
+        }
+    }
+}
+
+impl MediaFeature {
+    // 未知の特性や型の合わない値はマッチしない
+    pub fn matches(&self, environment: &MediaEnvironment) -> bool {
+        let actual = match &*self.name {
+            "width" => MediaValue::Length(environment.width),
+            "height" => MediaValue::Length(environment.height),
+            "aspect-ratio" => MediaValue::Number(environment.width / environment.height),
+            "resolution" => MediaValue::Resolution(environment.resolution),
+            "orientation" if environment.height >= environment.width => {
+                MediaValue::Keyword("portrait".to_string())
+            }
+            "orientation" => MediaValue::Keyword("landscape".to_string()),
+            "prefers-color-scheme" => MediaValue::Keyword(
+                match environment.prefers_color_scheme {
+                    ColorScheme::Light => "light",
+                    ColorScheme::Dark => "dark",
+                }
+                .to_string(),
+            ),
+            _ => return false,
+        };
+        if self.comparisons.is_empty() {
+            // 真偽値コンテキストでは0以外の値がマッチする
+            return match actual {
+                MediaValue::Number(n) | MediaValue::Length(n) | MediaValue::Resolution(n) => {
+                    n != 0.0
+                }
+                MediaValue::Keyword(_) => true,
+            };
+        }
+        self.comparisons
+            .iter()
+            .all(|&(comparison, ref value)| match (&actual, value) {
+                (&MediaValue::Length(a), &MediaValue::Length(b))
+                | (&MediaValue::Resolution(a), &MediaValue::Resolution(b))
+                | (&MediaValue::Number(a), &MediaValue::Number(b)) => comparison.compare(a, b),
+                (&MediaValue::Length(a), &MediaValue::Number(b)) if b == 0.0 => {
+                    comparison.compare(a, b)
+                }
+                (MediaValue::Keyword(a), MediaValue::Keyword(b)) => {
+                    comparison == Comparison::Equal && a.eq_ignore_ascii_case(b)
+                }
+                _ => false,
+            })
+    }
+}
+
+#[test]
+fn test_media_queries() {
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+
+    let css = "a { width: 0px } \
+               @media screen and (min-width: 600px) { a { width: 1px } } \
+               @media (400px <= width < 900px) and (orientation: landscape) { a { width: 2px } } \
+               @media not print { @media (prefers-color-scheme: dark) or (min-resolution: 2dppx) { a { width: 3px } } } \
+               @media print, (max-width: 30em) { a { width: 4px } } \
+               @font-face { font-family: x } \
+               @media tv { a { width: 5px } }";
+    let stylesheet = new_css_parser(css.to_string()).parse();
+    let widths = |media: MediaEnvironment| -> Vec<String> {
+        stylesheet
+            .style_rules(&media)
+            .iter()
+            .map(|rule| format!("{:?}", rule.declarations[0].value))
+            .collect()
+    };
+    let phone = MediaEnvironment {
+        width: 375.0,
+        height: 667.0,
+        resolution: 2.0,
+        ..Default::default()
+    };
+    let desktop = MediaEnvironment {
+        width: 1280.0,
+        height: 800.0,
+        prefers_color_scheme: ColorScheme::Dark,
+        ..Default::default()
+    };
+    let tablet_print = MediaEnvironment {
+        media_type: MediaType::Print,
+        width: 800.0,
+        height: 600.0,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        widths(phone),
+        vec!["Length(0.0, Px)", "Length(3.0, Px)", "Length(4.0, Px)"]
+    );
+    assert_eq!(
+        widths(desktop),
+        vec!["Length(0.0, Px)", "Length(1.0, Px)", "Length(3.0, Px)"]
+    );
+    assert_eq!(
+        widths(tablet_print),
+        vec!["Length(0.0, Px)", "Length(2.0, Px)", "Length(4.0, Px)"]
+    );
+}
+
+#[test]
+fn test_invalid_media_queries() {
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+    use style_sheet::rule::CSSRule;
+
+    let css = "@media (800px) { a { width: 1px } } \
+               @media screen and (width > 100px) or print { a { width: 2px } } \
+               @media screen and (width > 100px) or (color) { a { width: 2px } } \
+               @media (width) and (color) or (hover), screen { a { width: 3px } } \
+               @media not { a { width: 4px } } \
+               a { width: 5px } } b { width: 6px }";
+    let (stylesheet, errors) = new_css_parser(css.to_string()).parse_with_errors();
+    let widths: Vec<String> = stylesheet
+        .style_rules(&MediaEnvironment::default())
+        .iter()
+        .map(|rule| format!("{:?}", rule.declarations[0].value))
+        .collect();
+    // 無効なメディアクエリは not all になり、リストの他のクエリは使われる
+    assert_eq!(
+        widths,
+        vec!["Length(3.0, Px)", "Length(5.0, Px)", "Length(6.0, Px)"]
+    );
+    match stylesheet.rules[0] {
+        CSSRule::Media(ref media_rule) => {
+            assert_eq!(media_rule.queries.0, vec![MediaQuery::not_all()])
+        }
+        ref rule => panic!("{:?} is not a @media rule", rule),
+    }
+    let messages: Vec<&str> = errors.iter().map(|error| &*error.message).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "unexpected ) in media feature",
+            "unexpected p in media condition",
+            "or after a media type",
+            "and and or mixed without parentheses",
+            "unexpected { in media query",
+            "unexpected } in stylesheet",
+        ]
+    );
+}
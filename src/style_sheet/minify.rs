@@ -0,0 +1,221 @@
+// パース済みのスタイルシートを、適用結果を変えずに小さくする
+// コメントと空白はパーサーとシリアライザーで落ちるので、ここではルールと値を整理する
+
+use std::collections::HashMap;
+use style_sheet::css_parser::new_css_parser;
+use style_sheet::declaration::Declaration;
+use style_sheet::interface::{CSSParserTrait, SelectorTrait};
+use style_sheet::rule::{same_declarations, same_selectors, CSSRule};
+use style_sheet::serialize::{serialize, Format};
+use style_sheet::shorthand::{combine_shorthands, expand_shorthand};
+use style_sheet::style_sheet::Stylesheet;
+use style_sheet::supports::supports_declaration;
+use style_sheet::util::{Unit, Value};
+
+/// Rewrites `stylesheet` into an equivalent one with fewer rules and declarations:
+/// overridden declarations and empty rules are dropped, adjacent rules with the
+/// same selectors or the same declarations are merged, complete sets of
+/// longhands become their shorthand, and zero lengths lose their unit.
+/// Overridden declarations this engine cannot parse are kept as fallbacks.
+pub fn minify(stylesheet: &Stylesheet) -> Stylesheet {
+    Stylesheet {
+        rules: minify_rules(stylesheet.rules.clone()),
+        origin: stylesheet.origin,
+    }
+}
+
+/// Parses `source` and writes it back as minified CSS.
+pub fn minify_css(source: String) -> String {
+    let stylesheet = new_css_parser(source).parse();
+    serialize(&minify(&stylesheet), Format::Minified)
+}
+
+fn minify_rules(rules: Vec<CSSRule>) -> Vec<CSSRule> {
+    let mut minified: Vec<CSSRule> = Vec::new();
+    for rule in rules {
+        let rule = match rule {
+            CSSRule::Style(mut rule) => {
+                rule.declarations = minify_declarations(rule.declarations);
+                CSSRule::Style(rule)
+            }
+            CSSRule::Media(mut media_rule) => {
+                media_rule.rules = minify_rules(media_rule.rules);
+                CSSRule::Media(media_rule)
+            }
+            CSSRule::Supports(mut supports_rule) => {
+                supports_rule.rules = minify_rules(supports_rule.rules);
+                CSSRule::Supports(supports_rule)
+            }
+            CSSRule::Layer(mut layer_rule) => {
+                layer_rule.rules = minify_rules(layer_rule.rules);
+                CSSRule::Layer(layer_rule)
+            }
+            rule => rule,
+        };
+        if is_empty(&rule) {
+            continue;
+        }
+        // 直前のルールとまとめられればまとめる
+        if let (Some(CSSRule::Style(previous)), CSSRule::Style(current)) =
+            (minified.last_mut(), &rule)
+        {
+            if same_selectors(&previous.selectors, &current.selectors) {
+                // まとめたショートハンドは展開し直してから重複を除く
+                let declarations = previous
+                    .declarations
+                    .iter()
+                    .chain(current.declarations.iter())
+                    .cloned()
+                    .flat_map(expand_shorthand)
+                    .collect();
+                previous.declarations = minify_declarations(declarations);
+                continue;
+            }
+            if same_declarations(&previous.declarations, &current.declarations) {
+                previous.selectors.extend(current.selectors.iter().cloned());
+                // 最も詳細度の高いセレクターを先に置く
+                previous
+                    .selectors
+                    .sort_by_key(|selector| std::cmp::Reverse(selector.specificity()));
+                previous.selectors.dedup_by(|a, b| a.same_content(b));
+                continue;
+            }
+        }
+        minified.push(rule);
+    }
+    minified
+}
+
+// 宣言のない規則と、ルールを含まない条件付きルールは何もしない
+// 名前付きの @layer は空でもレイヤーの順番を宣言するので残す
+fn is_empty(rule: &CSSRule) -> bool {
+    match *rule {
+        CSSRule::Style(ref rule) => rule.declarations.is_empty(),
+        CSSRule::Media(ref media_rule) => media_rule.rules.is_empty(),
+        CSSRule::Supports(ref supports_rule) => supports_rule.rules.is_empty(),
+        CSSRule::Layer(ref layer_rule) => {
+            layer_rule.rules.is_empty() && layer_rule.name.is_anonymous()
+        }
+        CSSRule::LayerStatement(_) | CSSRule::Import(_) => false,
+    }
+}
+
+/*
+ 同じプロパティの宣言は、カスケードで勝つものだけを残す
+ !importantな宣言があればその最後のもの、なければ最後のもの
+ ただし、どちらかがこのエンジンで解釈できない値なら、他のブラウザ向けのフォールバックとして両方残す
+ 最後にロングハンドがそろっているものはショートハンドにまとめる
+*/
+fn minify_declarations(declarations: Vec<Declaration>) -> Vec<Declaration> {
+    let mut winners: HashMap<&str, (usize, bool)> = HashMap::new();
+    for (index, declaration) in declarations.iter().enumerate() {
+        let winner = winners
+            .entry(&declaration.name)
+            .or_insert((index, declaration.important));
+        if declaration.important || !winner.1 {
+            *winner = (index, declaration.important);
+        }
+    }
+    let minified = declarations
+        .iter()
+        .enumerate()
+        .filter(|&(index, declaration)| {
+            let winner = winners[&*declaration.name].0;
+            index == winner
+                || !(supports_declaration(declaration)
+                    && supports_declaration(&declarations[winner]))
+        })
+        .map(|(_, declaration)| {
+            let mut declaration = declaration.clone();
+            declaration.value = minify_value(declaration.value);
+            declaration
+        })
+        .collect();
+    combine_shorthands(minified)
+}
+
+// 0の長さは単位を省く（%は省けない）
+fn minify_value(value: Value) -> Value {
+    match value {
+        Value::Length(length, unit) if length == 0.0 && unit != Unit::Percent => Value::Number(0.0),
+        Value::List(values) => Value::List(values.into_iter().map(minify_value).collect()),
+        value => value,
+    }
+}
+
+#[test]
+fn test_minify() {
+    let css = "/* reset */ \
+               h1 { margin-top: 0px; color: #ff0000; color: rgb(0 0 255) } \
+               h1 { padding-left: 0.5em; /* spacing */ padding-left: 0em } \
+               h2 { color: blue !important; color: red } \
+               p {} \
+               @media print { a { } } \
+               @layer base { } \
+               .x { background-color: rgba(255, 255, 255, 0.5) } \
+               .y { background-color: rgba(255, 255, 255, 0.5) } \
+               @media screen { em { content: \"/* not a comment */\" } }";
+
+    assert_eq!(
+        minify_css(css.to_string()),
+        "h1{margin-top:0;color:#00f;padding-left:0}\
+         h2{color:blue!important}\
+         @layer base{}\
+         .x,.y{background-color:#ffffff80}\
+         @media screen{em{content:\"/* not a comment */\"}}"
+    );
+}
+
+#[test]
+fn test_minify_shorthands() {
+    // パース時に展開されたショートハンドは組み立て直される
+    for &(css, minified) in &[
+        ("a{margin:0}", "a{margin:0}"),
+        ("a{border:1px solid red}", "a{border:1px solid red}"),
+        ("a{background:url(x.png)}", "a{background:url(x.png)}"),
+        (
+            "a { padding: 1px 2px 1px; border-top: 2px dashed; border-color: blue }",
+            "a{padding:1px 2px;border-top-width:2px;border-top-style:dashed;border-color:blue}",
+        ),
+        (
+            "a { font: italic bold 12px/1.5 serif, x; flex: 1; list-style: none inside }",
+            "a{font:italic bold 12px/1.5 serif,x;flex:1;list-style:inside none}",
+        ),
+        (
+            "a { background: url('a b.png') no-repeat center / cover, #fff }",
+            "a{background:url(\"a b.png\") center/cover no-repeat,#fff}",
+        ),
+        (
+            "a { margin: var(--m); border: inherit }",
+            "a{margin:var(--m);border:inherit}",
+        ),
+        // !importantの異なるロングハンドはまとめない
+        (
+            "a { margin: 0; margin-left: 1px !important }",
+            "a{margin-top:0;margin-right:0;margin-bottom:0;margin-left:1px!important}",
+        ),
+    ] {
+        assert_eq!(minify_css(css.to_string()), minified);
+    }
+
+    let css = "body { margin: 0; padding: 0 16px; font: 16px/1.4 sans-serif; color: #333333 } \
+               .card { border: 1px solid #dddddd; border-radius: 4px; background: #ffffff url(bg.png) no-repeat } \
+               .card h2 { margin: 0 0 8px; font-weight: bold } \
+               ul.menu { list-style: none; padding: 0 } \
+               .row { display: flex; flex: 1 1 auto }";
+    let minified = minify_css(css.to_string());
+    assert!(minified.len() <= css.len(), "{}", minified);
+    for css in css.split("} ") {
+        let css = format!("{}}}", css.trim_end_matches('}'));
+        assert!(minify_css(css.clone()).len() <= css.len(), "{}", css);
+    }
+}
+
+#[test]
+fn test_minify_fallbacks() {
+    // 後の宣言が解釈できない値なら、前の宣言はフォールバックとして残す
+    assert_eq!(
+        minify_css("a { color: red; color: lab(50% 40 59); display: block; display: ruby; width: 1px; width: 2px }".to_string()),
+        "a{color:red;color:lab(50% 40 59);display:block;display:ruby;width:2px}"
+    );
+}
@@ -1,8 +1,26 @@
+pub mod bloom_filter;
+pub mod color;
 pub mod css_parser;
 pub mod declaration;
+pub mod import;
 pub mod interface;
+pub mod layer;
+pub mod math;
+pub mod media_query;
+pub mod minify;
+pub mod property;
+pub mod pseudo_class;
+pub mod pseudo_element;
 pub mod rule;
 pub mod selector;
+pub mod selector_map;
+pub mod serialize;
+pub mod shorthand;
 pub mod simple_selector;
+pub mod source_location;
+#[allow(clippy::module_inception)]
 pub mod style_sheet;
+pub mod supports;
+pub mod user_agent;
 pub mod util;
+pub mod variable;
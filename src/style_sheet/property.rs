@@ -0,0 +1,159 @@
+// プロパティの定義（継承するかどうかと初期値）
+// https://www.w3.org/TR/css-cascade-4/#initial-values
+// https://www.w3.org/TR/css-cascade-4/#inheriting
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use style_sheet::css_parser::new_css_parser;
+use style_sheet::interface::CSSParserTrait;
+use style_sheet::util::Value;
+
+/// Keywords every property accepts.
+pub const CSS_WIDE_KEYWORDS: [&str; 5] = ["inherit", "initial", "unset", "revert", "revert-layer"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyDefinition {
+    pub name: &'static str,
+    pub inherited: bool,
+    pub initial: Value,
+}
+
+// (プロパティ名, 継承するか, 初期値)
+const PROPERTIES: [(&str, bool, &str); 88] = [
+    // ボックス
+    ("display", false, "inline"),
+    ("position", false, "static"),
+    ("float", false, "none"),
+    ("clear", false, "none"),
+    ("box-sizing", false, "content-box"),
+    ("overflow-x", false, "visible"),
+    ("overflow-y", false, "visible"),
+    ("visibility", true, "visible"),
+    ("z-index", false, "auto"),
+    ("opacity", false, "1"),
+    ("vertical-align", false, "baseline"),
+    ("width", false, "auto"),
+    ("height", false, "auto"),
+    ("min-width", false, "auto"),
+    ("min-height", false, "auto"),
+    ("max-width", false, "none"),
+    ("max-height", false, "none"),
+    ("top", false, "auto"),
+    ("right", false, "auto"),
+    ("bottom", false, "auto"),
+    ("left", false, "auto"),
+    ("margin-top", false, "0px"),
+    ("margin-right", false, "0px"),
+    ("margin-bottom", false, "0px"),
+    ("margin-left", false, "0px"),
+    ("padding-top", false, "0px"),
+    ("padding-right", false, "0px"),
+    ("padding-bottom", false, "0px"),
+    ("padding-left", false, "0px"),
+    // ボーダー
+    ("border-top-width", false, "medium"),
+    ("border-right-width", false, "medium"),
+    ("border-bottom-width", false, "medium"),
+    ("border-left-width", false, "medium"),
+    ("border-top-style", false, "none"),
+    ("border-right-style", false, "none"),
+    ("border-bottom-style", false, "none"),
+    ("border-left-style", false, "none"),
+    ("border-top-color", false, "currentcolor"),
+    ("border-right-color", false, "currentcolor"),
+    ("border-bottom-color", false, "currentcolor"),
+    ("border-left-color", false, "currentcolor"),
+    ("border-collapse", true, "separate"),
+    ("border-spacing", true, "0px"),
+    ("outline-width", false, "medium"),
+    ("outline-style", false, "none"),
+    ("outline-color", false, "currentcolor"),
+    // 背景
+    ("background-color", false, "transparent"),
+    ("background-image", false, "none"),
+    ("background-repeat", false, "repeat"),
+    ("background-attachment", false, "scroll"),
+    ("background-position", false, "0% 0%"),
+    ("background-size", false, "auto"),
+    ("background-origin", false, "padding-box"),
+    ("background-clip", false, "border-box"),
+    // フレックスボックス
+    ("flex-grow", false, "0"),
+    ("flex-shrink", false, "1"),
+    ("flex-basis", false, "auto"),
+    ("flex-direction", false, "row"),
+    ("flex-wrap", false, "nowrap"),
+    // フォントとテキスト
+    ("color", true, "black"),
+    ("font-family", true, "serif"),
+    ("font-size", true, "medium"),
+    ("font-style", true, "normal"),
+    ("font-variant", true, "normal"),
+    ("font-weight", true, "normal"),
+    ("font-stretch", true, "normal"),
+    ("line-height", true, "normal"),
+    ("letter-spacing", true, "normal"),
+    ("word-spacing", true, "normal"),
+    ("text-align", true, "start"),
+    ("text-indent", true, "0px"),
+    ("text-transform", true, "none"),
+    ("text-decoration-line", false, "none"),
+    ("text-decoration-color", false, "currentcolor"),
+    ("white-space", true, "normal"),
+    ("direction", true, "ltr"),
+    ("cursor", true, "auto"),
+    ("caret-color", true, "auto"),
+    // リスト
+    ("list-style-type", true, "disc"),
+    ("list-style-position", true, "outside"),
+    ("list-style-image", true, "none"),
+    // 生成コンテンツ
+    ("content", false, "normal"),
+    ("quotes", true, "auto"),
+    ("counter-reset", false, "none"),
+    ("counter-increment", false, "none"),
+    ("counter-set", false, "none"),
+    ("column-rule-color", false, "currentcolor"),
+    ("fill", true, "black"),
+];
+
+fn registry() -> &'static HashMap<&'static str, PropertyDefinition> {
+    static REGISTRY: OnceLock<HashMap<&'static str, PropertyDefinition>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        PROPERTIES
+            .iter()
+            .map(|&(name, inherited, initial)| {
+                let initial = new_css_parser(initial.to_string())
+                    .parse_value_list()
+                    .unwrap();
+                (
+                    name,
+                    PropertyDefinition {
+                        name,
+                        inherited,
+                        initial,
+                    },
+                )
+            })
+            .collect()
+    })
+}
+
+/// Looks up a longhand property; `None` for shorthands and unknown properties.
+pub fn lookup(name: &str) -> Option<&'static PropertyDefinition> {
+    registry().get(name)
+}
+
+/// All inherited longhand properties.
+pub fn inherited_properties() -> impl Iterator<Item = &'static PropertyDefinition> {
+    registry()
+        .values()
+        .filter(|definition| definition.inherited)
+}
+
+pub fn is_css_wide_keyword(value: &Value) -> bool {
+    match *value {
+        Value::Keyword(ref keyword) => CSS_WIDE_KEYWORDS.contains(&&**keyword),
+        _ => false,
+    }
+}
@@ -0,0 +1,83 @@
+use style_sheet::interface::SelectorTrait;
+use style_sheet::selector::{max_specificity, RelativeSelector, Selector, Specificity};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PseudoClass {
+    /// `:not(<selector-list>)`
+    Not(Vec<Selector>),
+    /// `:is(<selector-list>)` (`:matches()` is accepted as an alias)
+    Is(Vec<Selector>),
+    /// `:where(<selector-list>)`
+    Where(Vec<Selector>),
+    /// `:has(<relative-selector-list>)`
+    Has(Vec<RelativeSelector>),
+    Hover,
+    Active,
+    Focus,
+    FocusVisible,
+    FocusWithin,
+    Checked,
+    Disabled,
+    Enabled,
+    Link,
+    Visited,
+    AnyLink,
+    Target,
+}
+
+impl PseudoClass {
+    /// Looks up a non-functional pseudo-class such as `hover` by name.
+    pub fn from_name(name: &str) -> Option<PseudoClass> {
+        match name {
+            "hover" => Some(PseudoClass::Hover),
+            "active" => Some(PseudoClass::Active),
+            "focus" => Some(PseudoClass::Focus),
+            "focus-visible" => Some(PseudoClass::FocusVisible),
+            "focus-within" => Some(PseudoClass::FocusWithin),
+            "checked" => Some(PseudoClass::Checked),
+            "disabled" => Some(PseudoClass::Disabled),
+            "enabled" => Some(PseudoClass::Enabled),
+            "link" => Some(PseudoClass::Link),
+            "visited" => Some(PseudoClass::Visited),
+            "any-link" => Some(PseudoClass::AnyLink),
+            "target" => Some(PseudoClass::Target),
+            _ => None,
+        }
+    }
+
+    /// The name of the pseudo-class, without its arguments.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PseudoClass::Not(_) => "not",
+            PseudoClass::Is(_) => "is",
+            PseudoClass::Where(_) => "where",
+            PseudoClass::Has(_) => "has",
+            PseudoClass::Hover => "hover",
+            PseudoClass::Active => "active",
+            PseudoClass::Focus => "focus",
+            PseudoClass::FocusVisible => "focus-visible",
+            PseudoClass::FocusWithin => "focus-within",
+            PseudoClass::Checked => "checked",
+            PseudoClass::Disabled => "disabled",
+            PseudoClass::Enabled => "enabled",
+            PseudoClass::Link => "link",
+            PseudoClass::Visited => "visited",
+            PseudoClass::AnyLink => "any-link",
+            PseudoClass::Target => "target",
+        }
+    }
+}
+
+impl SelectorTrait for PseudoClass {
+    // https://www.w3.org/TR/selectors-4/#specificity-rules
+    fn specificity(&self) -> Specificity {
+        match *self {
+            PseudoClass::Where(_) => (0, 0, 0),
+            PseudoClass::Not(ref selectors) | PseudoClass::Is(ref selectors) => {
+                max_specificity(selectors)
+            }
+            PseudoClass::Has(ref selectors) => max_specificity(selectors),
+            _ => (0, 1, 0),
+        }
+    }
+}
@@ -1,8 +1,96 @@
 use style_sheet::declaration::Declaration;
+use style_sheet::layer::LayerName;
+use style_sheet::media_query::MediaQueryList;
 use style_sheet::selector::Selector;
+use style_sheet::source_location::SourceLocation;
+use style_sheet::style_sheet::Stylesheet;
+use style_sheet::supports::SupportsCondition;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
 }
+
+impl Rule {
+    /// Whether `self` and `other` have the same selectors and declarations,
+    /// wherever they were written.
+    pub fn same_content(&self, other: &Rule) -> bool {
+        same_selectors(&self.selectors, &other.selectors)
+            && same_declarations(&self.declarations, &other.declarations)
+    }
+
+    /// Where the rule starts in the source: the earliest of its selectors.
+    pub fn location(&self) -> Option<&SourceLocation> {
+        self.selectors
+            .iter()
+            .map(Selector::location)
+            .min_by_key(|location| location.offset)
+    }
+}
+
+/// Whether two selector lists are the same, ignoring where they were written.
+pub fn same_selectors(a: &[Selector], b: &[Selector]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.same_content(b))
+}
+
+/// Whether two declaration lists are the same, ignoring where they were written.
+pub fn same_declarations(a: &[Declaration], b: &[Declaration]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.same_content(b))
+}
+
+/// An item of a stylesheet: a style rule or an at-rule that may contain rules.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CSSRule {
+    Style(Rule),
+    Media(MediaRule),
+    Import(ImportRule),
+    Supports(SupportsRule),
+    Layer(LayerRule),
+    /// `@layer <name>#;` declares the order of layers without adding rules.
+    LayerStatement(Vec<LayerName>),
+}
+
+/// `@media <query-list> { <rules> }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRule {
+    pub queries: MediaQueryList,
+    pub rules: Vec<CSSRule>,
+}
+
+/// `@supports <condition> { <rules> }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupportsRule {
+    pub condition: SupportsCondition,
+    pub rules: Vec<CSSRule>,
+}
+
+/// `@layer <name>? { <rules> }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerRule {
+    pub name: LayerName,
+    pub rules: Vec<CSSRule>,
+}
+
+/// `@import <url> [layer | layer(<name>)]? <media-query-list>?;`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportRule {
+    pub url: String,
+    /// The layer the imported rules go into; anonymous for a bare `layer`.
+    pub layer: Option<LayerName>,
+    /// The `supports()` condition the import depends on.
+    pub supports: Option<SupportsCondition>,
+    pub queries: MediaQueryList,
+    /// The imported sheet, filled in by `import::resolve_imports`.
+    /// Stays `None` if the file could not be loaded.
+    pub stylesheet: Option<Box<Stylesheet>>,
+}
+
+impl CSSRule {
+    pub fn as_style(&self) -> Option<&Rule> {
+        match *self {
+            CSSRule::Style(ref rule) => Some(rule),
+            _ => None,
+        }
+    }
+}
@@ -1,19 +1,222 @@
 use style_sheet::interface::SelectorTrait;
+use style_sheet::pseudo_class::PseudoClass;
 use style_sheet::simple_selector::SimpleSelector;
+use style_sheet::source_location::SourceLocation;
 
 pub type Specificity = (usize, usize, usize);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+    /// `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+    /// `a + b`
+    NextSibling,
+    /// `a ~ b`
+    SubsequentSibling,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Selector {
     Simple(SimpleSelector),
+    /// 右端の複合セレクターと、コンビネーターで繋がった左側のセレクター
+    Complex(Box<Selector>, Combinator, SimpleSelector),
+}
+
+impl Selector {
+    /// The rightmost compound selector, i.e. the one the subject element must match.
+    pub fn rightmost(&self) -> &SimpleSelector {
+        match *self {
+            Selector::Simple(ref simple) => simple,
+            Selector::Complex(_, _, ref simple) => simple,
+        }
+    }
+
+    fn compounds(&self) -> Vec<&SimpleSelector> {
+        match *self {
+            Selector::Simple(ref simple) => vec![simple],
+            Selector::Complex(ref left, _, ref simple) => {
+                let mut compounds = left.compounds();
+                compounds.push(simple);
+                compounds
+            }
+        }
+    }
+
+    fn compounds_mut(&mut self) -> Vec<&mut SimpleSelector> {
+        match *self {
+            Selector::Simple(ref mut simple) => vec![simple],
+            Selector::Complex(ref mut left, _, ref mut simple) => {
+                let mut compounds = left.compounds_mut();
+                compounds.push(simple);
+                compounds
+            }
+        }
+    }
+
+    /// Where the selector starts in the source, i.e. the location of its leftmost
+    /// compound selector.
+    pub fn location(&self) -> &SourceLocation {
+        match *self {
+            Selector::Simple(ref simple) => &simple.location,
+            Selector::Complex(ref left, ..) => left.location(),
+        }
+    }
+
+    /// Whether `self` and `other` are the same selector, wherever they were written.
+    pub fn same_content(&self, other: &Selector) -> bool {
+        let without_locations = |selector: &Selector| {
+            let mut selector = selector.clone();
+            for location in selector.locations_mut() {
+                *location = SourceLocation::default();
+            }
+            selector
+        };
+        without_locations(self) == without_locations(other)
+    }
+
+    /// The locations of every compound selector, including those in the
+    /// arguments of `:is()`, `:not()`, `:where()` and `:has()`.
+    pub fn locations_mut(&mut self) -> Vec<&mut SourceLocation> {
+        let mut locations = Vec::new();
+        for compound in self.compounds_mut() {
+            locations.push(&mut compound.location);
+            for pseudo_class in compound.pseudo_classes.iter_mut() {
+                match *pseudo_class {
+                    PseudoClass::Not(ref mut selectors)
+                    | PseudoClass::Is(ref mut selectors)
+                    | PseudoClass::Where(ref mut selectors) => {
+                        for selector in selectors {
+                            locations.extend(selector.locations_mut());
+                        }
+                    }
+                    PseudoClass::Has(ref mut selectors) => {
+                        for relative in selectors {
+                            locations.extend(relative.selector.locations_mut());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        locations
+    }
+
+    /// Whether `&` appears anywhere in the selector, including inside pseudo-classes.
+    pub fn contains_nesting(&self) -> bool {
+        self.compounds().into_iter().any(|compound| {
+            compound.nesting
+                || compound
+                    .pseudo_classes
+                    .iter()
+                    .any(|pseudo_class| match *pseudo_class {
+                        PseudoClass::Not(ref selectors)
+                        | PseudoClass::Is(ref selectors)
+                        | PseudoClass::Where(ref selectors) => {
+                            selectors.iter().any(Selector::contains_nesting)
+                        }
+                        PseudoClass::Has(ref selectors) => selectors
+                            .iter()
+                            .any(|relative| relative.selector.contains_nesting()),
+                        _ => false,
+                    })
+        })
+    }
+
+    /// Replaces every `&` with `:is(<parents>)`, which matches what the parent rule
+    /// matches and has the specificity of its most specific selector.
+    pub fn resolve_nesting(&mut self, parents: &[Selector]) {
+        for compound in self.compounds_mut() {
+            for pseudo_class in compound.pseudo_classes.iter_mut() {
+                match *pseudo_class {
+                    PseudoClass::Not(ref mut selectors)
+                    | PseudoClass::Is(ref mut selectors)
+                    | PseudoClass::Where(ref mut selectors) => {
+                        for selector in selectors {
+                            selector.resolve_nesting(parents);
+                        }
+                    }
+                    PseudoClass::Has(ref mut selectors) => {
+                        for relative in selectors {
+                            relative.selector.resolve_nesting(parents);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            if compound.nesting {
+                compound.nesting = false;
+                compound
+                    .pseudo_classes
+                    .push(PseudoClass::Is(parents.to_vec()));
+            }
+        }
+    }
+
+    /// Puts `compound` and `combinator` in front of the leftmost compound selector,
+    /// e.g. `&` and `>` turn `a b` into `& > a b`.
+    pub fn prepend(self, compound: SimpleSelector, combinator: Combinator) -> Selector {
+        match self {
+            Selector::Simple(simple) => {
+                Selector::Complex(Box::new(Selector::Simple(compound)), combinator, simple)
+            }
+            Selector::Complex(left, right_combinator, right) => Selector::Complex(
+                Box::new(left.prepend(compound, combinator)),
+                right_combinator,
+                right,
+            ),
+        }
+    }
+}
+
+/// A selector inside `:has()`, anchored at the element being matched.
+/// `:has(> img)` is stored as `Child` + `img`; a missing combinator means `Descendant`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelativeSelector {
+    pub combinator: Combinator,
+    pub selector: Selector,
 }
 
 impl SelectorTrait for Selector {
     fn specificity(&self) -> Specificity {
-        let Selector::Simple(ref simple) = *self;
-        let id_count = simple.id.iter().count();
-        let class_len = simple.class.len();
-        let tag_count = simple.tag_name.iter().count();
-        (id_count, class_len, tag_count)
+        match *self {
+            Selector::Simple(ref simple) => simple.specificity(),
+            Selector::Complex(ref left, _, ref simple) => {
+                add_specificity(left.specificity(), simple.specificity())
+            }
+        }
+    }
+}
+
+impl SelectorTrait for SimpleSelector {
+    fn specificity(&self) -> Specificity {
+        let id_count = self.id.iter().count();
+        let class_len = self.class.len();
+        let tag_count = self.tag_name.iter().count() + self.pseudo_element.iter().count();
+        self.pseudo_classes
+            .iter()
+            .fold((id_count, class_len, tag_count), |acc, pseudo_class| {
+                add_specificity(acc, pseudo_class.specificity())
+            })
+    }
+}
+
+impl SelectorTrait for RelativeSelector {
+    fn specificity(&self) -> Specificity {
+        self.selector.specificity()
     }
 }
+
+pub fn add_specificity(a: Specificity, b: Specificity) -> Specificity {
+    (a.0 + b.0, a.1 + b.1, a.2 + b.2)
+}
+
+/// The specificity of the most specific selector in a selector list.
+pub fn max_specificity<T: SelectorTrait>(selectors: &[T]) -> Specificity {
+    selectors
+        .iter()
+        .map(|selector| selector.specificity())
+        .max()
+        .unwrap_or((0, 0, 0))
+}
@@ -0,0 +1,86 @@
+// セレクターを右端の複合セレクターのid、クラス、タグ名で振り分ける索引
+// 要素ごとにすべてのルールを試さず、マッチしうるセレクターだけを試すために使う
+
+use std::collections::HashMap;
+use style_sheet::selector::Selector;
+
+/// Values keyed by the rightmost id, class or tag name of a selector, in that
+/// order of preference. Selectors with none of them go to the universal bucket.
+#[derive(Debug, Clone)]
+pub struct SelectorMap<T> {
+    ids: HashMap<String, Vec<T>>,
+    classes: HashMap<String, Vec<T>>,
+    tags: HashMap<String, Vec<T>>,
+    universal: Vec<T>,
+}
+
+impl<T> Default for SelectorMap<T> {
+    fn default() -> SelectorMap<T> {
+        SelectorMap {
+            ids: HashMap::new(),
+            classes: HashMap::new(),
+            tags: HashMap::new(),
+            universal: Vec::new(),
+        }
+    }
+}
+
+impl<T: Copy> SelectorMap<T> {
+    pub fn insert(&mut self, selector: &Selector, value: T) {
+        let rightmost = selector.rightmost();
+        // 要素の持つキーが少ないものほど候補を絞れる
+        let bucket = if let Some(ref id) = rightmost.id {
+            self.ids.entry(id.clone()).or_default()
+        } else if let Some(class) = rightmost.class.first() {
+            self.classes.entry(class.clone()).or_default()
+        } else if let Some(ref tag_name) = rightmost.tag_name {
+            self.tags.entry(tag_name.clone()).or_default()
+        } else {
+            &mut self.universal
+        };
+        bucket.push(value);
+    }
+
+    /// The values of every selector that may match an element with the given tag
+    /// name, id and classes, in no particular order.
+    pub fn candidates<'c, I>(&self, tag_name: &str, id: Option<&str>, classes: I) -> Vec<T>
+    where
+        I: IntoIterator<Item = &'c str>,
+    {
+        let mut candidates = self.universal.clone();
+        let mut extend = |bucket: Option<&Vec<T>>| candidates.extend(bucket.into_iter().flatten());
+        extend(self.tags.get(tag_name));
+        if let Some(id) = id {
+            extend(self.ids.get(id));
+        }
+        for class in classes {
+            extend(self.classes.get(class));
+        }
+        candidates
+    }
+}
+
+#[test]
+fn test_selector_map() {
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+
+    let css = "div #main, p.a {} .b .a.c {} p {} * {} :hover {} em .b {} #other {}";
+    let stylesheet = new_css_parser(css.to_string()).parse();
+    let mut map = SelectorMap::default();
+    for (rule_index, rule) in stylesheet.rules.iter().enumerate() {
+        for selector in &rule.as_style().unwrap().selectors {
+            map.insert(selector, rule_index);
+        }
+    }
+    let rules = |tag_name, id, classes: &[&'static str]| {
+        let mut rules = map.candidates(tag_name, id, classes.iter().cloned());
+        rules.sort_unstable();
+        rules
+    };
+
+    // 右端の複合セレクターのキーが一致するルールと、キーのないルールだけが候補になる
+    assert_eq!(rules("p", None, &["a"]), vec![0, 1, 2, 3, 4]);
+    assert_eq!(rules("span", Some("main"), &["b"]), vec![0, 3, 4, 5]);
+    assert_eq!(rules("em", None, &[]), vec![3, 4]);
+}
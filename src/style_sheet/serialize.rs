@@ -0,0 +1,861 @@
+// スタイルシートをCSSのテキストに戻す
+// 色や数値、セレクターはCSSOMの規則に従った正規の形で出力する
+// https://www.w3.org/TR/cssom-1/#serializing-css-values
+
+use style_sheet::color;
+use style_sheet::declaration::Declaration;
+use style_sheet::layer::LayerName;
+use style_sheet::math::MathExpression;
+use style_sheet::media_query::{
+    Comparison, MediaCondition, MediaFeature, MediaQuery, MediaQueryList, MediaType, MediaValue,
+};
+use style_sheet::pseudo_class::PseudoClass;
+use style_sheet::rule::{CSSRule, ImportRule, Rule};
+use style_sheet::selector::{Combinator, RelativeSelector, Selector};
+use style_sheet::simple_selector::SimpleSelector;
+use style_sheet::style_sheet::Stylesheet;
+use style_sheet::supports::SupportsCondition;
+use style_sheet::util::{Color, Value};
+
+const INDENT: &str = "  ";
+
+/// How `serialize` lays out its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// No optional whitespace, and colors and numbers in their shortest form.
+    Minified,
+    /// One declaration per line, blocks indented by two spaces and a blank line
+    /// between top-level rules.
+    Pretty,
+}
+
+/// Turns a stylesheet back into CSS. Imported sheets are not inlined; `@import`
+/// rules are written as they were.
+pub fn serialize(stylesheet: &Stylesheet, format: Format) -> String {
+    let mut writer = Writer {
+        format,
+        out: String::new(),
+    };
+    writer.rules(&stylesheet.rules, 0);
+    writer.out
+}
+
+/// A selector in its canonical form, e.g. `ul > li.item:hover`.
+pub fn serialize_selector(selector: &Selector) -> String {
+    let mut writer = Writer::pretty();
+    writer.selector(selector);
+    writer.out
+}
+
+/// A component value in its canonical form, e.g. `rgb(255, 0, 0)` or `calc(100% - 2em)`.
+pub fn serialize_value(value: &Value) -> String {
+    let mut writer = Writer::pretty();
+    writer.value(value);
+    writer.out
+}
+
+pub fn serialize_number(number: f32) -> String {
+    // -0 は 0 にする
+    if number == 0.0 {
+        "0".to_string()
+    } else {
+        number.to_string()
+    }
+}
+
+// 空白や引用符、括弧、バックスラッシュを含まないurl
+fn bare_url(arguments: &[Value]) -> Option<&str> {
+    match *arguments {
+        [Value::Str(ref url)]
+            if !url.is_empty()
+                && !url.contains(|c: char| c.is_whitespace() || "\"'()\\".contains(c)) =>
+        {
+            Some(url)
+        }
+        _ => None,
+    }
+}
+
+// calc()の中で無限大とNaNを表す定数
+fn math_constant(number: f32) -> &'static str {
+    if number.is_nan() {
+        "NaN"
+    } else if number > 0.0 {
+        "infinity"
+    } else {
+        "-infinity"
+    }
+}
+
+struct Writer {
+    format: Format,
+    out: String,
+}
+
+impl Writer {
+    fn pretty() -> Writer {
+        Writer {
+            format: Format::Pretty,
+            out: String::new(),
+        }
+    }
+
+    fn pretty_or(&mut self, pretty: &str, minified: &str) {
+        self.out.push_str(match self.format {
+            Format::Pretty => pretty,
+            Format::Minified => minified,
+        });
+    }
+
+    fn indent(&mut self, depth: usize) {
+        if self.format == Format::Pretty {
+            for _ in 0..depth {
+                self.out.push_str(INDENT);
+            }
+        }
+    }
+
+    fn newline(&mut self) {
+        self.pretty_or("\n", "");
+    }
+
+    // 縮小時は先頭の0を省く (0.5 -> .5)
+    fn number(&mut self, number: f32) {
+        let number = serialize_number(number);
+        match self.format {
+            Format::Minified if number.starts_with("0.") => self.out.push_str(&number[1..]),
+            Format::Minified if number.starts_with("-0.") => {
+                self.out.push('-');
+                self.out.push_str(&number[2..]);
+            }
+            _ => self.out.push_str(&number),
+        }
+    }
+
+    // Rules:
+
+    fn rules(&mut self, rules: &[CSSRule], depth: usize) {
+        for (index, rule) in rules.iter().enumerate() {
+            if index > 0 && depth == 0 {
+                self.newline();
+            }
+            self.indent(depth);
+            self.rule(rule, depth);
+            self.newline();
+        }
+    }
+
+    fn rule(&mut self, rule: &CSSRule, depth: usize) {
+        match *rule {
+            CSSRule::Style(ref rule) => self.style_rule(rule, depth),
+            CSSRule::Media(ref media_rule) => {
+                self.out.push_str("@media ");
+                self.media_query_list(&media_rule.queries);
+                self.block(&media_rule.rules, depth);
+            }
+            CSSRule::Supports(ref supports_rule) => {
+                self.out.push_str("@supports ");
+                self.supports_condition(&supports_rule.condition);
+                self.block(&supports_rule.rules, depth);
+            }
+            CSSRule::Layer(ref layer_rule) => {
+                self.out.push_str("@layer");
+                if !layer_rule.name.is_anonymous() {
+                    self.out.push(' ');
+                    self.layer_name(&layer_rule.name);
+                }
+                self.block(&layer_rule.rules, depth);
+            }
+            CSSRule::LayerStatement(ref names) => {
+                self.out.push_str("@layer ");
+                for (index, name) in names.iter().enumerate() {
+                    if index > 0 {
+                        self.pretty_or(", ", ",");
+                    }
+                    self.layer_name(name);
+                }
+                self.out.push(';');
+            }
+            CSSRule::Import(ref import_rule) => self.import_rule(import_rule),
+        }
+    }
+
+    // `{ <rules> }` の前の空白と中身
+    fn block(&mut self, rules: &[CSSRule], depth: usize) {
+        self.pretty_or(" {", "{");
+        if !rules.is_empty() {
+            self.newline();
+            self.rules(rules, depth + 1);
+            self.indent(depth);
+        }
+        self.out.push('}');
+    }
+
+    fn style_rule(&mut self, rule: &Rule, depth: usize) {
+        self.selector_list(&rule.selectors);
+        self.pretty_or(" {", "{");
+        if rule.declarations.is_empty() {
+            self.out.push('}');
+            return;
+        }
+        self.newline();
+        for (index, declaration) in rule.declarations.iter().enumerate() {
+            self.indent(depth + 1);
+            self.declaration(declaration);
+            // 縮小時は最後の ; を省く
+            if self.format == Format::Pretty || index + 1 < rule.declarations.len() {
+                self.out.push(';');
+            }
+            self.newline();
+        }
+        self.indent(depth);
+        self.out.push('}');
+    }
+
+    fn declaration(&mut self, declaration: &Declaration) {
+        self.out.push_str(&declaration.name);
+        self.pretty_or(": ", ":");
+        self.value(&declaration.value);
+        if declaration.important {
+            self.pretty_or(" !important", "!important");
+        }
+    }
+
+    fn import_rule(&mut self, import_rule: &ImportRule) {
+        self.out.push_str("@import url(");
+        self.string(&import_rule.url);
+        self.out.push(')');
+        if let Some(ref name) = import_rule.layer {
+            self.out.push_str(" layer");
+            if !name.is_anonymous() {
+                self.out.push('(');
+                self.layer_name(name);
+                self.out.push(')');
+            }
+        }
+        match import_rule.supports {
+            // 宣言は括弧を重ねずに書く
+            Some(SupportsCondition::Declaration(ref declaration)) => {
+                self.out.push_str(" supports(");
+                self.declaration(declaration);
+                self.out.push(')');
+            }
+            Some(ref condition) => {
+                self.out.push_str(" supports(");
+                self.supports_condition(condition);
+                self.out.push(')');
+            }
+            None => {}
+        }
+        if !import_rule.queries.0.is_empty() {
+            self.out.push(' ');
+            self.media_query_list(&import_rule.queries);
+        }
+        self.out.push(';');
+    }
+
+    fn layer_name(&mut self, name: &LayerName) {
+        self.out.push_str(&name.0.join("."));
+    }
+
+    // Selectors:
+
+    fn selector_list(&mut self, selectors: &[Selector]) {
+        for (index, selector) in selectors.iter().enumerate() {
+            if index > 0 {
+                self.pretty_or(", ", ",");
+            }
+            self.selector(selector);
+        }
+    }
+
+    fn selector(&mut self, selector: &Selector) {
+        match *selector {
+            Selector::Simple(ref compound) => self.compound_selector(compound),
+            Selector::Complex(ref left, combinator, ref right) => {
+                self.selector(left);
+                self.combinator(combinator);
+                self.compound_selector(right);
+            }
+        }
+    }
+
+    fn combinator(&mut self, combinator: Combinator) {
+        match combinator {
+            Combinator::Descendant => self.out.push(' '),
+            Combinator::Child => self.pretty_or(" > ", ">"),
+            Combinator::NextSibling => self.pretty_or(" + ", "+"),
+            Combinator::SubsequentSibling => self.pretty_or(" ~ ", "~"),
+        }
+    }
+
+    fn compound_selector(&mut self, selector: &SimpleSelector) {
+        let start = self.out.len();
+        if selector.nesting {
+            self.out.push('&');
+        }
+        if let Some(ref tag_name) = selector.tag_name {
+            self.out.push_str(tag_name);
+        }
+        if let Some(ref id) = selector.id {
+            self.out.push('#');
+            self.out.push_str(id);
+        }
+        for class in &selector.class {
+            self.out.push('.');
+            self.out.push_str(class);
+        }
+        for pseudo_class in &selector.pseudo_classes {
+            self.out.push(':');
+            self.out.push_str(pseudo_class.name());
+            match *pseudo_class {
+                PseudoClass::Not(ref selectors)
+                | PseudoClass::Is(ref selectors)
+                | PseudoClass::Where(ref selectors) => {
+                    self.out.push('(');
+                    self.selector_list(selectors);
+                    self.out.push(')');
+                }
+                PseudoClass::Has(ref selectors) => {
+                    self.out.push('(');
+                    self.relative_selector_list(selectors);
+                    self.out.push(')');
+                }
+                _ => {}
+            }
+        }
+        if let Some(pseudo_element) = selector.pseudo_element {
+            self.out.push_str("::");
+            self.out.push_str(pseudo_element.as_str());
+        }
+        // 何もない複合セレクターはユニバーサルセレクター
+        if self.out.len() == start {
+            self.out.push('*');
+        }
+    }
+
+    fn relative_selector_list(&mut self, selectors: &[RelativeSelector]) {
+        for (index, relative) in selectors.iter().enumerate() {
+            if index > 0 {
+                self.pretty_or(", ", ",");
+            }
+            match relative.combinator {
+                Combinator::Descendant => {}
+                Combinator::Child => self.pretty_or("> ", ">"),
+                Combinator::NextSibling => self.pretty_or("+ ", "+"),
+                Combinator::SubsequentSibling => self.pretty_or("~ ", "~"),
+            }
+            self.selector(&relative.selector);
+        }
+    }
+
+    // Values:
+
+    fn value(&mut self, value: &Value) {
+        match *value {
+            Value::Keyword(ref keyword) => self.out.push_str(keyword),
+            Value::Length(length, unit) => {
+                self.number(length);
+                self.out.push_str(unit.as_str());
+            }
+            Value::Number(number) => self.number(number),
+            Value::Angle(degrees) => {
+                self.number(degrees);
+                self.out.push_str("deg");
+            }
+            Value::ColorValue(color) => self.color(color),
+            Value::Str(ref string) => self.string(string),
+            // 縮めた形式では、引用符のいらないurlは引用符を外す
+            Value::Function(ref name, ref arguments)
+                if self.format == Format::Minified
+                    && name == "url"
+                    && bare_url(arguments).is_some() =>
+            {
+                self.out.push_str("url(");
+                self.out.push_str(bare_url(arguments).unwrap());
+                self.out.push(')');
+            }
+            Value::Function(ref name, ref arguments) => {
+                self.out.push_str(name);
+                self.out.push('(');
+                for (index, argument) in arguments.iter().enumerate() {
+                    if index > 0 {
+                        self.pretty_or(", ", ",");
+                    }
+                    self.value(argument);
+                }
+                self.out.push(')');
+            }
+            Value::Delimiter(delimiter) => self.out.push(delimiter),
+            Value::List(ref values) => self.value_list(values),
+            Value::Math(ref expression) => self.math_function(expression),
+            Value::Unparsed { ref css, .. } => self.out.push_str(css),
+        }
+    }
+
+    // 空白区切りの値。`,` の前には空白を入れず、`/` は前後を空ける
+    fn value_list(&mut self, values: &[Value]) {
+        let mut separate = false;
+        for value in values {
+            match *value {
+                Value::Delimiter(',') => {
+                    self.out.push(',');
+                    separate = self.format == Format::Pretty;
+                }
+                Value::Delimiter(delimiter) => {
+                    if self.format == Format::Pretty {
+                        self.out.push(' ');
+                    }
+                    self.out.push(delimiter);
+                    separate = self.format == Format::Pretty;
+                }
+                _ => {
+                    if separate {
+                        self.out.push(' ');
+                    }
+                    self.value(value);
+                    separate = true;
+                }
+            }
+        }
+    }
+
+    // https://www.w3.org/TR/cssom-1/#serialize-a-css-component-value
+    fn color(&mut self, color: Color) {
+        if self.format == Format::Minified {
+            return self.out.push_str(&shortest_color(color));
+        }
+        let separator = match self.format {
+            Format::Pretty => ", ",
+            Format::Minified => ",",
+        };
+        let channels = [color.r, color.g, color.b]
+            .iter()
+            .map(|channel| channel.to_string())
+            .collect::<Vec<_>>()
+            .join(separator);
+        if color.a == 255 {
+            self.out.push_str(&format!("rgb({})", channels));
+        } else {
+            self.out.push_str(&format!(
+                "rgba({}{}{})",
+                channels,
+                separator,
+                serialize_number(alpha(color.a))
+            ));
+        }
+    }
+
+    fn string(&mut self, string: &str) {
+        self.out.push('"');
+        for c in string.chars() {
+            match c {
+                '"' | '\\' => {
+                    self.out.push('\\');
+                    self.out.push(c);
+                }
+                '\n' => self.out.push_str("\\a "),
+                _ => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+
+    // Math functions:
+
+    // 最上位の式は calc() で囲む。min(), max(), clamp() はそのまま書く
+    fn math_function(&mut self, expression: &MathExpression) {
+        match *expression {
+            MathExpression::Min(_) | MathExpression::Max(_) | MathExpression::Clamp(..) => {
+                self.math(expression)
+            }
+            _ => {
+                self.out.push_str("calc(");
+                self.math(expression);
+                self.out.push(')');
+            }
+        }
+    }
+
+    fn math(&mut self, expression: &MathExpression) {
+        match *expression {
+            MathExpression::Number(number) if !number.is_finite() => {
+                self.out.push_str(math_constant(number))
+            }
+            MathExpression::Number(number) => self.number(number),
+            // 無限大の長さは定数と単位の積として書く
+            MathExpression::Length(length, unit) if !length.is_finite() => {
+                self.out.push_str(math_constant(length));
+                self.out.push_str(" * 1");
+                self.out.push_str(unit.as_str());
+            }
+            MathExpression::Length(length, unit) => {
+                self.number(length);
+                self.out.push_str(unit.as_str());
+            }
+            MathExpression::Sum(ref terms) => {
+                for (index, term) in terms.iter().enumerate() {
+                    // 負の項は引き算として書く
+                    match (index, negated(term)) {
+                        (0, _) => self.math(term),
+                        (_, Some(positive)) => {
+                            self.out.push_str(" - ");
+                            self.math(&positive);
+                        }
+                        (_, None) => {
+                            self.out.push_str(" + ");
+                            self.math(term);
+                        }
+                    }
+                }
+            }
+            MathExpression::Product(ref factors) => {
+                for (index, factor) in factors.iter().enumerate() {
+                    match *factor {
+                        MathExpression::Invert(ref divisor) => {
+                            if index == 0 {
+                                self.out.push('1');
+                            }
+                            self.out.push_str(" / ");
+                            self.math_operand(divisor);
+                        }
+                        _ => {
+                            if index > 0 {
+                                self.out.push_str(" * ");
+                            }
+                            self.math_operand(factor);
+                        }
+                    }
+                }
+            }
+            MathExpression::Invert(ref divisor) => {
+                self.out.push_str("1 / ");
+                self.math_operand(divisor);
+            }
+            MathExpression::Min(ref arguments) => self.math_arguments("min", arguments),
+            MathExpression::Max(ref arguments) => self.math_arguments("max", arguments),
+            MathExpression::Clamp(ref min, ref value, ref max) => {
+                let arguments = [(**min).clone(), (**value).clone(), (**max).clone()];
+                self.math_arguments("clamp", &arguments)
+            }
+        }
+    }
+
+    // 積の中の和は括弧で囲む
+    fn math_operand(&mut self, expression: &MathExpression) {
+        match *expression {
+            MathExpression::Sum(_) | MathExpression::Product(_) => {
+                self.out.push('(');
+                self.math(expression);
+                self.out.push(')');
+            }
+            _ => self.math(expression),
+        }
+    }
+
+    fn math_arguments(&mut self, name: &str, arguments: &[MathExpression]) {
+        self.out.push_str(name);
+        self.out.push('(');
+        for (index, argument) in arguments.iter().enumerate() {
+            if index > 0 {
+                self.pretty_or(", ", ",");
+            }
+            self.math(argument);
+        }
+        self.out.push(')');
+    }
+
+    // Media queries:
+
+    fn media_query_list(&mut self, queries: &MediaQueryList) {
+        for (index, query) in queries.0.iter().enumerate() {
+            if index > 0 {
+                self.pretty_or(", ", ",");
+            }
+            self.media_query(query);
+        }
+    }
+
+    fn media_query(&mut self, query: &MediaQuery) {
+        let media_type = match query.media_type {
+            MediaType::All => "all",
+            MediaType::Screen => "screen",
+            MediaType::Print => "print",
+            // 未知のメディアタイプは何にもマッチしないので、同じ意味の `not all` にする
+            MediaType::Unknown if query.negated => "all",
+            MediaType::Unknown => "not all",
+        };
+        if query.media_type == MediaType::All && !query.negated {
+            if let Some(ref condition) = query.condition {
+                return self.media_condition(condition);
+            }
+        }
+        if query.negated && query.media_type != MediaType::Unknown {
+            self.out.push_str("not ");
+        }
+        self.out.push_str(media_type);
+        if let Some(ref condition) = query.condition {
+            self.out.push_str(" and ");
+            self.media_in_parens(condition);
+        }
+    }
+
+    fn media_condition(&mut self, condition: &MediaCondition) {
+        match *condition {
+            MediaCondition::Feature(ref feature) => self.media_feature(feature),
+            MediaCondition::Not(ref condition) => {
+                self.out.push_str("not ");
+                self.media_in_parens(condition);
+            }
+            MediaCondition::And(ref conditions) | MediaCondition::Or(ref conditions) => {
+                let keyword = match *condition {
+                    MediaCondition::And(_) => " and ",
+                    _ => " or ",
+                };
+                for (index, condition) in conditions.iter().enumerate() {
+                    if index > 0 {
+                        self.out.push_str(keyword);
+                    }
+                    self.media_in_parens(condition);
+                }
+            }
+        }
+    }
+
+    fn media_in_parens(&mut self, condition: &MediaCondition) {
+        match *condition {
+            MediaCondition::Feature(ref feature) => self.media_feature(feature),
+            _ => {
+                self.out.push('(');
+                self.media_condition(condition);
+                self.out.push(')');
+            }
+        }
+    }
+
+    // 比較は範囲構文で書く
+    fn media_feature(&mut self, feature: &MediaFeature) {
+        self.out.push('(');
+        match feature.comparisons.len() {
+            0 => self.out.push_str(&feature.name),
+            1 => {
+                let (comparison, ref value) = feature.comparisons[0];
+                self.out.push_str(&feature.name);
+                match comparison {
+                    Comparison::Equal => self.pretty_or(": ", ":"),
+                    _ => {
+                        self.pretty_or(" ", "");
+                        self.out.push_str(comparison.as_str());
+                        self.pretty_or(" ", "");
+                    }
+                }
+                self.media_value(value);
+            }
+            _ => {
+                let (first, ref low) = feature.comparisons[0];
+                self.media_value(low);
+                self.pretty_or(" ", "");
+                self.out.push_str(first.flip().as_str());
+                self.pretty_or(" ", "");
+                self.out.push_str(&feature.name);
+                for &(comparison, ref value) in &feature.comparisons[1..] {
+                    self.pretty_or(" ", "");
+                    self.out.push_str(comparison.as_str());
+                    self.pretty_or(" ", "");
+                    self.media_value(value);
+                }
+            }
+        }
+        self.out.push(')');
+    }
+
+    fn media_value(&mut self, value: &MediaValue) {
+        match *value {
+            MediaValue::Number(number) => self.number(number),
+            MediaValue::Length(length) => {
+                self.number(length);
+                self.out.push_str("px");
+            }
+            MediaValue::Resolution(resolution) => {
+                self.number(resolution);
+                self.out.push_str("dppx");
+            }
+            MediaValue::Keyword(ref keyword) => self.out.push_str(keyword),
+        }
+    }
+
+    // Feature queries:
+
+    fn supports_condition(&mut self, condition: &SupportsCondition) {
+        match *condition {
+            SupportsCondition::Not(ref condition) => {
+                self.out.push_str("not ");
+                self.supports_in_parens(condition);
+            }
+            SupportsCondition::And(ref conditions) | SupportsCondition::Or(ref conditions) => {
+                let keyword = match *condition {
+                    SupportsCondition::And(_) => " and ",
+                    _ => " or ",
+                };
+                for (index, condition) in conditions.iter().enumerate() {
+                    if index > 0 {
+                        self.out.push_str(keyword);
+                    }
+                    self.supports_in_parens(condition);
+                }
+            }
+            _ => self.supports_in_parens(condition),
+        }
+    }
+
+    fn supports_in_parens(&mut self, condition: &SupportsCondition) {
+        match *condition {
+            SupportsCondition::Declaration(ref declaration) => {
+                self.out.push('(');
+                self.declaration(declaration);
+                self.out.push(')');
+            }
+            SupportsCondition::Selector(ref selector) => {
+                self.out.push_str("selector(");
+                self.out.push_str(selector);
+                self.out.push(')');
+            }
+            SupportsCondition::Unknown(ref css) => {
+                self.out.push('(');
+                self.out.push_str(css);
+                self.out.push(')');
+            }
+            _ => {
+                self.out.push('(');
+                self.supports_condition(condition);
+                self.out.push(')');
+            }
+        }
+    }
+}
+
+// 負の数値や長さ、負の係数を持つ積を符号を反転して返す
+fn negated(term: &MathExpression) -> Option<MathExpression> {
+    match *term {
+        MathExpression::Number(number) if number < 0.0 => Some(MathExpression::Number(-number)),
+        MathExpression::Length(length, unit) if length < 0.0 => {
+            Some(MathExpression::Length(-length, unit))
+        }
+        MathExpression::Product(ref factors) => match factors.first() {
+            Some(&MathExpression::Number(number)) if number < 0.0 => {
+                let mut factors = factors.clone();
+                if number == -1.0 {
+                    factors.remove(0);
+                } else {
+                    factors[0] = MathExpression::Number(-number);
+                }
+                Some(match factors.len() {
+                    1 => factors.swap_remove(0),
+                    _ => MathExpression::Product(factors),
+                })
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// 縮小時の色: 16進表記と色名のうち短いもの
+fn shortest_color(color: Color) -> String {
+    let mut digits = vec![color.r, color.g, color.b];
+    if color.a != 255 {
+        digits.push(color.a);
+    }
+    let hex = if digits.iter().all(|digit| digit >> 4 == digit & 0xf) {
+        digits
+            .iter()
+            .map(|digit| format!("{:x}", digit & 0xf))
+            .collect::<String>()
+    } else {
+        digits
+            .iter()
+            .map(|digit| format!("{:02x}", digit))
+            .collect::<String>()
+    };
+    match color::name(color) {
+        Some(name) if name.len() <= hex.len() => name.to_string(),
+        _ => format!("#{}", hex),
+    }
+}
+
+/*
+ アルファ値は小数点以下2桁で元の値に戻るならそれを使い、戻らなければ3桁にする
+ https://www.w3.org/TR/css-color-4/#serializing-alpha-values
+*/
+fn alpha(a: u8) -> f32 {
+    let rounded = (a as f32 / 255.0 * 100.0).round() / 100.0;
+    if (rounded * 255.0).round() as u8 == a {
+        rounded
+    } else {
+        (a as f32 / 255.0 * 1000.0).round() / 1000.0
+    }
+}
+
+#[test]
+fn test_serialize() {
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+    use style_sheet::source_location::clear_locations;
+
+    // 位置は書き方で変わるので、位置を消して比べる
+    let parse = |css: String| {
+        let mut stylesheet = new_css_parser(css).parse();
+        clear_locations(&mut stylesheet.rules);
+        stylesheet
+    };
+    let css = "@import 'base.css' layer(reset) screen; \
+               @layer reset, utilities; \
+               ul>li.item:not(.a,#b) , a:has(> img)::before { color: #f008; \
+                   margin-top: calc(100% - 2em) !important; font: italic 12px/1.5 \"Helvetica Neue\", serif } \
+               @media not print and (width >= 600px), (400px <= width < 900px) { \
+                   * { width: min(50%, 10em + 4px) } } \
+               @supports (display: block) and (not selector(a:hover)) { \
+                   @layer { p {} } }";
+    let stylesheet = parse(css.to_string());
+
+    let pretty = serialize(&stylesheet, Format::Pretty);
+    assert_eq!(
+        pretty,
+        "@import url(\"base.css\") layer(reset) screen;\n\
+         \n\
+         @layer reset, utilities;\n\
+         \n\
+         ul > li.item:not(.a, #b), a:has(> img)::before {\n\
+         \x20 color: rgba(255, 0, 0, 0.533);\n\
+         \x20 margin-top: calc(100% - 2em) !important;\n\
+         \x20 font-style: italic;\n\
+         \x20 font-variant: normal;\n\
+         \x20 font-weight: normal;\n\
+         \x20 font-stretch: normal;\n\
+         \x20 font-size: 12px;\n\
+         \x20 line-height: 1.5;\n\
+         \x20 font-family: \"Helvetica Neue\", serif;\n\
+         }\n\
+         \n\
+         @media not print and (width >= 600px), (400px <= width < 900px) {\n\
+         \x20 * {\n\
+         \x20   width: min(50%, 10em + 4px);\n\
+         \x20 }\n\
+         }\n\
+         \n\
+         @supports (display: block) and (not selector(a:hover)) {\n\
+         \x20 @layer {\n\
+         \x20   p {}\n\
+         \x20 }\n\
+         }\n"
+    );
+    // 出力をもう一度パースすると同じスタイルシートになる
+    assert_eq!(parse(pretty), stylesheet);
+
+    let minified = serialize(&stylesheet, Format::Minified);
+    assert_eq!(parse(minified.clone()), stylesheet);
+    assert!(minified.starts_with(
+        "@import url(\"base.css\") layer(reset) screen;@layer reset,utilities;\
+         ul>li.item:not(.a,#b),a:has(>img)::before{color:#f008;"
+    ));
+}
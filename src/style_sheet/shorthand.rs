@@ -0,0 +1,834 @@
+// ショートハンドプロパティをパース時にロングハンドへ展開する
+// 省略されたサブプロパティは初期値にリセットされる
+// https://www.w3.org/TR/css-cascade-4/#shorthand
+
+use style_sheet::declaration::Declaration;
+use style_sheet::property::is_css_wide_keyword;
+use style_sheet::util::{Unit, Value};
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+const BORDER_STYLES: [&str; 10] = [
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+/// Expands a shorthand declaration such as `margin: 10px 20px` into its longhands.
+/// Longhand declarations are returned unchanged.
+pub fn expand_shorthand(declaration: Declaration) -> Vec<Declaration> {
+    let longhands = match longhand_names(&declaration.name) {
+        Some(longhands) => longhands,
+        None => return vec![declaration],
+    };
+
+    // `margin: inherit` などはすべてのロングハンドに同じキーワードを設定する
+    if is_css_wide_keyword(&declaration.value) {
+        return longhands
+            .iter()
+            .map(|name| Declaration {
+                name: name.to_string(),
+                value: declaration.value.clone(),
+                important: declaration.important,
+                location: declaration.location.clone(),
+            })
+            .collect();
+    }
+
+    // var()を含む値は置換した後に展開するため、すべてのロングハンドに同じ値を保留する
+    if let Value::Unparsed { ref css, .. } = declaration.value {
+        return longhands
+            .iter()
+            .map(|name| Declaration {
+                name: name.to_string(),
+                value: Value::Unparsed {
+                    css: css.clone(),
+                    shorthand: Some(declaration.name.clone()),
+                },
+                important: declaration.important,
+                location: declaration.location.clone(),
+            })
+            .collect();
+    }
+
+    let values = components(&declaration.value);
+    let expanded = match &*declaration.name {
+        "margin" | "padding" | "inset" | "border-width" | "border-style" | "border-color" => {
+            box_sides(&values).map(|sides| longhands.into_iter().zip(sides).collect())
+        }
+        // longhand_names は各辺ごとに width, style, color の順に並んでいる
+        "border" | "border-top" | "border-right" | "border-bottom" | "border-left" => {
+            border_side(&values).map(|(width, style, color)| {
+                let parts = [width, style, color];
+                longhands
+                    .into_iter()
+                    .zip(parts.iter().cloned().cycle())
+                    .collect()
+            })
+        }
+        "background" => background(&values),
+        "font" => font(&values),
+        "flex" => flex(&values),
+        "list-style" => list_style(&values),
+        _ => None,
+    };
+
+    match expanded {
+        Some(expanded) => expanded
+            .into_iter()
+            .map(|(name, value)| Declaration {
+                name,
+                value,
+                important: declaration.important,
+                location: declaration.location.clone(),
+            })
+            .collect(),
+        // 解釈できないショートハンドはそのまま残す
+        None => vec![declaration],
+    }
+}
+
+fn longhand_names(name: &str) -> Option<Vec<String>> {
+    let sides = |format: &dyn Fn(&str) -> String| Some(SIDES.iter().map(|s| format(s)).collect());
+    match name {
+        "margin" | "padding" => sides(&|side| format!("{}-{}", name, side)),
+        "inset" => sides(&|side| side.to_string()),
+        "border-width" | "border-style" | "border-color" => {
+            let suffix = &name["border-".len()..];
+            sides(&|side| format!("border-{}-{}", side, suffix))
+        }
+        "border" => Some(
+            SIDES
+                .iter()
+                .flat_map(|side| {
+                    ["width", "style", "color"]
+                        .iter()
+                        .map(move |part| format!("border-{}-{}", side, part))
+                })
+                .collect(),
+        ),
+        "border-top" | "border-right" | "border-bottom" | "border-left" => Some(
+            ["width", "style", "color"]
+                .iter()
+                .map(|part| format!("{}-{}", name, part))
+                .collect(),
+        ),
+        "background" => Some(
+            [
+                "background-color",
+                "background-image",
+                "background-repeat",
+                "background-position",
+                "background-size",
+                "background-attachment",
+                "background-origin",
+                "background-clip",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+        ),
+        "font" => Some(
+            [
+                "font-style",
+                "font-variant",
+                "font-weight",
+                "font-stretch",
+                "font-size",
+                "line-height",
+                "font-family",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+        ),
+        "flex" => Some(
+            ["flex-grow", "flex-shrink", "flex-basis"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        ),
+        "list-style" => Some(
+            ["list-style-type", "list-style-position", "list-style-image"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+// 組み立て直すショートハンド。ロングハンドの多いものから試す
+const SHORTHANDS: [&str; 15] = [
+    "border",
+    "border-width",
+    "border-style",
+    "border-color",
+    "border-top",
+    "border-right",
+    "border-bottom",
+    "border-left",
+    "margin",
+    "padding",
+    "inset",
+    "background",
+    "font",
+    "flex",
+    "list-style",
+];
+
+/// The inverse of [`expand_shorthand`]: replaces a complete set of longhands
+/// with their shorthand, at the position of the first longhand. Longhands
+/// declared more than once or with differing `!important` are left alone, and
+/// so is any shorthand that would not expand back to the same values.
+pub fn combine_shorthands(declarations: Vec<Declaration>) -> Vec<Declaration> {
+    let mut declarations: Vec<Option<Declaration>> = declarations.into_iter().map(Some).collect();
+    for shorthand in SHORTHANDS.iter() {
+        let longhands = longhand_names(shorthand).unwrap();
+        let indices: Option<Vec<usize>> = longhands
+            .iter()
+            .map(|name| {
+                let mut found = declarations
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, declaration)| {
+                        declaration.as_ref().is_some_and(|d| d.name == *name)
+                    })
+                    .map(|(index, _)| index);
+                match (found.next(), found.next()) {
+                    (Some(index), None) => Some(index),
+                    _ => None,
+                }
+            })
+            .collect();
+        let indices = match indices {
+            Some(indices) => indices,
+            None => continue,
+        };
+        let combined = {
+            let parts: Vec<&Declaration> = indices
+                .iter()
+                .map(|&index| declarations[index].as_ref().unwrap())
+                .collect();
+            if parts
+                .iter()
+                .any(|part| part.important != parts[0].important)
+            {
+                continue;
+            }
+            let values: Vec<&Value> = parts.iter().map(|part| &part.value).collect();
+            let value = match shorthand_value(shorthand, &values) {
+                Some(value) => value,
+                None => continue,
+            };
+            let combined = Declaration {
+                name: shorthand.to_string(),
+                value,
+                important: parts[0].important,
+                location: parts[0].location.clone(),
+            };
+            // 展開し直して同じ値にならなければ組み立てない
+            let expanded = expand_shorthand(combined.clone());
+            let round_trips = expanded.len() == parts.len()
+                && expanded.iter().zip(parts.iter()).all(|(longhand, part)| {
+                    longhand.name == part.name && longhand.value == part.value
+                });
+            if !round_trips {
+                continue;
+            }
+            combined
+        };
+        let first = *indices.iter().min().unwrap();
+        for &index in &indices {
+            declarations[index] = None;
+        }
+        declarations[first] = Some(combined);
+    }
+    declarations.into_iter().flatten().collect()
+}
+
+// ロングハンドの値（longhand_names の順）からショートハンドの値を組み立てる
+// 初期値のサブプロパティは省く
+fn shorthand_value(shorthand: &str, values: &[&Value]) -> Option<Value> {
+    // すべて同じCSS全体キーワードか、同じショートハンドから展開されたvar()を含む値
+    if values.iter().all(|value| value == &values[0]) {
+        match *values[0] {
+            ref keyword if is_css_wide_keyword(keyword) => return Some(keyword.clone()),
+            Value::Unparsed {
+                ref css,
+                shorthand: Some(ref name),
+            } if name == shorthand => {
+                return Some(Value::Unparsed {
+                    css: css.clone(),
+                    shorthand: None,
+                })
+            }
+            _ => {}
+        }
+    }
+    match shorthand {
+        "margin" | "padding" | "inset" | "border-width" | "border-style" | "border-color" => {
+            Some(box_sides_value(values))
+        }
+        "border" => {
+            let sides: Vec<&[&Value]> = values.chunks(3).collect();
+            if sides.iter().all(|side| *side == sides[0]) {
+                Some(border_side_value(sides[0]))
+            } else {
+                None
+            }
+        }
+        "border-top" | "border-right" | "border-bottom" | "border-left" => {
+            Some(border_side_value(values))
+        }
+        "background" => background_value(values),
+        "font" => Some(font_value(values)),
+        "flex" => Some(flex_value(values)),
+        "list-style" => Some(list_style_value(values)),
+        _ => None,
+    }
+}
+
+// top, right, bottom, left を最も短い1〜4個の値にする
+fn box_sides_value(values: &[&Value]) -> Value {
+    let mut sides: Vec<Value> = values.iter().map(|&value| value.clone()).collect();
+    if sides[3] == sides[1] {
+        sides.pop();
+        if sides[2] == sides[0] {
+            sides.pop();
+            if sides[1] == sides[0] {
+                sides.pop();
+            }
+        }
+    }
+    list_or_single(sides)
+}
+
+// width, style, color
+fn border_side_value(values: &[&Value]) -> Value {
+    let initial = [
+        keyword_value("medium"),
+        keyword_value("none"),
+        keyword_value("currentcolor"),
+    ];
+    let parts: Vec<Value> = values
+        .iter()
+        .zip(initial.iter())
+        .filter(|&(&value, initial)| value != initial)
+        .map(|(&value, _)| value.clone())
+        .collect();
+    if parts.is_empty() {
+        keyword_value("none")
+    } else {
+        list_or_single(parts)
+    }
+}
+
+// color, image, repeat, position, size, attachment, origin, clip
+// color以外は各レイヤーの値を , で区切って持っている
+fn background_value(values: &[&Value]) -> Option<Value> {
+    let layered: Vec<Vec<Value>> = values[1..].iter().map(|value| layers(value)).collect();
+    let count = layered[0].len();
+    if layered.iter().any(|layers| layers.len() != count) {
+        return None;
+    }
+    let per_layer: Vec<Vec<&Value>> = (0..count)
+        .map(|i| layered.iter().map(|layers| &layers[i]).collect())
+        .collect();
+    let mut parts = Vec::new();
+    for (i, layer) in per_layer.iter().enumerate() {
+        if i > 0 {
+            parts.push(Value::Delimiter(','));
+        }
+        let (image, repeat, position, size, attachment, origin, clip) = (
+            layer[0], layer[1], layer[2], layer[3], layer[4], layer[5], layer[6],
+        );
+        let start = parts.len();
+        if *image != keyword_value("none") {
+            parts.push(image.clone());
+        }
+        let initial_position = Value::List(vec![
+            Value::Length(0.0, Unit::Percent),
+            Value::Length(0.0, Unit::Percent),
+        ]);
+        // サイズは位置の後に / をつけて書く
+        if *size != keyword_value("auto") {
+            parts.extend(components(position));
+            parts.push(Value::Delimiter('/'));
+            parts.extend(components(size));
+        } else if *position != initial_position {
+            parts.extend(components(position));
+        }
+        if *repeat != keyword_value("repeat") {
+            parts.extend(components(repeat));
+        }
+        if *attachment != keyword_value("scroll") {
+            parts.push(attachment.clone());
+        }
+        if *origin != keyword_value("padding-box") || *clip != keyword_value("border-box") {
+            parts.push(origin.clone());
+            if clip != origin {
+                parts.push(clip.clone());
+            }
+        }
+        if i == count - 1 && *values[0] != keyword_value("transparent") {
+            parts.push(values[0].clone());
+        }
+        if parts.len() == start {
+            parts.push(keyword_value("none"));
+        }
+    }
+    Some(list_or_single(parts))
+}
+
+// , で区切られたレイヤーごとの値
+fn layers(value: &Value) -> Vec<Value> {
+    match *value {
+        Value::List(ref values) if values.contains(&Value::Delimiter(',')) => values
+            .split(|value| *value == Value::Delimiter(','))
+            .map(|layer| list_or_single(layer.to_vec()))
+            .collect(),
+        ref value => vec![value.clone()],
+    }
+}
+
+// style, variant, weight, stretch, size, line-height, family
+fn font_value(values: &[&Value]) -> Value {
+    let normal = keyword_value("normal");
+    let mut parts: Vec<Value> = values[..4]
+        .iter()
+        .filter(|&&value| *value != normal)
+        .map(|&value| value.clone())
+        .collect();
+    parts.push(values[4].clone());
+    if *values[5] != normal {
+        parts.push(Value::Delimiter('/'));
+        parts.push(values[5].clone());
+    }
+    parts.extend(components(values[6]));
+    Value::List(parts)
+}
+
+// grow, shrink, basis
+fn flex_value(values: &[&Value]) -> Value {
+    let auto = keyword_value("auto");
+    let zero = Value::Length(0.0, Unit::Percent);
+    let (grow, shrink, basis) = (values[0], values[1], values[2]);
+    match (grow, shrink) {
+        (&Value::Number(g), &Value::Number(s)) if g == 0.0 && s == 0.0 && *basis == auto => {
+            keyword_value("none")
+        }
+        (&Value::Number(g), &Value::Number(s)) if g == 1.0 && s == 1.0 && *basis == auto => auto,
+        (_, &Value::Number(s)) if s == 1.0 && *basis == zero => grow.clone(),
+        _ if *basis == zero => Value::List(vec![grow.clone(), shrink.clone()]),
+        (_, &Value::Number(1.0)) => Value::List(vec![grow.clone(), basis.clone()]),
+        _ => Value::List(vec![grow.clone(), shrink.clone(), basis.clone()]),
+    }
+}
+
+// type, position, image
+fn list_style_value(values: &[&Value]) -> Value {
+    let none = keyword_value("none");
+    let (list_type, position, image) = (values[0], values[1], values[2]);
+    let mut parts = Vec::new();
+    if *position != keyword_value("outside") {
+        parts.push(position.clone());
+    }
+    if *list_type == none && *image == none {
+        parts.push(none);
+    } else {
+        if *list_type != keyword_value("disc") {
+            parts.insert(0, list_type.clone());
+        }
+        if *image != none {
+            parts.push(image.clone());
+        }
+    }
+    if parts.is_empty() {
+        parts.push(list_type.clone());
+    }
+    list_or_single(parts)
+}
+
+fn components(value: &Value) -> Vec<Value> {
+    match *value {
+        Value::List(ref values) => values.clone(),
+        ref value => vec![value.clone()],
+    }
+}
+
+fn keyword(value: &Value) -> Option<&str> {
+    match *value {
+        Value::Keyword(ref keyword) => Some(keyword),
+        _ => None,
+    }
+}
+
+fn is_length(value: &Value) -> bool {
+    match *value {
+        Value::Length(..) | Value::Math(_) => true,
+        Value::Number(n) => n == 0.0,
+        _ => false,
+    }
+}
+
+fn keyword_value(keyword: &str) -> Value {
+    Value::Keyword(keyword.to_string())
+}
+
+fn list_or_single(mut values: Vec<Value>) -> Value {
+    if values.len() == 1 {
+        values.swap_remove(0)
+    } else {
+        Value::List(values)
+    }
+}
+
+// 1〜4個の値を top, right, bottom, left に割り当てる
+fn box_sides(values: &[Value]) -> Option<[Value; 4]> {
+    match *values {
+        [ref all] => Some([all.clone(), all.clone(), all.clone(), all.clone()]),
+        [ref vertical, ref horizontal] => Some([
+            vertical.clone(),
+            horizontal.clone(),
+            vertical.clone(),
+            horizontal.clone(),
+        ]),
+        [ref top, ref horizontal, ref bottom] => Some([
+            top.clone(),
+            horizontal.clone(),
+            bottom.clone(),
+            horizontal.clone(),
+        ]),
+        [ref top, ref right, ref bottom, ref left] => {
+            Some([top.clone(), right.clone(), bottom.clone(), left.clone()])
+        }
+        _ => None,
+    }
+}
+
+// `<line-width> || <line-style> || <color>` を順不同で解釈する
+fn border_side(values: &[Value]) -> Option<(Value, Value, Value)> {
+    let mut width = None;
+    let mut style = None;
+    let mut color = None;
+    for value in values {
+        let slot = match keyword(value) {
+            Some("thin") | Some("medium") | Some("thick") => &mut width,
+            Some(k) if BORDER_STYLES.contains(&k) => &mut style,
+            _ if is_length(value) => &mut width,
+            _ => &mut color,
+        };
+        if slot.is_some() {
+            return None;
+        }
+        *slot = Some(value.clone());
+    }
+    Some((
+        width.unwrap_or_else(|| keyword_value("medium")),
+        style.unwrap_or_else(|| keyword_value("none")),
+        color.unwrap_or_else(|| keyword_value("currentcolor")),
+    ))
+}
+
+// カンマ区切りのレイヤーごとに解釈し、background-colorは最後のレイヤーからのみ取る
+fn background(values: &[Value]) -> Option<Vec<(String, Value)>> {
+    let layers: Vec<&[Value]> = values
+        .split(|value| *value == Value::Delimiter(','))
+        .collect();
+    let mut color = None;
+    let mut longhands: Vec<(&str, Vec<Value>)> = vec![
+        ("background-image", Vec::new()),
+        ("background-repeat", Vec::new()),
+        ("background-position", Vec::new()),
+        ("background-size", Vec::new()),
+        ("background-attachment", Vec::new()),
+        ("background-origin", Vec::new()),
+        ("background-clip", Vec::new()),
+    ];
+
+    for (i, layer) in layers.iter().enumerate() {
+        let mut image = None;
+        let mut repeat = Vec::new();
+        let mut position = Vec::new();
+        let mut size = Vec::new();
+        let mut attachment = None;
+        let mut boxes = Vec::new();
+        let mut after_slash = false;
+
+        for value in layer.iter() {
+            match (value, keyword(value)) {
+                (&Value::Delimiter('/'), _) => after_slash = true,
+                (_, Some("repeat-x"))
+                | (_, Some("repeat-y"))
+                | (_, Some("repeat"))
+                | (_, Some("no-repeat"))
+                | (_, Some("space"))
+                | (_, Some("round")) => repeat.push(value.clone()),
+                (_, Some("scroll")) | (_, Some("fixed")) | (_, Some("local")) => {
+                    attachment = Some(value.clone())
+                }
+                (_, Some("border-box")) | (_, Some("padding-box")) | (_, Some("content-box")) => {
+                    boxes.push(value.clone())
+                }
+                (_, Some("none")) => image = Some(value.clone()),
+                (Value::Function(name, _), _) if name == "url" || name.ends_with("gradient") => {
+                    image = Some(value.clone())
+                }
+                (_, Some("cover")) | (_, Some("contain")) => size.push(value.clone()),
+                _ if after_slash && (is_length(value) || keyword(value) == Some("auto")) => {
+                    size.push(value.clone())
+                }
+                (_, Some("left"))
+                | (_, Some("right"))
+                | (_, Some("top"))
+                | (_, Some("bottom"))
+                | (_, Some("center")) => position.push(value.clone()),
+                _ if is_length(value) => position.push(value.clone()),
+                _ if i == layers.len() - 1 && color.is_none() => color = Some(value.clone()),
+                _ => return None,
+            }
+        }
+
+        let origin = boxes
+            .first()
+            .cloned()
+            .unwrap_or_else(|| keyword_value("padding-box"));
+        let clip = boxes.get(1).cloned().unwrap_or_else(|| {
+            boxes
+                .first()
+                .cloned()
+                .unwrap_or_else(|| keyword_value("border-box"))
+        });
+        let layer_values = [
+            image.unwrap_or_else(|| keyword_value("none")),
+            if repeat.is_empty() {
+                keyword_value("repeat")
+            } else {
+                list_or_single(repeat)
+            },
+            if position.is_empty() {
+                Value::List(vec![
+                    Value::Length(0.0, Unit::Percent),
+                    Value::Length(0.0, Unit::Percent),
+                ])
+            } else {
+                list_or_single(position)
+            },
+            if size.is_empty() {
+                keyword_value("auto")
+            } else {
+                list_or_single(size)
+            },
+            attachment.unwrap_or_else(|| keyword_value("scroll")),
+            origin,
+            clip,
+        ];
+        for (longhand, value) in longhands.iter_mut().zip(layer_values.iter()) {
+            if i > 0 {
+                longhand.1.push(Value::Delimiter(','));
+            }
+            longhand.1.push(value.clone());
+        }
+    }
+
+    let mut expanded = vec![(
+        "background-color".to_string(),
+        color.unwrap_or_else(|| keyword_value("transparent")),
+    )];
+    expanded.extend(
+        longhands
+            .into_iter()
+            .map(|(name, values)| (name.to_string(), list_or_single(values))),
+    );
+    Some(expanded)
+}
+
+// `[<font-style> || <font-variant> || <font-weight> || <font-stretch>]? <font-size> [/ <line-height>]? <font-family>`
+fn font(values: &[Value]) -> Option<Vec<(String, Value)>> {
+    let mut style = None;
+    let mut variant = None;
+    let mut weight = None;
+    let mut stretch = None;
+    let mut rest = values;
+
+    // font-size より前のキーワード
+    while let Some((value, tail)) = rest.split_first() {
+        let slot = match (value, keyword(value)) {
+            (_, Some("italic")) | (_, Some("oblique")) => &mut style,
+            (_, Some("small-caps")) => &mut variant,
+            (_, Some("bold")) | (_, Some("bolder")) | (_, Some("lighter")) => &mut weight,
+            (&Value::Number(n), _) if (1.0..=1000.0).contains(&n) => &mut weight,
+            (_, Some(k)) if k.ends_with("condensed") || k.ends_with("expanded") => &mut stretch,
+            // normal は未指定のサブプロパティのいずれかに割り当てられる（どれでも初期値と同じ）
+            (_, Some("normal")) => {
+                rest = tail;
+                continue;
+            }
+            _ => break,
+        };
+        if slot.is_some() {
+            return None;
+        }
+        *slot = Some(value.clone());
+        rest = tail;
+    }
+
+    let (size, mut rest) = rest.split_first()?;
+    let mut line_height = keyword_value("normal");
+    if rest.first() == Some(&Value::Delimiter('/')) {
+        line_height = rest.get(1)?.clone();
+        rest = &rest[2..];
+    }
+    if rest.is_empty() {
+        return None;
+    }
+
+    Some(vec![
+        (
+            "font-style".to_string(),
+            style.unwrap_or_else(|| keyword_value("normal")),
+        ),
+        (
+            "font-variant".to_string(),
+            variant.unwrap_or_else(|| keyword_value("normal")),
+        ),
+        (
+            "font-weight".to_string(),
+            weight.unwrap_or_else(|| keyword_value("normal")),
+        ),
+        (
+            "font-stretch".to_string(),
+            stretch.unwrap_or_else(|| keyword_value("normal")),
+        ),
+        ("font-size".to_string(), size.clone()),
+        ("line-height".to_string(), line_height),
+        ("font-family".to_string(), list_or_single(rest.to_vec())),
+    ])
+}
+
+// https://www.w3.org/TR/css-flexbox-1/#flex-property
+fn flex(values: &[Value]) -> Option<Vec<(String, Value)>> {
+    let (grow, shrink, basis) = match *values {
+        [Value::Keyword(ref k)] if k == "none" => (
+            Value::Number(0.0),
+            Value::Number(0.0),
+            keyword_value("auto"),
+        ),
+        [Value::Keyword(ref k)] if k == "auto" => (
+            Value::Number(1.0),
+            Value::Number(1.0),
+            keyword_value("auto"),
+        ),
+        [Value::Number(grow)] => (
+            Value::Number(grow),
+            Value::Number(1.0),
+            Value::Length(0.0, Unit::Percent),
+        ),
+        [Value::Number(grow), Value::Number(shrink)] => (
+            Value::Number(grow),
+            Value::Number(shrink),
+            Value::Length(0.0, Unit::Percent),
+        ),
+        [Value::Number(grow), Value::Number(shrink), ref basis] => {
+            (Value::Number(grow), Value::Number(shrink), basis.clone())
+        }
+        [Value::Number(grow), ref basis] => {
+            (Value::Number(grow), Value::Number(1.0), basis.clone())
+        }
+        [ref basis] => (Value::Number(1.0), Value::Number(1.0), basis.clone()),
+        _ => return None,
+    };
+    Some(vec![
+        ("flex-grow".to_string(), grow),
+        ("flex-shrink".to_string(), shrink),
+        ("flex-basis".to_string(), basis),
+    ])
+}
+
+// `<list-style-type> || <list-style-position> || <list-style-image>`
+fn list_style(values: &[Value]) -> Option<Vec<(String, Value)>> {
+    let mut list_type = None;
+    let mut position = None;
+    let mut image = None;
+    let mut nones = 0;
+    for value in values {
+        let slot = match (value, keyword(value)) {
+            (_, Some("none")) => {
+                nones += 1;
+                continue;
+            }
+            (_, Some("inside")) | (_, Some("outside")) => &mut position,
+            (&Value::Function(..), _) => &mut image,
+            _ => &mut list_type,
+        };
+        if slot.is_some() {
+            return None;
+        }
+        *slot = Some(value.clone());
+    }
+    // none は list-style-type と list-style-image のうち未指定のものに割り当てる
+    let none = keyword_value("none");
+    let (list_type, image) = match (list_type, image, nones) {
+        (list_type, image, 0) => (
+            list_type.unwrap_or_else(|| keyword_value("disc")),
+            image.unwrap_or_else(|| none.clone()),
+        ),
+        (None, None, 1) | (None, None, 2) => (none.clone(), none.clone()),
+        (Some(list_type), None, 1) => (list_type, none.clone()),
+        (None, Some(image), 1) => (none.clone(), image),
+        _ => return None,
+    };
+    Some(vec![
+        ("list-style-type".to_string(), list_type),
+        (
+            "list-style-position".to_string(),
+            position.unwrap_or_else(|| keyword_value("outside")),
+        ),
+        ("list-style-image".to_string(), image),
+    ])
+}
+
+#[test]
+fn test_expand_shorthand() {
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+
+    let stylesheet = new_css_parser(
+        "a { margin: 10px 20px; border: 1px solid #000000; font: bold 12px/1.5 serif, x }"
+            .to_string(),
+    )
+    .parse();
+    let declarations: Vec<(String, Value)> = stylesheet.rules[0]
+        .as_style()
+        .unwrap()
+        .declarations
+        .iter()
+        .map(|d| (d.name.clone(), d.value.clone()))
+        .collect();
+    let get = |name: &str| {
+        declarations
+            .iter()
+            .rev()
+            .find(|d| d.0 == name)
+            .map(|d| d.1.clone())
+    };
+
+    assert_eq!(get("margin-top"), Some(Value::Length(10.0, Unit::Px)));
+    assert_eq!(get("margin-left"), Some(Value::Length(20.0, Unit::Px)));
+    assert_eq!(
+        get("border-bottom-width"),
+        Some(Value::Length(1.0, Unit::Px))
+    );
+    assert_eq!(get("border-left-style"), Some(keyword_value("solid")));
+    assert_eq!(get("font-weight"), Some(keyword_value("bold")));
+    assert_eq!(get("font-size"), Some(Value::Length(12.0, Unit::Px)));
+    assert_eq!(get("line-height"), Some(Value::Number(1.5)));
+    assert_eq!(
+        get("font-family"),
+        Some(Value::List(vec![
+            keyword_value("serif"),
+            Value::Delimiter(','),
+            keyword_value("x")
+        ]))
+    );
+    assert_eq!(get("margin"), None);
+}
@@ -1,6 +1,17 @@
-#[derive(Debug, Clone, PartialEq)]
+use style_sheet::pseudo_class::PseudoClass;
+use style_sheet::pseudo_element::PseudoElement;
+use style_sheet::source_location::SourceLocation;
+
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub class: Vec<String>,
+    pub pseudo_classes: Vec<PseudoClass>,
+    pub pseudo_element: Option<PseudoElement>,
+    /// Contains the nesting selector `&`. Nested rules replace it with
+    /// `:is(<parent selectors>)` when they are flattened.
+    pub nesting: bool,
+    /// Where the compound selector starts in the source.
+    pub location: SourceLocation,
 }
@@ -0,0 +1,90 @@
+// ルール、セレクター、宣言のソース上の位置
+// パーサーはバイトオフセットだけを記録し、パースの最後に行と列に直す
+
+use std::fmt;
+use std::sync::Arc;
+use style_sheet::rule::CSSRule;
+
+/// Where a selector or declaration was written. Lines and columns start at 1;
+/// columns count characters.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceLocation {
+    /// The file the stylesheet was loaded from; `None` for sheets parsed from a string.
+    pub file: Option<Arc<str>>,
+    pub line: usize,
+    pub column: usize,
+    /// Byte offset in the source.
+    pub offset: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref file) = self.file {
+            write!(f, "{}:", file)?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Calls `f` on the location of every selector and declaration in `rules`,
+/// including rules in conditional and layer blocks but not imported sheets.
+pub fn for_each_location(rules: &mut [CSSRule], f: &mut dyn FnMut(&mut SourceLocation)) {
+    for rule in rules {
+        match *rule {
+            CSSRule::Style(ref mut rule) => {
+                for selector in rule.selectors.iter_mut() {
+                    for location in selector.locations_mut() {
+                        f(location);
+                    }
+                }
+                for declaration in rule.declarations.iter_mut() {
+                    f(&mut declaration.location);
+                }
+            }
+            CSSRule::Media(ref mut media_rule) => for_each_location(&mut media_rule.rules, f),
+            CSSRule::Supports(ref mut supports_rule) => {
+                for_each_location(&mut supports_rule.rules, f)
+            }
+            CSSRule::Layer(ref mut layer_rule) => for_each_location(&mut layer_rule.rules, f),
+            CSSRule::Import(_) | CSSRule::LayerStatement(_) => {}
+        }
+    }
+}
+
+/// Fills in the line and column of locations from their byte offsets in `source`.
+pub fn resolve_lines(rules: &mut [CSSRule], source: &str) {
+    // 各行の先頭のオフセット
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+        .collect();
+    for_each_location(rules, &mut |location| {
+        let line = match line_starts.binary_search(&location.offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        location.line = line + 1;
+        location.column = source[line_starts[line]..location.offset].chars().count() + 1;
+    });
+}
+
+/// The line and column of a byte offset in `source`, counted as in [`SourceLocation`].
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Resets every location in `rules`, so sheets parsed from different sources
+/// can be compared by content.
+pub fn clear_locations(rules: &mut [CSSRule]) {
+    for_each_location(rules, &mut |location| *location = SourceLocation::default());
+}
+
+/// Records `file` as the file of every location in `rules`.
+pub fn set_file(rules: &mut [CSSRule], file: &str) {
+    let file: Arc<str> = Arc::from(file);
+    for_each_location(rules, &mut |location| location.file = Some(file.clone()));
+}
@@ -1,15 +1,111 @@
 use style_sheet::css_parser::*;
-use style_sheet::rule::Rule;
+use style_sheet::layer::{layer_key, LayerKey, LayerTree};
+use style_sheet::media_query::MediaEnvironment;
+use style_sheet::rule::{CSSRule, Rule};
+use style_sheet::supports::SupportsCondition;
 
 use super::interface::CSSParserTrait;
 
+/// Where a stylesheet comes from; decides its precedence in the cascade.
+/// https://www.w3.org/TR/css-cascade-4/#cascading-origins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Origin {
+    UserAgent,
+    User,
+    Author,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Stylesheet {
-    pub rules: Vec<Rule>,
+    pub rules: Vec<CSSRule>,
+    pub origin: Origin,
+}
+
+impl Stylesheet {
+    /// The style rules that apply in `media`, in order of appearance, with
+    /// conditional group rules such as `@media` flattened.
+    pub fn style_rules(&self, media: &MediaEnvironment) -> Vec<&Rule> {
+        self.cascade_rules(media, &mut LayerTree::default())
+            .into_iter()
+            .map(|(rule, _)| rule)
+            .collect()
+    }
+
+    /// Like `style_rules`, paired with the cascade layer of each rule. Layers are
+    /// declared in `layers`, which is shared by the sheets of the same origin.
+    pub fn cascade_rules(
+        &self,
+        media: &MediaEnvironment,
+        layers: &mut LayerTree,
+    ) -> Vec<(&Rule, LayerKey)> {
+        let mut style_rules = Vec::new();
+        let mut collector = RuleCollector {
+            media,
+            layers,
+            style_rules: &mut style_rules,
+        };
+        collector.collect(&self.rules, &[]);
+        style_rules
+    }
+}
+
+struct RuleCollector<'a, 'r> {
+    media: &'r MediaEnvironment,
+    layers: &'r mut LayerTree,
+    style_rules: &'r mut Vec<(&'a Rule, LayerKey)>,
+}
+
+impl<'a, 'r> RuleCollector<'a, 'r> {
+    // layer: ルールが属するレイヤーの位置（レイヤー外なら空）
+    fn collect(&mut self, rules: &'a [CSSRule], layer: &[usize]) {
+        for rule in rules {
+            match *rule {
+                CSSRule::Style(ref rule) => self.style_rules.push((rule, layer_key(layer))),
+                CSSRule::Media(ref media_rule) => {
+                    if media_rule.queries.matches(self.media) {
+                        self.collect(&media_rule.rules, layer);
+                    }
+                }
+                CSSRule::Supports(ref supports_rule) => {
+                    if supports_rule.condition.matches() {
+                        self.collect(&supports_rule.rules, layer);
+                    }
+                }
+                CSSRule::Layer(ref layer_rule) => {
+                    let sublayer = self.layers.declare(layer, &layer_rule.name);
+                    self.collect(&layer_rule.rules, &sublayer);
+                }
+                CSSRule::LayerStatement(ref names) => {
+                    for name in names {
+                        self.layers.declare(layer, name);
+                    }
+                }
+                // 読み込まれたシートのルールは @import の位置に展開する
+                CSSRule::Import(ref import_rule) => {
+                    if let Some(ref stylesheet) = import_rule.stylesheet {
+                        let supported = import_rule
+                            .supports
+                            .as_ref()
+                            .is_none_or(SupportsCondition::matches);
+                        if supported && import_rule.queries.matches(self.media) {
+                            match import_rule.layer {
+                                Some(ref name) => {
+                                    let sublayer = self.layers.declare(layer, name);
+                                    self.collect(&stylesheet.rules, &sublayer);
+                                }
+                                None => self.collect(&stylesheet.rules, layer),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
 
 pub fn new_style_sheet(source: String) -> Stylesheet {
     Stylesheet {
         rules: new_css_parser(source).parse_rules(),
+        origin: Origin::Author,
     }
 }
@@ -0,0 +1,187 @@
+// 機能クエリ @supports
+// 宣言やセレクターがこのエンジンで解釈できるかどうかをプロパティの定義から判定する
+// https://www.w3.org/TR/css-conditional-3/#at-supports
+
+use computed_style::{BorderStyle, Display, Float, Position};
+use parser::interface::DefaultParserTrait;
+use style_sheet::color;
+use style_sheet::css_parser::new_css_parser;
+use style_sheet::declaration::Declaration;
+use style_sheet::interface::CSSParserTrait;
+use style_sheet::property::{self, is_css_wide_keyword};
+use style_sheet::shorthand::expand_shorthand;
+use style_sheet::util::Value;
+use style_sheet::variable::is_custom_property;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SupportsCondition {
+    /// `(<property>: <value>)`
+    Declaration(Declaration),
+    /// `selector(<complex-selector>)`
+    Selector(String),
+    Not(Box<SupportsCondition>),
+    And(Vec<SupportsCondition>),
+    Or(Vec<SupportsCondition>),
+    /// Anything else in parentheses, which never matches.
+    Unknown(String),
+}
+
+impl SupportsCondition {
+    pub fn matches(&self) -> bool {
+        match *self {
+            SupportsCondition::Declaration(ref declaration) => supports_declaration(declaration),
+            SupportsCondition::Selector(ref selector) => supports_selector(selector),
+            SupportsCondition::Not(ref condition) => !condition.matches(),
+            SupportsCondition::And(ref conditions) => {
+                conditions.iter().all(|condition| condition.matches())
+            }
+            SupportsCondition::Or(ref conditions) => {
+                conditions.iter().any(|condition| condition.matches())
+            }
+            SupportsCondition::Unknown(_) => false,
+        }
+    }
+}
+
+/*
+ プロパティが登録されていて、値がそのプロパティとして解釈できれば対応している
+ ショートハンドは展開できたうえで、すべてのロングハンドが対応していればよい
+*/
+pub fn supports_declaration(declaration: &Declaration) -> bool {
+    if is_custom_property(&declaration.name) {
+        return true;
+    }
+    let longhands = expand_shorthand(declaration.clone());
+    // 展開できなかったショートハンドはそのまま返ってくる
+    longhands.iter().all(|longhand| {
+        property::lookup(&longhand.name).is_some() && accepts(&longhand.name, &longhand.value)
+    })
+}
+
+/// Whether `selector` is a complex selector that the selector parser accepts,
+/// so every pseudo-class and pseudo-element in it is implemented.
+/// Attribute and namespace selectors are not.
+pub fn supports_selector(selector: &str) -> bool {
+    let mut parser = new_css_parser(selector.trim().to_string());
+    parser.parse_complex_selector().is_ok() && parser.eof()
+}
+
+// 型付きで保持するプロパティは値を検査し、それ以外は登録されていれば受け付ける
+fn accepts(name: &str, value: &Value) -> bool {
+    if is_css_wide_keyword(value) {
+        return true;
+    }
+    if let Value::Unparsed { .. } = *value {
+        // var()を含む値は置換するまで検査できない
+        return true;
+    }
+    match name {
+        "display" => Display::from_value(value).is_some(),
+        "position" => Position::from_value(value).is_some(),
+        "float" => Float::from_value(value).is_some(),
+        "width" | "height" => is_length(value) || is_keyword(value, "auto"),
+        "color" | "background-color" => is_color(value),
+        _ if name.starts_with("margin-") => is_length(value) || is_keyword(value, "auto"),
+        _ if name.starts_with("padding-") => is_length(value),
+        _ if name.starts_with("border-") && name.ends_with("-style") => {
+            BorderStyle::from_value(value).is_some()
+        }
+        _ if name.starts_with("border-") && name.ends_with("-width") => {
+            is_length(value)
+                || ["thin", "medium", "thick"]
+                    .iter()
+                    .any(|keyword| is_keyword(value, keyword))
+        }
+        _ if name.starts_with("border-") && name.ends_with("-color") => is_color(value),
+        _ => true,
+    }
+}
+
+fn is_length(value: &Value) -> bool {
+    matches!(
+        *value,
+        Value::Length(..) | Value::Number(0.0) | Value::Math(_)
+    )
+}
+
+fn is_color(value: &Value) -> bool {
+    match *value {
+        Value::ColorValue(_) => true,
+        Value::Keyword(ref keyword) => {
+            keyword.eq_ignore_ascii_case("currentcolor") || color::from_keyword(keyword).is_some()
+        }
+        _ => false,
+    }
+}
+
+fn is_keyword(value: &Value, expected: &str) -> bool {
+    match *value {
+        Value::Keyword(ref keyword) => keyword.eq_ignore_ascii_case(expected),
+        _ => false,
+    }
+}
+
+#[test]
+fn test_supports() {
+    use style_sheet::css_parser::new_css_parser;
+    use style_sheet::interface::CSSParserTrait;
+    use style_sheet::media_query::MediaEnvironment;
+
+    let css = "@supports (display: block) { a { width: 1px } } \
+               @supports (display: ruby) { a { width: 2px } } \
+               @supports not (display: ruby) { a { width: 3px } } \
+               @supports (margin: 0 auto) and (--theme: dark) { a { width: 4px } } \
+               @supports (border: 1px wavy red) or (gap: 1em) { a { width: 5px } } \
+               @supports selector(li:has(> a:hover)) { a { width: 6px } } \
+               @supports selector(a::marker) or selector([href]) { a { width: 7px } } \
+               @supports (color: var(--accent)) and ((float: left) or (frobnicate)) { \
+                   a { width: 8px } } \
+               @supports not ((width: 10px) and (widht: 10px)) { a { width: 9px } }";
+    let stylesheet = new_css_parser(css.to_string()).parse();
+    let widths: Vec<String> = stylesheet
+        .style_rules(&MediaEnvironment::default())
+        .iter()
+        .map(|rule| format!("{:?}", rule.declarations[0].value))
+        .collect();
+    assert_eq!(
+        widths,
+        vec![
+            "Length(1.0, Px)",
+            "Length(3.0, Px)",
+            "Length(4.0, Px)",
+            "Length(6.0, Px)",
+            "Length(8.0, Px)",
+            "Length(9.0, Px)"
+        ]
+    );
+}
+
+#[test]
+fn test_invalid_supports() {
+    use style_sheet::media_query::MediaEnvironment;
+
+    let css = "@supports selector(a > > b) { a { width: 1px } } \
+               @supports selector(a:frob) or selector([href]) or selector(a, b) { a { width: 2px } } \
+               @supports foo { a { width: 3px } } \
+               @supports (display: block) and (color: red) or (float: left) { a { width: 4px } } \
+               a { @supports (display: block) junk { width: 5px } } \
+               @supports not selector(a > > b) { a { width: 6px } }";
+    let (stylesheet, errors) = new_css_parser(css.to_string()).parse_with_errors();
+    let widths: Vec<String> = stylesheet
+        .style_rules(&MediaEnvironment::default())
+        .iter()
+        .filter(|rule| !rule.declarations.is_empty())
+        .map(|rule| format!("{:?}", rule.declarations[0].value))
+        .collect();
+    // 構文が正しくない条件のルールは捨てられる
+    assert_eq!(widths, vec!["Length(6.0, Px)"]);
+    let messages: Vec<&str> = errors.iter().map(|error| &*error.message).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "unexpected f in @supports",
+            "and and or mixed without parentheses",
+            "unexpected j in @supports",
+        ]
+    );
+}
@@ -0,0 +1,21 @@
+// ブラウザ組み込みのユーザーエージェントスタイルシート
+// https://html.spec.whatwg.org/multipage/rendering.html
+
+use std::sync::OnceLock;
+use style_sheet::css_parser::new_css_parser;
+use style_sheet::interface::CSSParserTrait;
+use style_sheet::source_location::set_file;
+use style_sheet::style_sheet::{Origin, Stylesheet};
+
+const USER_AGENT_CSS: &str = include_str!("user_agent.css");
+
+/// The default stylesheet every document is styled with, at the lowest cascade origin.
+pub fn user_agent_stylesheet() -> &'static Stylesheet {
+    static STYLESHEET: OnceLock<Stylesheet> = OnceLock::new();
+    STYLESHEET.get_or_init(|| {
+        let mut stylesheet = new_css_parser(USER_AGENT_CSS.to_string()).parse();
+        stylesheet.origin = Origin::UserAgent;
+        set_file(&mut stylesheet.rules, "user_agent.css");
+        stylesheet
+    })
+}
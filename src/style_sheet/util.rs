@@ -1,26 +1,168 @@
 use style_sheet::interface::ValueTrait;
+use style_sheet::math::MathExpression;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Keyword(String),
     Length(f32, Unit),
+    Number(f32),
+    /// An angle such as `90deg` or `0.25turn`, in degrees
+    Angle(f32),
     ColorValue(Color),
+    Str(String),
+    /// `name(arg, ...)`, e.g. `attr(title)` or `counter(item, upper-roman)`
+    Function(String, Vec<Value>),
+    /// `,` or `/` between component values, e.g. in `font: 12px/1.5 a, b`
+    Delimiter(char),
+    /// Whitespace-separated component values, e.g. `"(" attr(href) ")"`
+    List(Vec<Value>),
+    /// A `calc()`, `min()`, `max()` or `clamp()` expression that could not be
+    /// simplified to a single number or length
+    Math(MathExpression),
+    /// Declaration text kept verbatim: the value of a custom property, or a value
+    /// containing `var()` that is parsed after substitution. `shorthand` is the
+    /// shorthand a pending-substitution longhand was expanded from.
+    Unparsed {
+        css: String,
+        shorthand: Option<String>,
+    },
 }
 
 impl ValueTrait for Value {
     fn to_px(&self) -> f32 {
+        self.resolve_px(&LengthContext::default())
+    }
+
+    fn resolve_px(&self, context: &LengthContext) -> f32 {
         match *self {
-            Value::Length(f, Unit::Px) => f,
+            Value::Length(f, unit) => f * unit.px_per_unit(context),
+            Value::Math(ref expression) => expression.resolve_px(context),
             _ => 0.0,
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// https://www.w3.org/TR/css-values-4/#lengths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Unit {
     Px,
+    Em,
+    Rem,
+    Ex,
+    Ch,
+    Percent,
+    Vw,
+    Vh,
+    Vmin,
+    Vmax,
+    Cm,
+    Mm,
+    Q,
+    In,
+    Pt,
+    Pc,
 }
 
+impl Unit {
+    pub fn from_name(name: &str) -> Option<Unit> {
+        match &*name.to_ascii_lowercase() {
+            "px" => Some(Unit::Px),
+            "em" => Some(Unit::Em),
+            "rem" => Some(Unit::Rem),
+            "ex" => Some(Unit::Ex),
+            "ch" => Some(Unit::Ch),
+            "%" => Some(Unit::Percent),
+            "vw" => Some(Unit::Vw),
+            "vh" => Some(Unit::Vh),
+            "vmin" => Some(Unit::Vmin),
+            "vmax" => Some(Unit::Vmax),
+            "cm" => Some(Unit::Cm),
+            "mm" => Some(Unit::Mm),
+            "q" => Some(Unit::Q),
+            "in" => Some(Unit::In),
+            "pt" => Some(Unit::Pt),
+            "pc" => Some(Unit::Pc),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Unit::Px => "px",
+            Unit::Em => "em",
+            Unit::Rem => "rem",
+            Unit::Ex => "ex",
+            Unit::Ch => "ch",
+            Unit::Percent => "%",
+            Unit::Vw => "vw",
+            Unit::Vh => "vh",
+            Unit::Vmin => "vmin",
+            Unit::Vmax => "vmax",
+            Unit::Cm => "cm",
+            Unit::Mm => "mm",
+            Unit::Q => "q",
+            Unit::In => "in",
+            Unit::Pt => "pt",
+            Unit::Pc => "pc",
+        }
+    }
+
+    /// Units whose px value does not depend on any context.
+    pub fn is_absolute(self) -> bool {
+        matches!(
+            self,
+            Unit::Px | Unit::Cm | Unit::Mm | Unit::Q | Unit::In | Unit::Pt | Unit::Pc
+        )
+    }
+
+    pub fn px_per_unit(self, context: &LengthContext) -> f32 {
+        match self {
+            Unit::Px => 1.0,
+            Unit::Em => context.font_size,
+            Unit::Rem => context.root_font_size,
+            // フォントの寸法情報がないため、仕様で認められている0.5emで近似する
+            Unit::Ex | Unit::Ch => context.font_size / 2.0,
+            Unit::Percent => context.percentage_base / 100.0,
+            Unit::Vw => context.viewport_width / 100.0,
+            Unit::Vh => context.viewport_height / 100.0,
+            Unit::Vmin => context.viewport_width.min(context.viewport_height) / 100.0,
+            Unit::Vmax => context.viewport_width.max(context.viewport_height) / 100.0,
+            Unit::In => 96.0,
+            Unit::Cm => 96.0 / 2.54,
+            Unit::Mm => 96.0 / 25.4,
+            Unit::Q => 96.0 / 101.6,
+            Unit::Pt => 96.0 / 72.0,
+            Unit::Pc => 16.0,
+        }
+    }
+}
+
+/// Everything a relative length may refer to when it is converted to px.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthContext {
+    pub font_size: f32,
+    pub root_font_size: f32,
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+    /// What `100%` resolves to, e.g. the containing block width for margins.
+    pub percentage_base: f32,
+}
+
+impl Default for LengthContext {
+    fn default() -> LengthContext {
+        LengthContext {
+            font_size: DEFAULT_FONT_SIZE,
+            root_font_size: DEFAULT_FONT_SIZE,
+            viewport_width: 0.0,
+            viewport_height: 0.0,
+            percentage_base: 0.0,
+        }
+    }
+}
+
+/// The initial `font-size` (`medium`).
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
+
 #[derive(Debug, Clone, PartialEq, Copy)]
 pub struct Color {
     pub r: u8,
@@ -28,6 +170,3 @@ pub struct Color {
     pub b: u8,
     pub a: u8,
 }
-
-// css.rsの削除
-// html_parserの移動
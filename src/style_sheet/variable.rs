@@ -0,0 +1,157 @@
+// カスタムプロパティとvar()の置換
+// https://www.w3.org/TR/css-variables-1/
+
+use std::collections::{HashMap, HashSet};
+
+pub fn is_custom_property(name: &str) -> bool {
+    name.starts_with("--")
+}
+
+pub fn contains_var(css: &str) -> bool {
+    find_var(css).is_some()
+}
+
+/// Replaces every `var(--name[, fallback])` in `css`. `lookup` returns the value of
+/// a custom property, or `None` if it is guaranteed-invalid.
+/// Returns `None` when a reference has no value and no fallback.
+pub fn substitute<F>(css: &str, lookup: &mut F) -> Option<String>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut result = String::new();
+    let mut rest = css;
+    while let Some(start) = find_var(rest) {
+        result.push_str(&rest[..start]);
+        let arguments_start = start + "var(".len();
+        let end = arguments_start + matching_paren(&rest[arguments_start..])?;
+        let arguments = &rest[arguments_start..end];
+        let (name, fallback) = match top_level_comma(arguments) {
+            Some(comma) => (&arguments[..comma], Some(&arguments[comma + 1..])),
+            None => (arguments, None),
+        };
+        let value = match lookup(name.trim()) {
+            Some(value) => value,
+            None => substitute(fallback?.trim(), lookup)?,
+        };
+        result.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Some(result)
+}
+
+/*
+ カスタムプロパティ同士の参照を解決する
+ 循環参照に含まれるプロパティはフォールバックの有無に関わらず無効になる
+*/
+pub fn resolve_custom_properties(raw: &HashMap<String, String>) -> HashMap<String, String> {
+    let mut resolver = Resolver {
+        raw,
+        resolved: HashMap::new(),
+        stack: Vec::new(),
+        cyclic: HashSet::new(),
+    };
+    raw.keys()
+        .filter_map(|name| resolver.resolve(name).map(|value| (name.clone(), value)))
+        .collect()
+}
+
+struct Resolver<'a> {
+    raw: &'a HashMap<String, String>,
+    resolved: HashMap<String, Option<String>>,
+    // 解決中のプロパティ
+    stack: Vec<String>,
+    cyclic: HashSet<String>,
+}
+
+impl<'a> Resolver<'a> {
+    fn resolve(&mut self, name: &str) -> Option<String> {
+        if let Some(value) = self.resolved.get(name) {
+            return value.clone();
+        }
+        if let Some(index) = self.stack.iter().position(|resolving| resolving == name) {
+            self.cyclic.extend(self.stack[index..].iter().cloned());
+            return None;
+        }
+        let css = self.raw.get(name)?;
+
+        self.stack.push(name.to_string());
+        let value = substitute(css, &mut |reference| self.resolve(reference));
+        self.stack.pop();
+
+        let value = if self.cyclic.contains(name) {
+            None
+        } else {
+            value
+        };
+        self.resolved.insert(name.to_string(), value.clone());
+        value
+    }
+}
+
+// 識別子の途中ではない `var(` の位置
+fn find_var(css: &str) -> Option<usize> {
+    let lowercase = css.to_ascii_lowercase();
+    let mut offset = 0;
+    while let Some(index) = lowercase[offset..].find("var(") {
+        let start = offset + index;
+        let preceded_by_identifier = lowercase[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        if !preceded_by_identifier {
+            return Some(start);
+        }
+        offset = start + "var(".len();
+    }
+    None
+}
+
+// `(` の直後から対応する `)` までのバイト数
+fn matching_paren(css: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (index, c) in css.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(index),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn top_level_comma(css: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (index, c) in css.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[test]
+fn test_resolve_custom_properties() {
+    let raw: HashMap<String, String> = [
+        ("--gap", "4px"),
+        ("--double", "var(--gap) var(--gap)"),
+        ("--fallback", "var(--missing, var(--gap, 1px))"),
+        ("--a", "var(--b)"),
+        ("--b", "var(--a, 1px)"),
+        ("--uses-cycle", "var(--a, 2px)"),
+    ]
+    .iter()
+    .map(|&(name, css)| (name.to_string(), css.to_string()))
+    .collect();
+    let resolved = resolve_custom_properties(&raw);
+
+    assert_eq!(resolved.get("--double"), Some(&"4px 4px".to_string()));
+    assert_eq!(resolved.get("--fallback"), Some(&"4px".to_string()));
+    assert_eq!(resolved.get("--a"), None);
+    assert_eq!(resolved.get("--b"), None);
+    assert_eq!(resolved.get("--uses-cycle"), Some(&"2px".to_string()));
+}